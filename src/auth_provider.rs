@@ -0,0 +1,61 @@
+use ::futures_util::future::ready;
+use ::futures_util::future::BoxFuture;
+use ::hyper::http::request::Parts as RequestParts;
+use ::hyper::http::response::Parts as ResponseParts;
+use ::std::fmt::Debug;
+
+use crate::Error;
+
+mod digest_auth;
+pub(crate) use self::digest_auth::*;
+
+mod oauth2_provider;
+pub use self::oauth2_provider::*;
+
+mod sigv4_provider;
+pub use self::sigv4_provider::*;
+
+/// The future returned from the methods of an `AuthProvider`.
+pub type AuthFuture<'a, T> = BoxFuture<'a, Result<T, Error>>;
+
+/// Provides the credentials for requests, which may need to be fetched,
+/// renewed, or worked out from the request itself.
+///
+/// This is for credentials which cannot be set once using `Request::bearer_auth`,
+/// such as OAuth2 tokens which expire, or signatures built from each request.
+///
+/// A provider is set on a `Server`, using `ServerConfigBuilder::auth_provider` or `Server::auth_provider`.
+/// It is called for every request sent to the server, including each retry.
+/// Redirects are only authorized when they are to the same origin as the original request.
+///
+/// The provider runs after every `Middleware`, just before the request is sent,
+/// so it sees any headers or query parameters they change.
+/// It replaces any `Authorization` header set using `bearer_auth` or `basic_auth`,
+/// on the `Request`, the `Server`, or the `ServerConfigBuilder`.
+pub trait AuthProvider: Debug + Send + Sync {
+    /// Adds the credentials to the request, just before it is sent.
+    ///
+    /// The body is given when it is held in memory, as it is after any `Middleware`,
+    /// which includes requests without a body (as an empty slice).
+    /// It is `None` for bodies which are streamed, such as from `Request::file`.
+    fn authorize<'a>(
+        &'a self,
+        request: &'a mut RequestParts,
+        body: Option<&'a [u8]>,
+    ) -> AuthFuture<'a, ()>;
+
+    /// Called when the server responds with `401 Unauthorized`,
+    /// to the request given, as it was authorized and sent.
+    ///
+    /// Returning true sends the request again, once, calling `AuthProvider::authorize` first.
+    /// This is skipped for bodies which can only be sent once, such as from `Request::reader`.
+    ///
+    /// **Defaults** to never retrying.
+    fn retry_unauthorized<'a>(
+        &'a self,
+        _request: &'a RequestParts,
+        _response: &'a ResponseParts,
+    ) -> AuthFuture<'a, bool> {
+        Box::pin(ready(Ok(false)))
+    }
+}
@@ -0,0 +1,562 @@
+use ::anyhow::anyhow;
+use ::futures_util::future::ready;
+use ::getrandom::getrandom;
+use ::hyper::http::header::AUTHORIZATION;
+use ::hyper::http::header::WWW_AUTHENTICATE;
+use ::hyper::http::request::Parts as RequestParts;
+use ::hyper::http::response::Parts as ResponseParts;
+use ::hyper::http::HeaderValue;
+use ::hyper::http::Method;
+use ::hyper::Uri;
+use ::md5::Md5;
+use ::sha2::Digest;
+use ::sha2::Sha256;
+use ::sha2::Sha512_256;
+use ::std::cmp::Reverse;
+use ::std::collections::HashMap;
+use ::std::fmt::Debug;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+use ::std::sync::Arc;
+use ::std::sync::Mutex;
+
+use crate::AuthFuture;
+use crate::AuthProvider;
+use crate::Error;
+
+/// The Digest challenges sent by servers, stored by origin.
+///
+/// This is held on the `Server`, so later requests reuse the nonce,
+/// rather than each needing a round trip to be challenged.
+#[derive(Debug, Default)]
+pub(crate) struct DigestNonces {
+    challenges: Mutex<HashMap<String, DigestChallenge>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: DigestAlgorithm,
+    is_session: bool,
+    is_qop_auth: bool,
+    is_userhash: bool,
+    nonce_count: u32,
+}
+
+/// The hash algorithms supported, from weakest to strongest.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum DigestAlgorithm {
+    Md5,
+    Sha256,
+    Sha512_256,
+}
+
+/// Sends credentials using HTTP Digest authentication, from RFC 7616.
+///
+/// The first request to an origin is sent without credentials.
+/// The challenge in the `401 Unauthorized` response is then stored,
+/// and the request is sent again with the response to it.
+/// Later requests reuse the stored nonce, counting up each use, until the server rejects it.
+#[derive(Clone)]
+pub(crate) struct DigestAuth {
+    username: String,
+    password: String,
+    nonces: Arc<DigestNonces>,
+}
+
+impl DigestAuth {
+    pub(crate) fn new(username: String, password: String, nonces: Arc<DigestNonces>) -> Self {
+        Self {
+            username,
+            password,
+            nonces,
+        }
+    }
+
+    /// Builds the `Authorization` header value, using the next nonce count of the challenge.
+    ///
+    /// `None` is returned when there is no challenge for the origin yet.
+    fn next_authorization(
+        &self,
+        request_path: &Uri,
+        method: &Method,
+    ) -> Result<Option<String>, Error> {
+        let Ok(mut challenges) = self.nonces.challenges.lock() else {
+            return Ok(None);
+        };
+        let Some(challenge) = challenges.get_mut(&origin(request_path)) else {
+            return Ok(None);
+        };
+        challenge.nonce_count = challenge.nonce_count.wrapping_add(1);
+
+        // The client nonce must be unpredictable, so it is taken from the operating system.
+        let mut cnonce_bytes = [0; 16];
+        getrandom(&mut cnonce_bytes).map_err(|err| Error::Auth {
+            request_path: request_path.clone(),
+            source: anyhow!("Failed to generate the Digest client nonce, {}", err).into(),
+        })?;
+        let cnonce = ::hex::encode(cnonce_bytes);
+        let uri = request_path
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+
+        Ok(Some(self.authorization(challenge, method, uri, &cnonce)))
+    }
+
+    /// Builds the `Authorization` header value, for the challenge and request given.
+    fn authorization(
+        &self,
+        challenge: &DigestChallenge,
+        method: &Method,
+        uri: &str,
+        cnonce: &str,
+    ) -> String {
+        let algorithm = challenge.algorithm;
+        let nonce_count = format!("{:08x}", challenge.nonce_count);
+
+        let mut ha1 = algorithm.hash(&format!(
+            "{}:{}:{}",
+            self.username, challenge.realm, self.password
+        ));
+        if challenge.is_session {
+            ha1 = algorithm.hash(&format!("{}:{}:{}", ha1, challenge.nonce, cnonce));
+        }
+        let ha2 = algorithm.hash(&format!("{}:{}", method, uri));
+
+        let response = if challenge.is_qop_auth {
+            algorithm.hash(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, challenge.nonce, nonce_count, cnonce, ha2
+            ))
+        } else {
+            algorithm.hash(&format!("{}:{}:{}", ha1, challenge.nonce, ha2))
+        };
+
+        let username = if challenge.is_userhash {
+            algorithm.hash(&format!("{}:{}", self.username, challenge.realm))
+        } else {
+            self.username.clone()
+        };
+
+        let algorithm_name = match challenge.is_session {
+            true => format!("{}-sess", algorithm.as_str()),
+            false => algorithm.as_str().to_string(),
+        };
+
+        let mut params = vec![
+            format!("username={}", quote(&username)),
+            format!("realm={}", quote(&challenge.realm)),
+            format!("uri={}", quote(uri)),
+            format!("algorithm={}", algorithm_name),
+            format!("nonce={}", quote(&challenge.nonce)),
+        ];
+        if challenge.is_qop_auth {
+            params.push(format!("nc={}", nonce_count));
+            params.push(format!("cnonce={}", quote(cnonce)));
+            params.push("qop=auth".to_string());
+        }
+        params.push(format!("response={}", quote(&response)));
+        if let Some(opaque) = challenge.opaque.as_deref() {
+            params.push(format!("opaque={}", quote(opaque)));
+        }
+        if challenge.is_userhash {
+            params.push("userhash=true".to_string());
+        }
+
+        format!("Digest {}", params.join(", "))
+    }
+}
+
+impl AuthProvider for DigestAuth {
+    fn authorize<'a>(
+        &'a self,
+        request: &'a mut RequestParts,
+        _body: Option<&'a [u8]>,
+    ) -> AuthFuture<'a, ()> {
+        let result = match self.next_authorization(&request.uri, &request.method) {
+            Err(err) => Err(err),
+            // Without a challenge, the request is sent without credentials to get one.
+            Ok(None) => Ok(()),
+            Ok(Some(header_text)) => HeaderValue::from_str(&header_text)
+                .map(|mut header_value| {
+                    header_value.set_sensitive(true);
+                    request.headers.insert(AUTHORIZATION, header_value);
+                })
+                .map_err(|err| Error::InvalidHeader {
+                    name: AUTHORIZATION.to_string(),
+                    source: Box::new(err),
+                }),
+        };
+
+        Box::pin(ready(result))
+    }
+
+    fn retry_unauthorized<'a>(
+        &'a self,
+        request: &'a RequestParts,
+        response: &'a ResponseParts,
+    ) -> AuthFuture<'a, bool> {
+        let maybe_challenge = response
+            .headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|header_value| header_value.to_str().ok())
+            .flat_map(parse_challenges)
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("digest"))
+            .filter_map(|(_, params)| DigestChallenge::from_params(&params))
+            .min_by_key(|challenge| Reverse(challenge.algorithm));
+
+        let is_retrying = match (maybe_challenge, self.nonces.challenges.lock()) {
+            (Some(challenge), Ok(mut challenges)) => {
+                challenges.insert(origin(&request.uri), challenge);
+                true
+            }
+            _ => false,
+        };
+
+        Box::pin(ready(Ok(is_retrying)))
+    }
+}
+
+impl Debug for DigestAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("DigestAuth")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl DigestChallenge {
+    /// Builds a challenge from the parameters of a `WWW-Authenticate: Digest` header.
+    ///
+    /// `None` is returned for challenges using an algorithm or qop which is not supported.
+    fn from_params(params: &[(String, String)]) -> Option<Self> {
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(param_name, _)| param_name.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+
+        let (algorithm, is_session) = DigestAlgorithm::parse(param("algorithm").unwrap_or("MD5"))?;
+        let is_qop_auth = match param("qop") {
+            None => false,
+            Some(qop)
+                if qop
+                    .split(',')
+                    .any(|qop| qop.trim().eq_ignore_ascii_case("auth")) =>
+            {
+                true
+            }
+            Some(_) => return None,
+        };
+
+        Some(Self {
+            realm: param("realm")?.to_string(),
+            nonce: param("nonce")?.to_string(),
+            opaque: param("opaque").map(str::to_string),
+            algorithm,
+            is_session,
+            is_qop_auth,
+            is_userhash: param("userhash").is_some_and(|value| value.eq_ignore_ascii_case("true")),
+            nonce_count: 0,
+        })
+    }
+}
+
+impl DigestAlgorithm {
+    /// Reads the algorithm name, returning it along with if it is a `-sess` variant.
+    fn parse(name: &str) -> Option<(Self, bool)> {
+        let (name, is_session) = match name.len().checked_sub("-sess".len()) {
+            Some(index) if name[index..].eq_ignore_ascii_case("-sess") => (&name[..index], true),
+            _ => (name, false),
+        };
+
+        let algorithm = [Self::Md5, Self::Sha256, Self::Sha512_256]
+            .into_iter()
+            .find(|algorithm| algorithm.as_str().eq_ignore_ascii_case(name))?;
+
+        Some((algorithm, is_session))
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Sha256 => "SHA-256",
+            Self::Sha512_256 => "SHA-512-256",
+        }
+    }
+
+    /// Hashes the text given, as lowercase hex.
+    fn hash(self, text: &str) -> String {
+        match self {
+            Self::Md5 => ::hex::encode(Md5::digest(text)),
+            Self::Sha256 => ::hex::encode(Sha256::digest(text)),
+            Self::Sha512_256 => ::hex::encode(Sha512_256::digest(text)),
+        }
+    }
+}
+
+/// The origin of the path given, which challenges are stored under.
+fn origin(request_path: &Uri) -> String {
+    format!(
+        "{}://{}",
+        request_path.scheme_str().unwrap_or("http"),
+        request_path
+            .authority()
+            .map(|authority| authority.as_str().to_ascii_lowercase())
+            .unwrap_or_default()
+    )
+}
+
+/// Wraps the text in quotes, escaping any quotes or backslashes within it.
+fn quote(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+/// Splits a `WWW-Authenticate` header into the challenges within it,
+/// each being the scheme and its parameters.
+///
+/// Parameter values have any quotes removed.
+fn parse_challenges(header: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut challenges: Vec<(String, Vec<(String, String)>)> = vec![];
+
+    for item in split_unquoted_commas(header) {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let token_end = item
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(item.len());
+        let is_param = item[token_end..].trim_start().starts_with('=');
+
+        let param_text = if is_param {
+            item
+        } else {
+            challenges.push((item[..token_end].to_string(), vec![]));
+            item[token_end..].trim()
+        };
+
+        if let Some((name, value)) = param_text.split_once('=') {
+            if let Some((_, params)) = challenges.last_mut() {
+                params.push((name.trim().to_string(), unquote(value.trim())));
+            }
+        }
+    }
+
+    challenges
+}
+
+/// Splits the text on each comma which is not within a quoted string.
+fn split_unquoted_commas(text: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut is_quoted = false;
+    let mut is_escaped = false;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if is_escaped => is_escaped = false,
+            '\\' if is_quoted => is_escaped = true,
+            '"' => is_quoted = !is_quoted,
+            ',' if !is_quoted => {
+                parts.push(&text[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+
+    parts
+}
+
+/// Removes the quotes from a quoted string, and undoes any escaping within it.
+fn unquote(value: &str) -> String {
+    let Some(quoted) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    else {
+        return value.to_string();
+    };
+
+    let mut unquoted = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            _ => unquoted.push(c),
+        }
+    }
+
+    unquoted
+}
+
+#[cfg(test)]
+mod test_authorization {
+    use super::*;
+
+    // The examples from RFC 7616 section 3.9.1.
+    const CHALLENGE_MD5: &str = r#"Digest
+        realm="http-auth@example.org",
+        qop="auth, auth-int",
+        algorithm=MD5,
+        nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v",
+        opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#;
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn digest_auth() -> DigestAuth {
+        DigestAuth::new(
+            "Mufasa".to_string(),
+            "Circle of Life".to_string(),
+            Arc::new(DigestNonces::default()),
+        )
+    }
+
+    fn first_challenge(header: &str) -> DigestChallenge {
+        let (_, params) = parse_challenges(header).remove(0);
+        let mut challenge = DigestChallenge::from_params(&params).unwrap();
+        challenge.nonce_count = 1;
+        challenge
+    }
+
+    #[test]
+    fn it_should_match_the_rfc_md5_example() {
+        let challenge = first_challenge(CHALLENGE_MD5);
+        let authorization =
+            digest_auth().authorization(&challenge, &Method::GET, "/dir/index.html", CNONCE);
+
+        assert_eq!(
+            authorization,
+            r#"Digest username="Mufasa", realm="http-auth@example.org", uri="/dir/index.html", algorithm=MD5, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", nc=00000001, cnonce="f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ", qop=auth, response="8ca523f5e9506fed4657c9700eebdbec", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#
+        );
+    }
+
+    #[test]
+    fn it_should_match_the_rfc_sha256_example() {
+        let challenge = first_challenge(&CHALLENGE_MD5.replace("MD5", "SHA-256"));
+        let authorization =
+            digest_auth().authorization(&challenge, &Method::GET, "/dir/index.html", CNONCE);
+
+        assert!(authorization.contains("algorithm=SHA-256"));
+        assert!(authorization.contains(
+            r#"response="753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1""#
+        ));
+    }
+
+    #[test]
+    fn it_should_hash_the_username_when_asked() {
+        // The user and realm from the example in RFC 7616 section 3.9.2.
+        let challenge = first_challenge(
+            r#"Digest realm="api@example.org", algorithm=SHA-512-256, nonce="abc", userhash=true"#,
+        );
+        let digest_auth = DigestAuth::new(
+            "J\u{e4}s\u{f8}n Doe".to_string(),
+            "Secret, or not?".to_string(),
+            Arc::new(DigestNonces::default()),
+        );
+        let authorization = digest_auth.authorization(&challenge, &Method::GET, "/doe.json", "x");
+
+        assert!(authorization.contains(
+            r#"username="793263caabb707a56211940d90411ea4a575adeccb7e360aeb624ed06ece9b0b""#
+        ));
+        assert!(authorization.contains("userhash=true"));
+    }
+
+    #[test]
+    fn it_should_pick_the_strongest_algorithm() {
+        let digest_auth = digest_auth();
+        let mut response = ::hyper::http::Response::new(());
+        let headers = response.headers_mut();
+        headers.append(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static(r#"Digest realm="a", nonce="1", algorithm=MD5, qop="auth""#),
+        );
+        headers.append(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static(
+                r#"Digest realm="a", nonce="2", algorithm=SHA-256, qop="auth", Basic realm="a""#,
+            ),
+        );
+        let (parts, _) = response.into_parts();
+        let (request_parts, ()) = ::hyper::http::Request::get("http://example.com/path")
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let is_retrying = ::futures_util::FutureExt::now_or_never(
+            digest_auth.retry_unauthorized(&request_parts, &parts),
+        )
+        .unwrap()
+        .unwrap();
+
+        let challenges = digest_auth.nonces.challenges.lock().unwrap();
+        let challenge = &challenges["http://example.com"];
+        assert!(is_retrying);
+        assert_eq!(challenge.algorithm, DigestAlgorithm::Sha256);
+        assert_eq!(challenge.nonce, "2");
+    }
+
+    #[test]
+    fn it_should_not_accept_unsupported_challenges() {
+        let challenge = |header: &str| {
+            let (_, params) = parse_challenges(header).remove(0);
+            DigestChallenge::from_params(&params)
+        };
+
+        assert_eq!(
+            challenge(r#"Digest realm="a", nonce="1", qop="auth-int""#),
+            None
+        );
+        assert_eq!(
+            challenge(r#"Digest realm="a", nonce="1", algorithm=SHA-1"#),
+            None
+        );
+        assert_eq!(challenge(r#"Digest realm="a""#), None);
+    }
+}
+
+#[cfg(test)]
+mod test_parse_challenges {
+    use super::*;
+
+    fn params(params: &[(&str, &str)]) -> Vec<(String, String)> {
+        params
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn it_should_parse_multiple_challenges() {
+        let challenges = parse_challenges(
+            r#"Negotiate, Digest realm="a, b", nonce="1", algorithm=MD5-sess, Basic realm="c""#,
+        );
+
+        assert_eq!(
+            challenges,
+            vec![
+                ("Negotiate".to_string(), vec![]),
+                (
+                    "Digest".to_string(),
+                    params(&[("realm", "a, b"), ("nonce", "1"), ("algorithm", "MD5-sess")])
+                ),
+                ("Basic".to_string(), params(&[("realm", "c")])),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_unescape_quoted_values() {
+        let challenges = parse_challenges(r#"Digest realm="say \"hi\" \\ bye""#);
+
+        assert_eq!(challenges[0].1, params(&[("realm", r#"say "hi" \ bye"#)]));
+    }
+}
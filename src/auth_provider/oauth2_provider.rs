@@ -0,0 +1,471 @@
+use ::anyhow::anyhow;
+use ::anyhow::Result as AnyhowResult;
+use ::hyper::http::header::AUTHORIZATION;
+use ::hyper::http::request::Parts as RequestParts;
+use ::hyper::http::response::Parts as ResponseParts;
+use ::hyper::http::HeaderValue;
+use ::hyper::Uri;
+use ::percent_encoding::utf8_percent_encode;
+use ::percent_encoding::AsciiSet;
+use ::percent_encoding::NON_ALPHANUMERIC;
+use ::serde::Deserialize;
+use ::std::fmt::Debug;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+use ::std::time::Duration;
+use ::std::time::Instant;
+use ::tokio::sync::Mutex as AsyncMutex;
+
+use crate::AuthFuture;
+use crate::AuthProvider;
+use crate::Error;
+use crate::Server;
+
+const DEFAULT_REFRESH_BEFORE_EXPIRY: Duration = Duration::from_secs(30);
+
+/// Characters which are percent encoded in `application/x-www-form-urlencoded` values.
+/// Spaces are encoded separately, as `+`.
+const FORM_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'*')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b' ');
+
+/// An `AuthProvider` for OAuth2, which fetches an access token from a token endpoint,
+/// and sends it as `Authorization: Bearer <token>`.
+///
+/// The token is fetched on the first request, and reused until it is about to expire.
+/// It is then fetched again before the next request, rather than waiting for it to be rejected.
+/// If the server still responds with `401 Unauthorized`, then a new token is fetched,
+/// and the request is sent again, once.
+///
+/// Tokens are fetched using either the client credentials grant, or the refresh token grant.
+/// The client id and secret are form encoded, and sent using HTTP Basic authentication,
+/// as section 2.3.1 of RFC 6749 requires.
+///
+/// ```rust
+/// use ::kantan::OAuth2Provider;
+/// use ::kantan::ServerConfig;
+///
+/// # fn main() -> ::anyhow::Result<()> {
+/// let auth_provider = OAuth2Provider::client_credentials(
+///     "https://auth.example.com/oauth/token",
+///     "my-client-id",
+///     "my-client-secret",
+/// )?
+/// .scopes(["orders:read", "orders:write"]);
+///
+/// let config = ServerConfig::builder()
+///     .auth_provider(auth_provider)
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+pub struct OAuth2Provider {
+    token_url: String,
+    token_server: Server,
+    client_id: String,
+    client_secret: String,
+    scopes: Vec<String>,
+    refresh_before_expiry: Duration,
+    tokens: AsyncMutex<Tokens>,
+}
+
+/// The tokens held by an `OAuth2Provider`.
+///
+/// This is behind an async lock, so concurrent requests wait for one token to be fetched,
+/// rather than all fetching their own.
+#[derive(Default)]
+struct Tokens {
+    access_token: Option<AccessToken>,
+    refresh_token: Option<String>,
+}
+
+struct AccessToken {
+    header_value: HeaderValue,
+
+    /// When a new token is fetched, ahead of this one expiring.
+    /// `None` is for tokens which do not expire.
+    refresh_at: Option<Instant>,
+}
+
+/// The successful response from a token endpoint, from RFC 6749 section 5.1.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: Option<String>,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+impl OAuth2Provider {
+    /// A provider which fetches tokens using the client credentials grant.
+    pub fn client_credentials<I, S>(
+        token_url: &str,
+        client_id: I,
+        client_secret: S,
+    ) -> AnyhowResult<Self>
+    where
+        I: Into<String>,
+        S: Into<String>,
+    {
+        Self::new(token_url, client_id.into(), client_secret.into(), None)
+    }
+
+    /// A provider which fetches tokens using the refresh token grant,
+    /// starting from the refresh token given.
+    ///
+    /// When the token endpoint returns a new refresh token, it is used from then on.
+    pub fn refresh_token<I, S, R>(
+        token_url: &str,
+        client_id: I,
+        client_secret: S,
+        refresh_token: R,
+    ) -> AnyhowResult<Self>
+    where
+        I: Into<String>,
+        S: Into<String>,
+        R: Into<String>,
+    {
+        Self::new(
+            token_url,
+            client_id.into(),
+            client_secret.into(),
+            Some(refresh_token.into()),
+        )
+    }
+
+    fn new(
+        token_url: &str,
+        client_id: String,
+        client_secret: String,
+        refresh_token: Option<String>,
+    ) -> AnyhowResult<Self> {
+        Ok(Self {
+            token_url: token_url.to_string(),
+            token_server: Server::new(token_url.to_string())?,
+            client_id,
+            client_secret,
+            scopes: vec![],
+            refresh_before_expiry: DEFAULT_REFRESH_BEFORE_EXPIRY,
+            tokens: AsyncMutex::new(Tokens {
+                access_token: None,
+                refresh_token,
+            }),
+        })
+    }
+
+    /// Sets the scopes asked for when fetching a token.
+    ///
+    /// **Defaults** to no scopes, leaving it to the token endpoint.
+    pub fn scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets how long before a token expires that a new one is fetched.
+    ///
+    /// This is capped at half of the token's lifetime,
+    /// so short lived tokens are still reused for a while.
+    ///
+    /// **Defaults** to 30 seconds.
+    pub fn refresh_before_expiry(mut self, duration: Duration) -> Self {
+        self.refresh_before_expiry = duration;
+        self
+    }
+
+    /// Returns true if the token is missing, or expires within the refresh window.
+    fn is_refresh_needed(&self, tokens: &Tokens, now: Instant) -> bool {
+        match &tokens.access_token {
+            None => true,
+            Some(AccessToken {
+                refresh_at: Some(refresh_at),
+                ..
+            }) => *refresh_at <= now,
+            Some(_) => false,
+        }
+    }
+
+    /// Works out when to refresh a token, fetched now, which expires in the time given.
+    ///
+    /// `None` is returned when the expiry is too far in the future to represent,
+    /// and the token is treated as never expiring.
+    fn refresh_at(&self, now: Instant, expires_in: Duration) -> Option<Instant> {
+        let refresh_before_expiry = self.refresh_before_expiry.min(expires_in / 2);
+        now.checked_add(expires_in - refresh_before_expiry)
+    }
+
+    /// Fetches a new access token, storing it along with any new refresh token.
+    async fn fetch_token(&self, tokens: &mut Tokens, request_path: &Uri) -> Result<(), Error> {
+        let auth_error = |source: Error| Error::Auth {
+            request_path: request_path.clone(),
+            source: Box::new(source),
+        };
+
+        let scope = self.scopes.join(" ");
+        let mut form = match tokens.refresh_token.as_deref() {
+            Some(refresh_token) => vec![
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ],
+            None => vec![("grant_type", "client_credentials")],
+        };
+        if !scope.is_empty() {
+            form.push(("scope", scope.as_str()));
+        }
+
+        let token_response = self
+            .token_server
+            .post("")
+            .basic_auth(
+                form_urlencode(&self.client_id),
+                form_urlencode(&self.client_secret),
+            )
+            .form(&form)
+            .expect_success()
+            .try_send()
+            .await
+            .map_err(auth_error)?
+            .try_json::<TokenResponse>()
+            .map_err(auth_error)?;
+
+        if let Some(token_type) = token_response.token_type.as_deref() {
+            if !token_type.eq_ignore_ascii_case("bearer") {
+                return Err(Error::Auth {
+                    request_path: request_path.clone(),
+                    source: anyhow!("Unsupported OAuth2 token type '{}'", token_type).into(),
+                });
+            }
+        }
+
+        let header_text = format!("Bearer {}", token_response.access_token);
+        let mut header_value =
+            HeaderValue::from_str(&header_text).map_err(|err| Error::InvalidHeader {
+                name: AUTHORIZATION.to_string(),
+                source: Box::new(err),
+            })?;
+        header_value.set_sensitive(true);
+
+        let now = Instant::now();
+        tokens.access_token = Some(AccessToken {
+            header_value,
+            refresh_at: token_response
+                .expires_in
+                .and_then(|expires_in| self.refresh_at(now, Duration::from_secs(expires_in))),
+        });
+        if token_response.refresh_token.is_some() {
+            tokens.refresh_token = token_response.refresh_token;
+        }
+
+        Ok(())
+    }
+}
+
+impl AuthProvider for OAuth2Provider {
+    fn authorize<'a>(
+        &'a self,
+        request: &'a mut RequestParts,
+        _body: Option<&'a [u8]>,
+    ) -> AuthFuture<'a, ()> {
+        Box::pin(async move {
+            let mut tokens = self.tokens.lock().await;
+            if self.is_refresh_needed(&tokens, Instant::now()) {
+                self.fetch_token(&mut tokens, &request.uri).await?;
+            }
+
+            if let Some(access_token) = tokens.access_token.as_ref() {
+                request
+                    .headers
+                    .insert(AUTHORIZATION, access_token.header_value.clone());
+            }
+
+            Ok(())
+        })
+    }
+
+    fn retry_unauthorized<'a>(
+        &'a self,
+        request: &'a RequestParts,
+        _response: &'a ResponseParts,
+    ) -> AuthFuture<'a, bool> {
+        Box::pin(async move {
+            // The token sent was rejected, so the next request fetches a new one.
+            // When another request has already replaced it, the new token is kept.
+            let mut tokens = self.tokens.lock().await;
+            let cached_header = tokens
+                .access_token
+                .as_ref()
+                .map(|access_token| &access_token.header_value);
+            if cached_header == request.headers.get(AUTHORIZATION) {
+                tokens.access_token = None;
+            }
+
+            Ok(true)
+        })
+    }
+}
+
+/// Encodes the text as an `application/x-www-form-urlencoded` value.
+fn form_urlencode(text: &str) -> String {
+    utf8_percent_encode(text, FORM_ENCODE_SET)
+        .to_string()
+        .replace(' ', "+")
+}
+
+impl Debug for OAuth2Provider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("OAuth2Provider")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("scopes", &self.scopes)
+            .field("refresh_before_expiry", &self.refresh_before_expiry)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test_is_refresh_needed {
+    use super::*;
+
+    fn provider() -> OAuth2Provider {
+        OAuth2Provider::client_credentials("http://localhost/token", "client", "secret").unwrap()
+    }
+
+    fn tokens_refreshing_at(refresh_at: Option<Instant>) -> Tokens {
+        Tokens {
+            access_token: Some(AccessToken {
+                header_value: HeaderValue::from_static("Bearer abc123"),
+                refresh_at,
+            }),
+            refresh_token: None,
+        }
+    }
+
+    #[test]
+    fn it_should_need_refresh_without_a_token() {
+        assert!(provider().is_refresh_needed(&Tokens::default(), Instant::now()));
+    }
+
+    #[test]
+    fn it_should_not_need_refresh_for_tokens_without_an_expiry() {
+        let tokens = tokens_refreshing_at(None);
+
+        assert!(!provider().is_refresh_needed(&tokens, Instant::now()));
+    }
+
+    #[test]
+    fn it_should_need_refresh_once_the_refresh_time_has_passed() {
+        let now = Instant::now();
+        let tokens = tokens_refreshing_at(Some(now));
+
+        assert!(provider().is_refresh_needed(&tokens, now));
+        assert!(provider().is_refresh_needed(&tokens, now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn it_should_not_need_refresh_before_the_refresh_time() {
+        let now = Instant::now();
+        let tokens = tokens_refreshing_at(Some(now + Duration::from_secs(10)));
+
+        assert!(!provider().is_refresh_needed(&tokens, now));
+    }
+
+    #[test]
+    fn it_should_refresh_within_the_refresh_window() {
+        let now = Instant::now();
+        let refresh_at = provider().refresh_at(now, Duration::from_secs(3600));
+
+        assert_eq!(refresh_at, Some(now + Duration::from_secs(3570)));
+    }
+
+    #[test]
+    fn it_should_cap_the_refresh_window_for_short_lived_tokens() {
+        let now = Instant::now();
+        let refresh_at = provider().refresh_at(now, Duration::from_secs(20));
+
+        assert_eq!(refresh_at, Some(now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn it_should_not_expire_tokens_too_far_in_the_future() {
+        let refresh_at = provider().refresh_at(Instant::now(), Duration::from_secs(u64::MAX));
+
+        assert_eq!(refresh_at, None);
+    }
+
+    #[test]
+    fn it_should_redact_the_secret_from_debug() {
+        let output = format!("{:?}", provider());
+
+        assert!(output.contains("client"));
+        assert!(!output.contains("\"secret\""));
+    }
+}
+
+#[cfg(test)]
+mod test_form_urlencode {
+    use super::*;
+
+    #[test]
+    fn it_should_encode_client_secrets() {
+        assert_eq!(form_urlencode("p:ss+w%rd"), "p%3Ass%2Bw%25rd");
+        assert_eq!(form_urlencode("my client"), "my+client");
+        assert_eq!(form_urlencode("my-client_1.0*"), "my-client_1.0*");
+    }
+}
+
+#[cfg(test)]
+mod test_retry_unauthorized {
+    use super::*;
+
+    fn provider_with_token(header_value: &'static str) -> OAuth2Provider {
+        let provider =
+            OAuth2Provider::client_credentials("http://localhost/token", "client", "secret")
+                .unwrap();
+        provider.tokens.try_lock().unwrap().access_token = Some(AccessToken {
+            header_value: HeaderValue::from_static(header_value),
+            refresh_at: None,
+        });
+
+        provider
+    }
+
+    async fn retry_with_header(provider: &OAuth2Provider, header_value: &'static str) -> bool {
+        let (request, ()) = ::hyper::http::Request::get("http://localhost/protected")
+            .header(AUTHORIZATION, header_value)
+            .body(())
+            .unwrap()
+            .into_parts();
+        let (response, ()) = ::hyper::http::Response::new(()).into_parts();
+
+        provider
+            .retry_unauthorized(&request, &response)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_clear_the_token_which_was_rejected() {
+        let provider = provider_with_token("Bearer token-1");
+
+        assert!(retry_with_header(&provider, "Bearer token-1").await);
+        assert!(provider.tokens.lock().await.access_token.is_none());
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_a_newer_token_fetched_since_the_request_was_sent() {
+        let provider = provider_with_token("Bearer token-2");
+
+        assert!(retry_with_header(&provider, "Bearer token-1").await);
+
+        let tokens = provider.tokens.lock().await;
+        let access_token = tokens.access_token.as_ref().unwrap();
+        assert_eq!(access_token.header_value, "Bearer token-2");
+    }
+}
@@ -0,0 +1,757 @@
+use ::futures_util::future::ready;
+use ::hmac::Hmac;
+use ::hmac::Mac;
+use ::hyper::http::header::HeaderName;
+use ::hyper::http::header::AUTHORIZATION;
+use ::hyper::http::header::HOST;
+use ::hyper::http::request::Parts as RequestParts;
+use ::hyper::http::HeaderMap;
+use ::hyper::http::HeaderValue;
+use ::hyper::Uri;
+use ::percent_encoding::percent_decode_str;
+use ::percent_encoding::percent_encode;
+use ::percent_encoding::AsciiSet;
+use ::percent_encoding::NON_ALPHANUMERIC;
+use ::sha2::Digest;
+use ::sha2::Sha256;
+use ::std::collections::BTreeMap;
+use ::std::fmt::Debug;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+use ::std::time::SystemTime;
+
+use crate::AuthFuture;
+use crate::AuthProvider;
+use crate::Error;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const X_AMZ_DATE: HeaderName = HeaderName::from_static("x-amz-date");
+const X_AMZ_SECURITY_TOKEN: HeaderName = HeaderName::from_static("x-amz-security-token");
+const X_AMZ_CONTENT_SHA256: HeaderName = HeaderName::from_static("x-amz-content-sha256");
+
+/// Headers which are signed, along with any starting with `x-amz-`.
+///
+/// Other headers are left out, as proxies and the connection may add or change them,
+/// such as `Accept-Encoding`, `Content-Length`, or `User-Agent`.
+const SIGNED_HEADERS: &[&str] = &["content-md5", "content-type", "date", "host"];
+const SIGNED_HEADER_PREFIX: &str = "x-amz-";
+
+/// Characters which are percent encoded within the canonical request.
+/// This is everything apart from the unreserved characters.
+const ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// An `AuthProvider` which signs each request using AWS Signature Version 4.
+///
+/// This works with AWS, and with S3 compatible storage such as MinIO.
+/// The signature covers the method, path, query, headers, and a hash of the body.
+///
+/// Bodies held in memory (such as from `Request::json` or `Request::bytes`) are hashed and signed.
+/// Streamed bodies (such as from `Request::file` or `Request::body_stream`)
+/// are sent as `UNSIGNED-PAYLOAD`, as the body is not known before it is sent.
+/// For the `s3` service, the payload hash is also sent in the `x-amz-content-sha256` header.
+///
+/// ```rust
+/// use ::kantan::ServerConfig;
+/// use ::kantan::SigV4Provider;
+///
+/// let signer = SigV4Provider::new(
+///     "my-access-key-id",
+///     "my-secret-access-key",
+///     "us-east-1",
+///     "s3",
+/// );
+///
+/// let config = ServerConfig::builder()
+///     .auth_provider(signer)
+///     .build();
+/// ```
+///
+/// Path segments are percent encoded once, as S3 expects.
+///
+/// Only the `Host`, `Content-Type`, `Content-MD5`, `Date`, and `x-amz-*` headers are signed.
+/// Others can be added using `SigV4Provider::sign_header`.
+#[derive(Clone)]
+pub struct SigV4Provider {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    region: String,
+    service: String,
+    is_unsigned_payload: bool,
+    signed_headers: Vec<String>,
+}
+
+impl SigV4Provider {
+    /// A provider signing with the credentials given, for the region and service given.
+    pub fn new<A, S, R, V>(access_key_id: A, secret_access_key: S, region: R, service: V) -> Self
+    where
+        A: Into<String>,
+        S: Into<String>,
+        R: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+            region: region.into(),
+            service: service.into(),
+            is_unsigned_payload: false,
+            signed_headers: Vec::new(),
+        }
+    }
+
+    /// Sends the session token from temporary credentials, in the `x-amz-security-token` header.
+    pub fn session_token<T>(mut self, session_token: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.session_token = Some(session_token.into());
+        self
+    }
+
+    /// Sends every body as `UNSIGNED-PAYLOAD`, skipping hashing the body.
+    ///
+    /// This saves hashing large uploads, relying on TLS to protect the body instead.
+    pub fn unsigned_payload(mut self) -> Self {
+        self.is_unsigned_payload = true;
+        self
+    }
+
+    /// Includes the header given in the signature, when it is on the request.
+    ///
+    /// Only send headers which are not changed on the way to the server,
+    /// or the signature will not match.
+    pub fn sign_header<N>(mut self, name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.signed_headers.push(name.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Signs the request at the time given, as `YYYYMMDDTHHMMSSZ`.
+    ///
+    /// This adds the `Host`, `x-amz-date`, and `Authorization` headers,
+    /// along with the `x-amz-security-token` and `x-amz-content-sha256` headers when needed.
+    pub(crate) fn sign(
+        &self,
+        request: &mut RequestParts,
+        body: Option<&[u8]>,
+        amz_date: &str,
+    ) -> Result<(), Error> {
+        let payload_hash = match body {
+            Some(body) if !self.is_unsigned_payload => sha256_hex(body),
+            _ => UNSIGNED_PAYLOAD.to_string(),
+        };
+
+        let headers = &mut request.headers;
+        if !headers.contains_key(HOST) {
+            insert_header(headers, HOST, &host(&request.uri), false)?;
+        }
+        insert_header(headers, X_AMZ_DATE, amz_date, false)?;
+        if let Some(session_token) = self.session_token.as_deref() {
+            insert_header(headers, X_AMZ_SECURITY_TOKEN, session_token, true)?;
+        }
+        if self.service == "s3" {
+            insert_header(headers, X_AMZ_CONTENT_SHA256, &payload_hash, false)?;
+        }
+
+        let (canonical_headers, signed_headers) = canonical_headers(headers, &self.signed_headers);
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            request.method,
+            canonical_uri(request.uri.path()),
+            canonical_query(request.uri.query().unwrap_or_default()),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let date = amz_date.get(..8).unwrap_or(amz_date);
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let secret = format!("AWS4{}", self.secret_access_key);
+        let signing_key = [date, &self.region, &self.service, "aws4_request"]
+            .into_iter()
+            .fold(secret.into_bytes(), |key, data| hmac_sha256(&key, data));
+        let signature = ::hex::encode(hmac_sha256(&signing_key, &string_to_sign));
+
+        let authorization = format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, self.access_key_id, scope, signed_headers, signature
+        );
+        insert_header(headers, AUTHORIZATION, &authorization, true)
+    }
+}
+
+impl AuthProvider for SigV4Provider {
+    fn authorize<'a>(
+        &'a self,
+        request: &'a mut RequestParts,
+        body: Option<&'a [u8]>,
+    ) -> AuthFuture<'a, ()> {
+        let amz_date = format_amz_date(SystemTime::now());
+        Box::pin(ready(self.sign(request, body, &amz_date)))
+    }
+}
+
+impl Debug for SigV4Provider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("SigV4Provider")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field(
+                "session_token",
+                &self.session_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("region", &self.region)
+            .field("service", &self.service)
+            .field("is_unsigned_payload", &self.is_unsigned_payload)
+            .field("signed_headers", &self.signed_headers)
+            .finish()
+    }
+}
+
+fn insert_header(
+    headers: &mut HeaderMap,
+    name: HeaderName,
+    value: &str,
+    is_sensitive: bool,
+) -> Result<(), Error> {
+    let mut header_value = HeaderValue::from_str(value).map_err(|err| Error::InvalidHeader {
+        name: name.to_string(),
+        source: Box::new(err),
+    })?;
+    header_value.set_sensitive(is_sensitive);
+    headers.insert(name, header_value);
+
+    Ok(())
+}
+
+/// The `Host` header for the path given, which is also what hyper would send.
+fn host(request_path: &Uri) -> String {
+    let host = request_path.host().unwrap_or_default();
+    match request_path.port_u16() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+/// Percent encodes each segment of the path, undoing any encoding already there.
+fn canonical_uri(path: &str) -> String {
+    if path.is_empty() {
+        return "/".to_string();
+    }
+
+    path.split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Percent encodes each query parameter, sorted by name and then value.
+///
+/// A `+` is read as a space, as query parameters are form encoded,
+/// so it is signed as `%20`. A literal plus is sent as `%2B`.
+fn canonical_query(query: &str) -> String {
+    let mut params = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            (
+                uri_encode(&name.replace('+', " ")),
+                uri_encode(&value.replace('+', " ")),
+            )
+        })
+        .collect::<Vec<_>>();
+    params.sort();
+
+    params
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Returns the canonical headers, with a line for each, and the list of signed header names.
+///
+/// Only the headers in `SIGNED_HEADERS`, starting with `x-amz-`, or in `extra_headers` are included.
+/// Names are lowercased and sorted, repeated headers are joined with commas,
+/// and whitespace within values is collapsed.
+fn canonical_headers(headers: &HeaderMap, extra_headers: &[String]) -> (String, String) {
+    let mut canonical: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (name, value) in headers {
+        let name_str = name.as_str();
+        let is_signed = SIGNED_HEADERS.contains(&name_str)
+            || name_str.starts_with(SIGNED_HEADER_PREFIX)
+            || extra_headers.iter().any(|extra| extra == name_str);
+        if !is_signed {
+            continue;
+        }
+
+        let value = String::from_utf8_lossy(value.as_bytes())
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        canonical.entry(name.as_str()).or_default().push(value);
+    }
+
+    let canonical_headers = canonical
+        .iter()
+        .map(|(name, values)| format!("{}:{}\n", name, values.join(",")))
+        .collect();
+    let signed_headers = canonical.into_keys().collect::<Vec<_>>().join(";");
+
+    (canonical_headers, signed_headers)
+}
+
+fn uri_encode(text: &str) -> String {
+    let decoded = percent_decode_str(text).collect::<Vec<u8>>();
+    percent_encode(&decoded, ENCODE_SET).to_string()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    ::hex::encode(Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC should accept keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Formats the time as `YYYYMMDDTHHMMSSZ`, in UTC.
+pub(crate) fn format_amz_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Converts days since 1970 to a civil date, using Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod test_aws_test_vectors {
+    use super::*;
+    use ::hyper::http::Method;
+    use ::hyper::http::Request as HttpRequest;
+
+    // The requests from the AWS Signature Version 4 test suite.
+    const AMZ_DATE: &str = "20150830T123600Z";
+    const CREDENTIAL: &str = "AKIDEXAMPLE/20150830/us-east-1/service/aws4_request";
+
+    fn signer() -> SigV4Provider {
+        SigV4Provider::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "us-east-1",
+            "service",
+        )
+    }
+
+    fn sign(method: Method, path: &str, headers: &[(&str, &str)], body: &[u8]) -> String {
+        let mut builder = HttpRequest::builder()
+            .method(method)
+            .uri(path)
+            .header("Host", "example.amazonaws.com");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let (mut parts, ()) = builder.body(()).unwrap().into_parts();
+
+        // The custom headers in the test suite are all signed.
+        let signer = headers
+            .iter()
+            .fold(signer(), |signer, (name, _)| signer.sign_header(*name));
+        signer.sign(&mut parts, Some(body), AMZ_DATE).unwrap();
+        parts.headers[AUTHORIZATION].to_str().unwrap().to_string()
+    }
+
+    fn expected(signed_headers: &str, signature: &str) -> String {
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}, SignedHeaders={}, Signature={}",
+            CREDENTIAL, signed_headers, signature
+        )
+    }
+
+    #[test]
+    fn it_should_sign_get_vanilla() {
+        assert_eq!(
+            sign(Method::GET, "/", &[], b""),
+            expected(
+                "host;x-amz-date",
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_post_vanilla() {
+        assert_eq!(
+            sign(Method::POST, "/", &[], b""),
+            expected(
+                "host;x-amz-date",
+                "5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_vanilla_query_order_key_case() {
+        assert_eq!(
+            sign(Method::GET, "/?Param2=value2&Param1=value1", &[], b""),
+            expected(
+                "host;x-amz-date",
+                "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_vanilla_empty_query_key() {
+        assert_eq!(
+            sign(Method::GET, "/?Param1=value1", &[], b""),
+            expected(
+                "host;x-amz-date",
+                "a67d582fa61cc504c4bae71f336f98b97f1ea3c7a6bfe1b6e45aec72011b9aeb"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_unreserved() {
+        assert_eq!(
+            sign(
+                Method::GET,
+                "/-._~0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz",
+                &[],
+                b""
+            ),
+            expected(
+                "host;x-amz-date",
+                "07ef7494c76fa4850883e2b006601f940f8a34d404d0cfa977f52a65bbf5f24f"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_utf8() {
+        assert_eq!(
+            sign(Method::GET, "/%E1%88%B4", &[], b""),
+            expected(
+                "host;x-amz-date",
+                "8318018e0b0f223aa2bbf98705b62bb787dc9c0e678f255a891fd03141be5d85"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_space() {
+        assert_eq!(
+            sign(Method::GET, "/example%20space/", &[], b""),
+            expected(
+                "host;x-amz-date",
+                "652487583200325589f1fba4c7e578f72c47cb61beeca81406b39ddec1366741"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_vanilla_query_order_encoded() {
+        assert_eq!(
+            sign(
+                Method::GET,
+                "/?Param-3=Value3&Param=Value2&%E1%88%B4=Value1",
+                &[],
+                b""
+            ),
+            expected(
+                "host;x-amz-date",
+                "371d3713e185cc334048618a97f809c9ffe339c62934c032af5a0e595648fcac"
+            )
+        );
+    }
+
+    // The AWS test suite has no spaces or plus signs in query values.
+    // These signatures are from the `aws-sigv4` crate of the AWS SDK for Rust (version 1.6.0),
+    // signing the same requests with the test suite credentials and date.
+    #[test]
+    fn it_should_sign_a_space_in_a_query_value() {
+        let expected = expected(
+            "host;x-amz-date",
+            "ffb4ff39bd71adfcc563cbecb7d967309bd9ac80b54162f65eba75481cb5153d",
+        );
+
+        assert_eq!(
+            sign(Method::GET, "/?Param1=value%20one", &[], b""),
+            expected
+        );
+        assert_eq!(sign(Method::GET, "/?Param1=value+one", &[], b""), expected);
+    }
+
+    #[test]
+    fn it_should_sign_a_plus_in_a_query_value() {
+        assert_eq!(
+            sign(Method::GET, "/?Param1=a%2Bb", &[], b""),
+            expected(
+                "host;x-amz-date",
+                "d27f4ac0ed6cb9097b1fe063420a26e0576ada50cb618c1020533b84aa411959"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_post_header_key_sort() {
+        assert_eq!(
+            sign(Method::POST, "/", &[("My-Header1", "value1")], b""),
+            expected(
+                "host;my-header1;x-amz-date",
+                "c5410059b04c1ee005303aed430f6e6645f61f4dc9e1461ec8f8916fdf18852c"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_header_value_trim() {
+        assert_eq!(
+            sign(
+                Method::GET,
+                "/",
+                &[("My-Header1", " value1"), ("My-Header2", " \"a   b   c\"")],
+                b""
+            ),
+            expected(
+                "host;my-header1;my-header2;x-amz-date",
+                "acc3ed3afb60bb290fc8d2dd0098b9911fcaa05412b367055dee359757a9c736"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_get_header_key_duplicate() {
+        assert_eq!(
+            sign(
+                Method::GET,
+                "/",
+                &[
+                    ("My-Header1", "value2"),
+                    ("My-Header1", "value2"),
+                    ("My-Header1", "value1")
+                ],
+                b""
+            ),
+            expected(
+                "host;my-header1;x-amz-date",
+                "c9d5ea9f3f72853aea855b47ea873832890dbdd183b4468f858259531a5138ea"
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_sign_post_x_www_form_urlencoded() {
+        assert_eq!(
+            sign(
+                Method::POST,
+                "/",
+                &[("Content-Type", "application/x-www-form-urlencoded")],
+                b"Param1=value1"
+            ),
+            expected(
+                "content-type;host;x-amz-date",
+                "ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a"
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_sign {
+    use super::*;
+    use ::hyper::http::Request as HttpRequest;
+
+    fn request_parts(uri: &str) -> RequestParts {
+        let (parts, ()) = HttpRequest::put(uri).body(()).unwrap().into_parts();
+        parts
+    }
+
+    #[test]
+    fn it_should_add_the_host_from_the_uri() {
+        let mut parts = request_parts("http://localhost:9000/bucket/key");
+        let signer = SigV4Provider::new("id", "secret", "us-east-1", "service");
+        signer.sign(&mut parts, None, "20150830T123600Z").unwrap();
+
+        assert_eq!(parts.headers[HOST], "localhost:9000");
+        assert_eq!(parts.headers[X_AMZ_DATE], "20150830T123600Z");
+    }
+
+    #[test]
+    fn it_should_send_the_payload_hash_to_s3() {
+        let mut parts = request_parts("http://localhost:9000/bucket/key");
+        let signer = SigV4Provider::new("id", "secret", "us-east-1", "s3");
+        signer
+            .sign(&mut parts, Some(b""), "20150830T123600Z")
+            .unwrap();
+
+        assert_eq!(
+            parts.headers[X_AMZ_CONTENT_SHA256],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert!(parts.headers[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .contains("SignedHeaders=host;x-amz-content-sha256;x-amz-date,"));
+    }
+
+    #[test]
+    fn it_should_not_sign_streamed_or_unsigned_payloads() {
+        let signer = SigV4Provider::new("id", "secret", "us-east-1", "s3");
+        let mut streamed_parts = request_parts("http://localhost:9000/bucket/key");
+        signer
+            .sign(&mut streamed_parts, None, "20150830T123600Z")
+            .unwrap();
+
+        let mut unsigned_parts = request_parts("http://localhost:9000/bucket/key");
+        signer
+            .unsigned_payload()
+            .sign(&mut unsigned_parts, Some(b"body"), "20150830T123600Z")
+            .unwrap();
+
+        assert_eq!(
+            streamed_parts.headers[X_AMZ_CONTENT_SHA256],
+            UNSIGNED_PAYLOAD
+        );
+        assert_eq!(
+            unsigned_parts.headers[X_AMZ_CONTENT_SHA256],
+            UNSIGNED_PAYLOAD
+        );
+    }
+
+    #[test]
+    fn it_should_sign_the_session_token() {
+        let mut parts = request_parts("http://localhost:9000/bucket/key");
+        let signer =
+            SigV4Provider::new("id", "secret", "us-east-1", "service").session_token("token");
+        signer.sign(&mut parts, None, "20150830T123600Z").unwrap();
+
+        assert_eq!(parts.headers[X_AMZ_SECURITY_TOKEN], "token");
+        assert!(parts.headers[X_AMZ_SECURITY_TOKEN].is_sensitive());
+        assert!(parts.headers[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .contains("SignedHeaders=host;x-amz-date;x-amz-security-token,"));
+    }
+
+    #[test]
+    fn it_should_only_sign_allowed_headers() {
+        let mut parts = request_parts("http://localhost:9000/bucket/key");
+        for (name, value) in [
+            ("accept-encoding", "gzip"),
+            ("content-length", "4"),
+            ("content-type", "text/plain"),
+            ("user-agent", "kantan"),
+            ("x-amz-meta-colour", "blue"),
+            ("x-api-key", "key"),
+        ] {
+            parts.headers.insert(name, HeaderValue::from_static(value));
+        }
+        let signer = SigV4Provider::new("id", "secret", "us-east-1", "service");
+        signer.sign(&mut parts, None, "20150830T123600Z").unwrap();
+
+        assert!(parts.headers[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .contains("SignedHeaders=content-type;host;x-amz-date;x-amz-meta-colour,"));
+    }
+
+    #[test]
+    fn it_should_sign_extra_headers_given() {
+        let mut parts = request_parts("http://localhost:9000/bucket/key");
+        parts
+            .headers
+            .insert("x-api-key", HeaderValue::from_static("key"));
+        let signer =
+            SigV4Provider::new("id", "secret", "us-east-1", "service").sign_header("X-Api-Key");
+        signer.sign(&mut parts, None, "20150830T123600Z").unwrap();
+
+        assert!(parts.headers[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .contains("SignedHeaders=host;x-amz-date;x-api-key,"));
+    }
+
+    #[test]
+    fn it_should_redact_secrets_from_debug() {
+        let signer =
+            SigV4Provider::new("id", "hunter2", "us-east-1", "s3").session_token("session-abc");
+        let output = format!("{:?}", signer);
+
+        assert!(!output.contains("hunter2"));
+        assert!(!output.contains("session-abc"));
+    }
+}
+
+#[cfg(test)]
+mod test_format_amz_date {
+    use super::*;
+    use ::std::time::Duration;
+
+    #[test]
+    fn it_should_format_the_unix_epoch() {
+        assert_eq!(format_amz_date(SystemTime::UNIX_EPOCH), "19700101T000000Z");
+    }
+
+    #[test]
+    fn it_should_format_the_aws_test_suite_date() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_440_938_160);
+
+        assert_eq!(format_amz_date(time), "20150830T123600Z");
+    }
+
+    #[test]
+    fn it_should_format_leap_days() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(951_868_799);
+
+        assert_eq!(format_amz_date(time), "20000229T235959Z");
+    }
+}
@@ -0,0 +1,108 @@
+use ::base64::engine::general_purpose::STANDARD as BASE64;
+use ::base64::Engine;
+use ::hyper::http::header::InvalidHeaderValue;
+use ::hyper::http::HeaderValue;
+use ::std::fmt::Debug;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+
+/// Credentials sent in the `Authorization` header of a request.
+///
+/// These are set using `Request::bearer_auth` and `Request::basic_auth`,
+/// or on every request using `ServerConfig::authorization`.
+///
+/// The secrets are hidden when printed using `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// Sent as `Bearer <token>`.
+    Bearer(String),
+
+    /// Sent as `Basic <base64 of username:password>`.
+    Basic { username: String, password: String },
+}
+
+impl Authorization {
+    /// Credentials for sending the token given, as `Bearer <token>`.
+    pub fn bearer<T>(token: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self::Bearer(token.into())
+    }
+
+    /// Credentials for HTTP Basic authentication.
+    pub fn basic<U, P>(username: U, password: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        Self::Basic {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Builds the `Authorization` header value.
+    ///
+    /// It is marked as sensitive, so it is also hidden when printed using `Debug`.
+    pub(crate) fn to_header_value(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        let header_text = match self {
+            Self::Bearer(token) => format!("Bearer {}", token),
+            Self::Basic { username, password } => {
+                let credentials = format!("{}:{}", username, password);
+                format!("Basic {}", BASE64.encode(credentials))
+            }
+        };
+
+        let mut header_value = HeaderValue::from_str(&header_text)?;
+        header_value.set_sensitive(true);
+
+        Ok(header_value)
+    }
+}
+
+impl Debug for Authorization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Bearer(_) => f.debug_tuple("Bearer").field(&"<redacted>").finish(),
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_authorization {
+    use super::*;
+
+    #[test]
+    fn it_should_build_bearer_headers() {
+        let header_value = Authorization::bearer("abc123").to_header_value().unwrap();
+
+        assert_eq!(header_value, "Bearer abc123");
+        assert!(header_value.is_sensitive());
+    }
+
+    #[test]
+    fn it_should_build_basic_headers() {
+        // The example from RFC 7617.
+        let header_value = Authorization::basic("Aladdin", "open sesame")
+            .to_header_value()
+            .unwrap();
+
+        assert_eq!(header_value, "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+    }
+
+    #[test]
+    fn it_should_redact_secrets_from_debug() {
+        let bearer = format!("{:?}", Authorization::bearer("abc123"));
+        let basic = format!("{:?}", Authorization::basic("Aladdin", "open sesame"));
+
+        assert!(!bearer.contains("abc123"));
+        assert!(basic.contains("Aladdin"));
+        assert!(!basic.contains("open sesame"));
+    }
+}
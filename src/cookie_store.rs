@@ -0,0 +1,43 @@
+use ::hyper::http::HeaderValue;
+use ::hyper::Uri;
+use ::std::fmt::Debug;
+
+mod cookie_file_format;
+pub use self::cookie_file_format::*;
+
+mod memory_cookie_store;
+pub use self::memory_cookie_store::*;
+
+mod stored_cookie;
+pub(crate) use self::stored_cookie::*;
+
+mod stored_cookies;
+pub(crate) use self::stored_cookies::*;
+
+/// Stores the cookies returned by responses,
+/// and decides which cookies to send on each request.
+///
+/// The `Server` uses a `MemoryCookieStore` by default.
+/// A different store can be given using `ServerConfig::cookie_store`,
+/// such as one shared across servers, persisted elsewhere, or kept per tenant.
+///
+/// Stores are shared between requests running at the same time,
+/// so they take `&self` and need to handle their own locking.
+pub trait CookieStore: Debug + Send + Sync {
+    /// Stores the cookies from the `Set-Cookie` headers given,
+    /// which were received in the response to the URI.
+    fn store_set_cookies(
+        &self,
+        set_cookie_headers: &mut dyn Iterator<Item = &HeaderValue>,
+        request_uri: &Uri,
+    );
+
+    /// Returns the value for the `Cookie` header,
+    /// to send on a request to the URI given.
+    ///
+    /// `None` is returned when there are no cookies to send.
+    fn cookie_header(&self, request_uri: &Uri) -> Option<HeaderValue>;
+
+    /// Removes all of the cookies stored.
+    fn clear(&self);
+}
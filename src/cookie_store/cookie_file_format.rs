@@ -0,0 +1,454 @@
+use ::anyhow::anyhow;
+use ::anyhow::Result;
+use ::cookie::Cookie;
+use ::serde::Deserialize;
+use ::serde::Serialize;
+use ::std::time::Duration;
+use ::std::time::SystemTime;
+use ::std::time::UNIX_EPOCH;
+
+use crate::StoredCookie;
+
+const NETSCAPE_HEADER: &str = "# Netscape HTTP Cookie File";
+const NETSCAPE_HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// The file formats cookies can be saved to, and loaded from.
+///
+/// See `Server::save_cookies_to` and `Server::load_cookies_from`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CookieFileFormat {
+    /// A JSON array of cookies,
+    /// with the expiry stored as seconds since the Unix epoch.
+    Json,
+
+    /// The Netscape `cookies.txt` format, as used by curl and wget.
+    Netscape,
+}
+
+/// A cookie as it is written in the JSON format.
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonCookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    expires: Option<u64>,
+}
+
+impl CookieFileFormat {
+    /// Writes out the cookies given in this format, oldest first.
+    ///
+    /// Cookies which have expired are left out.
+    pub(crate) fn write_cookies<'a, I>(&self, cookies: I, now: SystemTime) -> Result<String>
+    where
+        I: IntoIterator<Item = &'a StoredCookie>,
+    {
+        let mut cookies = cookies
+            .into_iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .collect::<Vec<&StoredCookie>>();
+        cookies.sort_by_key(|cookie| cookie.created_at());
+
+        match self {
+            Self::Json => {
+                let json_cookies = cookies
+                    .into_iter()
+                    .map(to_json_cookie)
+                    .collect::<Vec<JsonCookie>>();
+                serde_json::to_string_pretty(&json_cookies)
+                    .map_err(|err| anyhow!("Failed to write cookies as JSON, {}", err))
+            }
+            Self::Netscape => {
+                let mut contents = format!("{}\n", NETSCAPE_HEADER);
+                for cookie in cookies {
+                    contents.push_str(&to_netscape_line(cookie));
+                    contents.push('\n');
+                }
+
+                Ok(contents)
+            }
+        }
+    }
+
+    /// Reads the cookies from contents in this format.
+    ///
+    /// Each cookie is given a creation time just after the one before it,
+    /// so they keep the order they were written in, which is used to order the `Cookie` header.
+    /// Cookies which have expired are left out.
+    pub(crate) fn read_cookies(
+        &self,
+        contents: &str,
+        now: SystemTime,
+    ) -> Result<Vec<StoredCookie>> {
+        let cookies = match self {
+            Self::Json => {
+                let json_cookies: Vec<JsonCookie> = serde_json::from_str(contents)
+                    .map_err(|err| anyhow!("Failed to read cookies as JSON, {}", err))?;
+
+                json_cookies
+                    .into_iter()
+                    .map(|json_cookie| from_json_cookie(json_cookie, now))
+                    .collect::<Result<Vec<StoredCookie>>>()?
+            }
+            Self::Netscape => contents
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| is_netscape_cookie_line(line))
+                .map(|(index, line)| {
+                    from_netscape_line(line, now)
+                        .map_err(|err| anyhow!("Invalid cookie on line {}, {}", index + 1, err))
+                })
+                .collect::<Result<Vec<StoredCookie>>>()?,
+        };
+
+        Ok(cookies
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut cookie)| {
+                cookie.set_created_at(now + Duration::from_nanos(index as u64));
+                cookie
+            })
+            .filter(|cookie| !cookie.is_expired(now))
+            .collect())
+    }
+}
+
+fn build_cookie(
+    name: String,
+    value: String,
+    is_secure: bool,
+    is_http_only: bool,
+) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .secure(is_secure)
+        .http_only(is_http_only)
+        .finish()
+}
+
+fn to_unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn from_unix_seconds(seconds: u64) -> Result<SystemTime> {
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(seconds))
+        .ok_or_else(|| anyhow!("Expiry '{}' is too far in the future", seconds))
+}
+
+fn to_json_cookie(cookie: &StoredCookie) -> JsonCookie {
+    JsonCookie {
+        name: cookie.cookie().name().to_string(),
+        value: cookie.cookie().value().to_string(),
+        domain: cookie.domain().to_string(),
+        host_only: cookie.is_host_only(),
+        path: cookie.path().to_string(),
+        secure: cookie.is_secure(),
+        http_only: cookie.is_http_only(),
+        expires: cookie.expires_at().map(to_unix_seconds),
+    }
+}
+
+fn from_json_cookie(json_cookie: JsonCookie, now: SystemTime) -> Result<StoredCookie> {
+    let cookie = build_cookie(
+        json_cookie.name,
+        json_cookie.value,
+        json_cookie.secure,
+        json_cookie.http_only,
+    );
+
+    let expires_at = json_cookie.expires.map(from_unix_seconds).transpose()?;
+
+    Ok(StoredCookie::from_saved(
+        cookie,
+        json_cookie.domain,
+        json_cookie.host_only,
+        json_cookie.path,
+        expires_at,
+        now,
+    ))
+}
+
+/// Returns true for lines holding a cookie,
+/// skipping blank lines and comments.
+fn is_netscape_cookie_line(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && (!line.starts_with('#') || line.starts_with(NETSCAPE_HTTP_ONLY_PREFIX))
+}
+
+/// Writes a line of tab separated fields:
+/// domain, include subdomains, path, secure, expiry, name, and value.
+///
+/// Session cookies are written with an expiry of zero.
+fn to_netscape_line(cookie: &StoredCookie) -> String {
+    let http_only_prefix = if cookie.is_http_only() {
+        NETSCAPE_HTTP_ONLY_PREFIX
+    } else {
+        ""
+    };
+    let domain_prefix = if cookie.is_host_only() { "" } else { "." };
+
+    format!(
+        "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        http_only_prefix,
+        domain_prefix,
+        cookie.domain(),
+        to_netscape_bool(!cookie.is_host_only()),
+        cookie.path(),
+        to_netscape_bool(cookie.is_secure()),
+        cookie.expires_at().map(to_unix_seconds).unwrap_or(0),
+        cookie.cookie().name(),
+        cookie.cookie().value(),
+    )
+}
+
+fn from_netscape_line(line: &str, now: SystemTime) -> Result<StoredCookie> {
+    let (is_http_only, line) = match line.strip_prefix(NETSCAPE_HTTP_ONLY_PREFIX) {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    let fields = line.split('\t').collect::<Vec<&str>>();
+    let [domain, include_subdomains, path, secure, expires, name, value] = fields[..] else {
+        return Err(anyhow!(
+            "Expected 7 tab separated fields, found {}",
+            fields.len()
+        ));
+    };
+
+    let is_host_only = !from_netscape_bool(include_subdomains)?;
+    let is_secure = from_netscape_bool(secure)?;
+    let expires = expires
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| anyhow!("Invalid expiry '{}'", expires))?;
+    let expires_at = match expires {
+        0 => None,
+        seconds => Some(from_unix_seconds(seconds)?),
+    };
+
+    let cookie = build_cookie(
+        name.to_string(),
+        value.trim_end_matches('\r').to_string(),
+        is_secure,
+        is_http_only,
+    );
+
+    Ok(StoredCookie::from_saved(
+        cookie,
+        domain.trim_start_matches('.').to_string(),
+        is_host_only,
+        path.to_string(),
+        expires_at,
+        now,
+    ))
+}
+
+fn to_netscape_bool(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+fn from_netscape_bool(value: &str) -> Result<bool> {
+    match value {
+        "TRUE" => Ok(true),
+        "FALSE" => Ok(false),
+        _ => Err(anyhow!("Expected TRUE or FALSE, found '{}'", value)),
+    }
+}
+
+#[cfg(test)]
+mod test_cookie_file_format {
+    use super::*;
+
+    use ::hyper::Uri;
+
+    use crate::StoredCookies;
+
+    fn uri(uri: &str) -> Uri {
+        uri.parse().unwrap()
+    }
+
+    fn build_store(now: SystemTime) -> StoredCookies {
+        let mut store = StoredCookies::default();
+        for set_cookie in [
+            "session=abc; Path=/; HttpOnly",
+            "theme=dark; Domain=example.com; Path=/; Max-Age=3600",
+            "secret=shh; Path=/admin; Secure",
+        ] {
+            let cookie = Cookie::parse(set_cookie.to_string()).unwrap();
+            store.store_cookie(cookie, &uri("http://api.example.com/"), now);
+        }
+
+        store
+    }
+
+    fn round_trip(format: CookieFileFormat) {
+        let now = SystemTime::now();
+        let store = build_store(now);
+        let loaded_store = save_and_load(format, &store, now);
+
+        for request_uri in [
+            "http://api.example.com/",
+            "http://www.example.com/",
+            "https://api.example.com/admin",
+            "http://api.example.com/admin",
+        ] {
+            assert_eq!(
+                loaded_store.cookie_header(&uri(request_uri), now),
+                store.cookie_header(&uri(request_uri), now),
+                "for {}",
+                request_uri
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_round_trip_json() {
+        round_trip(CookieFileFormat::Json);
+    }
+
+    #[test]
+    fn it_should_round_trip_netscape() {
+        round_trip(CookieFileFormat::Netscape);
+    }
+
+    fn round_trip_cookie_order(format: CookieFileFormat) {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        for (set_cookie, seconds) in [("zebra=1", 0), ("apple=2", 1), ("zebra=3", 2)] {
+            let cookie = Cookie::parse(set_cookie.to_string()).unwrap();
+            let created_at = now + Duration::from_secs(seconds);
+            store.store_cookie(cookie, &uri("http://example.com/"), created_at);
+        }
+
+        let mut loaded_store = save_and_load(format, &store, now);
+        assert_eq!(
+            loaded_store.cookie_header(&uri("http://example.com/"), now),
+            Some("zebra=3; apple=2".to_string())
+        );
+
+        // Replacing a loaded cookie keeps its place, across saving it again.
+        let cookie = Cookie::parse("zebra=4").unwrap();
+        loaded_store.store_cookie(cookie, &uri("http://example.com/"), now);
+        let reloaded_store = save_and_load(format, &loaded_store, now);
+        assert_eq!(
+            reloaded_store.cookie_header(&uri("http://example.com/"), now),
+            Some("zebra=4; apple=2".to_string())
+        );
+    }
+
+    fn save_and_load(
+        format: CookieFileFormat,
+        store: &StoredCookies,
+        now: SystemTime,
+    ) -> StoredCookies {
+        let contents = format
+            .write_cookies(store.unexpired_cookies(now), now)
+            .unwrap();
+        let mut loaded_store = StoredCookies::default();
+        for cookie in format.read_cookies(&contents, now).unwrap() {
+            loaded_store.store(cookie, now);
+        }
+
+        loaded_store
+    }
+
+    #[test]
+    fn it_should_keep_the_cookie_order_in_json() {
+        round_trip_cookie_order(CookieFileFormat::Json);
+    }
+
+    #[test]
+    fn it_should_keep_the_cookie_order_in_netscape() {
+        round_trip_cookie_order(CookieFileFormat::Netscape);
+    }
+
+    #[test]
+    fn it_should_write_netscape_lines() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let store = build_store(now);
+
+        let contents = CookieFileFormat::Netscape
+            .write_cookies(store.unexpired_cookies(now), now)
+            .unwrap();
+
+        assert_eq!(
+            contents,
+            "# Netscape HTTP Cookie File\n\
+             #HttpOnly_api.example.com\tFALSE\t/\tFALSE\t0\tsession\tabc\n\
+             .example.com\tTRUE\t/\tFALSE\t4600\ttheme\tdark\n\
+             api.example.com\tFALSE\t/admin\tTRUE\t0\tsecret\tshh\n"
+        );
+    }
+
+    #[test]
+    fn it_should_discard_expired_cookies_on_read() {
+        let now = UNIX_EPOCH + Duration::from_secs(10_000);
+        let contents = "# Netscape HTTP Cookie File\n\
+                        example.com\tFALSE\t/\tFALSE\t5000\told\tgone\n\
+                        example.com\tFALSE\t/\tFALSE\t20000\tnew\there\n";
+
+        let cookies = CookieFileFormat::Netscape
+            .read_cookies(contents, now)
+            .unwrap();
+
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].cookie().name(), "new");
+    }
+
+    #[test]
+    fn it_should_error_on_invalid_netscape_lines() {
+        let contents = "example.com\tFALSE\t/\n";
+
+        let result = CookieFileFormat::Netscape.read_cookies(contents, SystemTime::now());
+
+        let message = result.unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "Invalid cookie on line 1, Expected 7 tab separated fields, found 3"
+        );
+    }
+
+    #[test]
+    fn it_should_error_on_an_expiry_too_far_in_the_future() {
+        let contents = "example.com\tFALSE\t/\tFALSE\t18446744073709551615\tsession\tabc123\n";
+
+        let result = CookieFileFormat::Netscape.read_cookies(contents, SystemTime::now());
+
+        let message = result.unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "Invalid cookie on line 1, Expiry '18446744073709551615' is too far in the future"
+        );
+    }
+
+    #[test]
+    fn it_should_error_on_a_json_expiry_too_far_in_the_future() {
+        let contents = r#"[{
+            "name": "session",
+            "value": "abc123",
+            "domain": "example.com",
+            "host_only": true,
+            "path": "/",
+            "secure": false,
+            "http_only": false,
+            "expires": 18446744073709551615
+        }]"#;
+
+        let result = CookieFileFormat::Json.read_cookies(contents, SystemTime::now());
+
+        let message = result.unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "Expiry '18446744073709551615' is too far in the future"
+        );
+    }
+}
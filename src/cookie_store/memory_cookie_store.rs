@@ -0,0 +1,124 @@
+use ::hyper::http::HeaderValue;
+use ::hyper::Uri;
+use ::std::fs::read_to_string;
+use ::std::fs::write;
+use ::std::path::Path;
+use ::std::sync::Mutex;
+use ::std::sync::MutexGuard;
+use ::std::time::SystemTime;
+
+use crate::CookieFileFormat;
+use crate::CookieStore;
+use crate::Error;
+use crate::StoredCookies;
+
+/// The default `CookieStore`, which holds cookies in memory.
+///
+/// Cookies are matched against the request URI on their domain, path,
+/// expiry, and the `Secure` attribute. Following the rules in RFC 6265.
+///
+/// It can be shared between servers, by passing it in to each `ServerConfig`.
+///
+/// ```rust
+/// use ::std::sync::Arc;
+/// use ::kantan::MemoryCookieStore;
+/// use ::kantan::ServerConfig;
+///
+/// let cookie_store = Arc::new(MemoryCookieStore::new());
+/// let config = ServerConfig::builder()
+///     .save_cookies()
+///     .cookie_store(cookie_store.clone())
+///     .build();
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryCookieStore {
+    cookies: Mutex<StoredCookies>,
+}
+
+impl MemoryCookieStore {
+    /// Creates a new store, holding no cookies.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Saves all of the cookies stored to the file given, in the format given.
+    ///
+    /// Cookies which have expired are not saved.
+    /// Session cookies (which have no expiry) are saved.
+    pub fn save_to<P>(&self, path: P, format: CookieFileFormat) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let now = SystemTime::now();
+        let contents = format
+            .write_cookies(self.lock().unexpired_cookies(now), now)
+            .map_err(|err| Error::CookieFile {
+                path: path.to_path_buf(),
+                source: err.into(),
+            })?;
+
+        write(path, contents).map_err(|err| Error::CookieFile {
+            path: path.to_path_buf(),
+            source: Box::new(err),
+        })
+    }
+
+    /// Loads cookies from the file given, in the format given.
+    ///
+    /// They are stored over the top of the existing cookies.
+    /// Cookies in the file which have expired are discarded.
+    pub fn load_from<P>(&self, path: P, format: CookieFileFormat) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = read_to_string(path).map_err(|err| Error::CookieFile {
+            path: path.to_path_buf(),
+            source: Box::new(err),
+        })?;
+
+        let now = SystemTime::now();
+        let cookies = format
+            .read_cookies(&contents, now)
+            .map_err(|err| Error::CookieFile {
+                path: path.to_path_buf(),
+                source: err.into(),
+            })?;
+
+        let mut stored_cookies = self.lock();
+        for cookie in cookies {
+            stored_cookies.store(cookie, now);
+        }
+
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StoredCookies> {
+        self.cookies
+            .lock()
+            .expect("Failed to lock MemoryCookieStore, a previous use panicked")
+    }
+}
+
+impl CookieStore for MemoryCookieStore {
+    fn store_set_cookies(
+        &self,
+        set_cookie_headers: &mut dyn Iterator<Item = &HeaderValue>,
+        request_uri: &Uri,
+    ) {
+        self.lock()
+            .store_set_cookie_headers(set_cookie_headers, request_uri, SystemTime::now());
+    }
+
+    fn cookie_header(&self, request_uri: &Uri) -> Option<HeaderValue> {
+        self.lock()
+            .cookie_header(request_uri, SystemTime::now())
+            .and_then(|header| HeaderValue::from_str(&header).ok())
+    }
+
+    fn clear(&self) {
+        self.lock().clear();
+    }
+}
@@ -0,0 +1,360 @@
+use ::cookie::Cookie;
+use ::hyper::Uri;
+use ::std::net::IpAddr;
+use ::std::time::Duration;
+use ::std::time::SystemTime;
+use ::std::time::UNIX_EPOCH;
+
+/// A cookie held within a cookie store,
+/// along with the details needed to decide where it is sent.
+///
+/// This follows the storage model in section 5.3 of RFC 6265.
+#[derive(Debug, Clone)]
+pub(crate) struct StoredCookie {
+    cookie: Cookie<'static>,
+    domain: String,
+    is_host_only: bool,
+    path: String,
+    expires_at: Option<SystemTime>,
+    created_at: SystemTime,
+}
+
+impl StoredCookie {
+    /// Builds the cookie to store, from a cookie sent in the response to the URI given.
+    ///
+    /// `None` is returned when the cookie should be ignored.
+    /// Such as it having a `Domain` which the URI is not within,
+    /// or a `Domain` which is a public suffix like `com` or `co.uk`.
+    pub(crate) fn from_response(
+        cookie: Cookie<'static>,
+        request_uri: &Uri,
+        now: SystemTime,
+    ) -> Option<Self> {
+        let request_host = request_uri.host()?.to_ascii_lowercase();
+
+        let (domain, is_host_only) = match cookie.domain() {
+            Some(domain) if !domain.is_empty() => {
+                let domain = domain.to_ascii_lowercase();
+                if !is_domain_match(&request_host, &domain) {
+                    return None;
+                }
+
+                // A public suffix is only allowed when it is the host itself,
+                // and then the cookie is host only.
+                // This follows step 5 of section 5.3 of RFC 6265.
+                if is_public_suffix(&domain) {
+                    if domain != request_host {
+                        return None;
+                    }
+
+                    (domain, true)
+                } else {
+                    (domain, false)
+                }
+            }
+            _ => (request_host, true),
+        };
+
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_string(),
+            _ => default_path(request_uri.path()),
+        };
+
+        // Max-Age takes priority over Expires.
+        // A Max-Age too far in the future to represent is kept as a session cookie.
+        let expires_at = match (cookie.max_age(), cookie.expires_datetime()) {
+            (Some(max_age), _) if max_age.whole_seconds() <= 0 => Some(UNIX_EPOCH),
+            (Some(max_age), _) => {
+                now.checked_add(Duration::from_secs(max_age.whole_seconds() as u64))
+            }
+            (None, Some(expires)) => Some(SystemTime::from(expires)),
+            (None, None) => None,
+        };
+
+        Some(Self {
+            cookie,
+            domain,
+            is_host_only,
+            path,
+            expires_at,
+            created_at: now,
+        })
+    }
+
+    /// Builds a cookie to store from details saved previously,
+    /// such as from a cookies file.
+    pub(crate) fn from_saved(
+        cookie: Cookie<'static>,
+        domain: String,
+        is_host_only: bool,
+        path: String,
+        expires_at: Option<SystemTime>,
+        created_at: SystemTime,
+    ) -> Self {
+        Self {
+            cookie,
+            domain: domain.to_ascii_lowercase(),
+            is_host_only,
+            path,
+            expires_at,
+            created_at,
+        }
+    }
+
+    pub(crate) fn cookie(&self) -> &Cookie<'static> {
+        &self.cookie
+    }
+
+    pub(crate) fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub(crate) fn is_host_only(&self) -> bool {
+        self.is_host_only
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// When this cookie expires.
+    /// `None` is for session cookies, which do not expire.
+    pub(crate) fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+    }
+
+    pub(crate) fn created_at(&self) -> SystemTime {
+        self.created_at
+    }
+
+    pub(crate) fn set_created_at(&mut self, created_at: SystemTime) {
+        self.created_at = created_at;
+    }
+
+    /// Returns true if both have the same name, domain, and path.
+    /// Storing one will replace the other.
+    pub(crate) fn is_same_cookie(&self, other: &Self) -> bool {
+        self.cookie.name() == other.cookie.name()
+            && self.domain == other.domain
+            && self.path == other.path
+    }
+
+    pub(crate) fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at
+            .map(|expires_at| expires_at <= now)
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn is_secure(&self) -> bool {
+        self.cookie.secure().unwrap_or(false)
+    }
+
+    pub(crate) fn is_http_only(&self) -> bool {
+        self.cookie.http_only().unwrap_or(false)
+    }
+
+    /// Returns true if this cookie should be sent on a request to the URI given.
+    ///
+    /// This follows section 5.4 of RFC 6265.
+    pub(crate) fn is_matching(&self, request_uri: &Uri, now: SystemTime) -> bool {
+        let request_host = match request_uri.host() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        };
+
+        let is_domain_matching = if self.is_host_only {
+            request_host == self.domain
+        } else {
+            is_domain_match(&request_host, &self.domain)
+        };
+
+        let is_secure_matching = !self.is_secure() || request_uri.scheme_str() == Some("https");
+
+        is_domain_matching
+            && is_secure_matching
+            && is_path_match(request_uri.path(), &self.path)
+            && !self.is_expired(now)
+    }
+}
+
+/// Returns true if the host is within the domain.
+///
+/// This follows section 5.1.3 of RFC 6265.
+pub(crate) fn is_domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+
+    let host_without_brackets = host.trim_start_matches('[').trim_end_matches(']');
+    let is_ip_address = host_without_brackets.parse::<IpAddr>().is_ok();
+
+    !is_ip_address
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+/// Public suffixes with more than one label, which cookies cannot be set on.
+///
+/// This is a small subset of the public suffix list,
+/// covering the most common registries.
+const MULTI_LABEL_PUBLIC_SUFFIXES: &[&str] = &[
+    "ac.jp", "ac.nz", "ac.uk", "co.id", "co.il", "co.in", "co.jp", "co.kr", "co.nz", "co.th",
+    "co.uk", "co.za", "com.ar", "com.au", "com.br", "com.cn", "com.hk", "com.mx", "com.my",
+    "com.ng", "com.pk", "com.sg", "com.tr", "com.tw", "com.ua", "edu.au", "gov.au", "gov.uk",
+    "ltd.uk", "me.uk", "ne.jp", "net.au", "net.br", "net.cn", "net.nz", "or.jp", "org.au",
+    "org.br", "org.cn", "org.nz", "org.uk", "plc.uk",
+];
+
+/// Returns true if the domain is a public suffix,
+/// such as `com` or `co.uk`, which is shared between many sites.
+///
+/// All single label domains are treated as public suffixes.
+pub(crate) fn is_public_suffix(domain: &str) -> bool {
+    !domain.contains('.') || MULTI_LABEL_PUBLIC_SUFFIXES.contains(&domain)
+}
+
+/// Returns true if the request path is within the cookie path.
+///
+/// This follows section 5.1.4 of RFC 6265.
+pub(crate) fn is_path_match(request_path: &str, cookie_path: &str) -> bool {
+    let request_path = if request_path.is_empty() {
+        "/"
+    } else {
+        request_path
+    };
+
+    if request_path == cookie_path {
+        return true;
+    }
+
+    request_path.starts_with(cookie_path)
+        && (cookie_path.ends_with('/') || request_path.as_bytes()[cookie_path.len()] == b'/')
+}
+
+/// The path used for cookies which do not set one.
+///
+/// This follows section 5.1.4 of RFC 6265.
+pub(crate) fn default_path(request_path: &str) -> String {
+    if !request_path.starts_with('/') {
+        return "/".to_string();
+    }
+
+    match request_path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => request_path[..index].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test_matching {
+    use super::*;
+
+    #[test]
+    fn it_should_domain_match_subdomains() {
+        assert!(is_domain_match("example.com", "example.com"));
+        assert!(is_domain_match("api.example.com", "example.com"));
+        assert!(!is_domain_match("badexample.com", "example.com"));
+        assert!(!is_domain_match("example.com", "api.example.com"));
+        assert!(!is_domain_match("1.2.3.4", "2.3.4"));
+    }
+
+    #[test]
+    fn it_should_find_public_suffixes() {
+        assert!(is_public_suffix("com"));
+        assert!(is_public_suffix("localhost"));
+        assert!(is_public_suffix("co.uk"));
+        assert!(!is_public_suffix("example.com"));
+        assert!(!is_public_suffix("example.co.uk"));
+    }
+
+    #[test]
+    fn it_should_path_match_sub_paths() {
+        assert!(is_path_match("/", "/"));
+        assert!(is_path_match("/users", "/"));
+        assert!(is_path_match("/users/1", "/users"));
+        assert!(is_path_match("/users/1", "/users/"));
+        assert!(!is_path_match("/users-list", "/users"));
+        assert!(!is_path_match("/", "/users"));
+    }
+
+    #[test]
+    fn it_should_build_default_paths() {
+        assert_eq!(default_path(""), "/");
+        assert_eq!(default_path("/"), "/");
+        assert_eq!(default_path("/login"), "/");
+        assert_eq!(default_path("/users/login"), "/users");
+    }
+}
+
+#[cfg(test)]
+mod test_from_response {
+    use super::*;
+
+    fn from_response(set_cookie: &str) -> StoredCookie {
+        try_from_response(set_cookie, "http://example.com/users/login").unwrap()
+    }
+
+    fn try_from_response(set_cookie: &str, request_uri: &'static str) -> Option<StoredCookie> {
+        let cookie = Cookie::parse(set_cookie.to_string()).unwrap();
+        let request_uri = Uri::from_static(request_uri);
+
+        StoredCookie::from_response(cookie, &request_uri, SystemTime::now())
+    }
+
+    #[test]
+    fn it_should_ignore_a_top_level_domain() {
+        let cookie = try_from_response("a=1; Domain=com", "http://example.com/");
+
+        assert!(cookie.is_none());
+    }
+
+    #[test]
+    fn it_should_ignore_a_multi_label_public_suffix() {
+        let cookie = try_from_response("a=1; Domain=co.uk", "http://example.co.uk/");
+
+        assert!(cookie.is_none());
+    }
+
+    #[test]
+    fn it_should_keep_a_public_suffix_domain_set_by_the_host_as_host_only() {
+        let cookie = try_from_response("a=1; Domain=localhost", "http://localhost/").unwrap();
+
+        assert_eq!(cookie.domain(), "localhost");
+        assert!(cookie.is_host_only());
+    }
+
+    #[test]
+    fn it_should_keep_registrable_domains() {
+        let cookie =
+            try_from_response("a=1; Domain=example.co.uk", "http://www.example.co.uk/").unwrap();
+
+        assert_eq!(cookie.domain(), "example.co.uk");
+        assert!(!cookie.is_host_only());
+    }
+
+    #[test]
+    fn it_should_expire_using_max_age() {
+        let before = SystemTime::now();
+        let expires_at = from_response("session=abc123; Max-Age=60")
+            .expires_at()
+            .unwrap();
+
+        assert!(expires_at >= before + Duration::from_secs(60));
+        assert!(expires_at <= SystemTime::now() + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn it_should_expire_immediately_with_a_zero_max_age() {
+        let cookie = from_response("session=abc123; Max-Age=0");
+
+        assert_eq!(cookie.expires_at(), Some(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn it_should_keep_an_oversized_max_age_as_a_session_cookie() {
+        let cookie = from_response("session=abc123; Max-Age=99999999999999999999");
+
+        assert_eq!(cookie.expires_at(), None);
+    }
+}
@@ -0,0 +1,333 @@
+use ::cookie::Cookie;
+use ::hyper::http::HeaderValue;
+use ::hyper::Uri;
+use ::std::time::SystemTime;
+
+use crate::StoredCookie;
+
+/// The cookies held within a `MemoryCookieStore`.
+/// This decides which of them to send on each request.
+///
+/// Cookies are matched against the request URI on their domain, path,
+/// expiry, and the `Secure` attribute. Following the rules in RFC 6265.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StoredCookies {
+    cookies: Vec<StoredCookie>,
+}
+
+impl StoredCookies {
+    /// Stores the cookies from `Set-Cookie` headers,
+    /// received in the response to the URI given.
+    ///
+    /// Headers which cannot be parsed are ignored, like a browser would.
+    pub(crate) fn store_set_cookie_headers<'a, I>(
+        &mut self,
+        cookie_headers: I,
+        request_uri: &Uri,
+        now: SystemTime,
+    ) where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        for cookie_header in cookie_headers {
+            let maybe_cookie = cookie_header
+                .to_str()
+                .ok()
+                .and_then(|cookie_str| Cookie::parse(cookie_str).ok());
+
+            if let Some(cookie) = maybe_cookie {
+                self.store_cookie(cookie.into_owned(), request_uri, now);
+            }
+        }
+    }
+
+    /// Stores the cookie, as though it was received in the response to the URI given.
+    ///
+    /// This replaces any cookie with the same name, domain, and path.
+    /// Cookies which have expired (such as `Max-Age=0`) remove that cookie instead.
+    pub(crate) fn store_cookie(
+        &mut self,
+        cookie: Cookie<'static>,
+        request_uri: &Uri,
+        now: SystemTime,
+    ) {
+        if let Some(stored_cookie) = StoredCookie::from_response(cookie, request_uri, now) {
+            self.store(stored_cookie, now);
+        }
+    }
+
+    /// Stores the cookie given, replacing any cookie with the same name, domain, and path.
+    ///
+    /// If the cookie has expired, then it removes that cookie instead.
+    pub(crate) fn store(&mut self, mut stored_cookie: StoredCookie, now: SystemTime) {
+        self.cookies.retain(|cookie| !cookie.is_expired(now));
+
+        let maybe_index = self
+            .cookies
+            .iter()
+            .position(|cookie| cookie.is_same_cookie(&stored_cookie));
+        if let Some(index) = maybe_index {
+            let old_cookie = self.cookies.remove(index);
+            stored_cookie.set_created_at(old_cookie.created_at());
+        }
+
+        if !stored_cookie.is_expired(now) {
+            self.cookies.push(stored_cookie);
+        }
+    }
+
+    /// Returns the cookies to send on a request to the URI given.
+    ///
+    /// They are ordered with the longest paths first,
+    /// and then the oldest cookies first.
+    pub(crate) fn matching_cookies(
+        &self,
+        request_uri: &Uri,
+        now: SystemTime,
+    ) -> Vec<&Cookie<'static>> {
+        let mut matching: Vec<&StoredCookie> = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.is_matching(request_uri, now))
+            .collect();
+
+        matching.sort_by(|a, b| {
+            b.path()
+                .len()
+                .cmp(&a.path().len())
+                .then_with(|| a.created_at().cmp(&b.created_at()))
+        });
+
+        matching.into_iter().map(|cookie| cookie.cookie()).collect()
+    }
+
+    /// Returns the value for a single `Cookie` header,
+    /// holding all of the cookies to send to the URI given.
+    ///
+    /// `None` is returned when there are no cookies to send.
+    pub(crate) fn cookie_header(&self, request_uri: &Uri, now: SystemTime) -> Option<String> {
+        let cookies = self.matching_cookies(request_uri, now);
+        if cookies.is_empty() {
+            return None;
+        }
+
+        let header = cookies
+            .into_iter()
+            .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+            .collect::<Vec<String>>()
+            .join("; ");
+
+        Some(header)
+    }
+
+    /// Returns all of the cookies held which have not expired.
+    pub(crate) fn unexpired_cookies(&self, now: SystemTime) -> Vec<&StoredCookie> {
+        self.cookies
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.cookies.clear();
+    }
+}
+
+#[cfg(test)]
+mod test_cookie_header {
+    use super::*;
+
+    use ::std::time::Duration;
+
+    fn uri(uri: &str) -> Uri {
+        uri.parse().unwrap()
+    }
+
+    fn store_cookie(
+        store: &mut StoredCookies,
+        set_cookie: &str,
+        request_uri: &str,
+        now: SystemTime,
+    ) {
+        let cookie = Cookie::parse(set_cookie.to_string()).unwrap();
+        store.store_cookie(cookie, &uri(request_uri), now);
+    }
+
+    #[test]
+    fn it_should_combine_cookies_into_one_header() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1", "http://example.com/", now);
+        store_cookie(
+            &mut store,
+            "b=2",
+            "http://example.com/",
+            now + Duration::from_secs(1),
+        );
+
+        let header = store.cookie_header(&uri("http://example.com/"), now);
+        assert_eq!(header, Some("a=1; b=2".to_string()));
+    }
+
+    #[test]
+    fn it_should_order_longer_paths_first() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1; Path=/", "http://example.com/", now);
+        store_cookie(&mut store, "b=2; Path=/users", "http://example.com/", now);
+
+        let header = store.cookie_header(&uri("http://example.com/users/1"), now);
+        assert_eq!(header, Some("b=2; a=1".to_string()));
+    }
+
+    #[test]
+    fn it_should_only_send_cookies_within_the_path() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1; Path=/admin", "http://example.com/", now);
+
+        assert_eq!(
+            store.cookie_header(&uri("http://example.com/users"), now),
+            None
+        );
+        assert_eq!(
+            store.cookie_header(&uri("http://example.com/admin/users"), now),
+            Some("a=1".to_string())
+        );
+    }
+
+    #[test]
+    fn it_should_use_default_path_from_request() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1", "http://example.com/users/login", now);
+
+        assert_eq!(store.cookie_header(&uri("http://example.com/"), now), None);
+        assert_eq!(
+            store.cookie_header(&uri("http://example.com/users/1"), now),
+            Some("a=1".to_string())
+        );
+    }
+
+    #[test]
+    fn it_should_only_send_host_only_cookies_to_the_same_host() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1", "http://example.com/", now);
+
+        assert_eq!(
+            store.cookie_header(&uri("http://api.example.com/"), now),
+            None
+        );
+        assert_eq!(store.cookie_header(&uri("http://other.com/"), now), None);
+    }
+
+    #[test]
+    fn it_should_send_domain_cookies_to_subdomains() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(
+            &mut store,
+            "a=1; Domain=example.com",
+            "http://www.example.com/",
+            now,
+        );
+
+        assert_eq!(
+            store.cookie_header(&uri("http://api.example.com/"), now),
+            Some("a=1".to_string())
+        );
+    }
+
+    #[test]
+    fn it_should_ignore_cookies_for_other_domains() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(
+            &mut store,
+            "a=1; Domain=other.com",
+            "http://example.com/",
+            now,
+        );
+
+        assert_eq!(store.cookie_header(&uri("http://other.com/"), now), None);
+    }
+
+    #[test]
+    fn it_should_ignore_cookies_for_a_top_level_domain() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1; Domain=com", "http://example.com/", now);
+
+        assert_eq!(store.cookie_header(&uri("http://example.com/"), now), None);
+        assert_eq!(store.cookie_header(&uri("http://other.com/"), now), None);
+    }
+
+    #[test]
+    fn it_should_only_send_secure_cookies_over_https() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1; Secure", "https://example.com/", now);
+
+        assert_eq!(store.cookie_header(&uri("http://example.com/"), now), None);
+        assert_eq!(
+            store.cookie_header(&uri("https://example.com/"), now),
+            Some("a=1".to_string())
+        );
+    }
+
+    #[test]
+    fn it_should_remove_cookies_on_max_age_zero() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1", "http://example.com/", now);
+        store_cookie(&mut store, "a=; Max-Age=0", "http://example.com/", now);
+
+        assert_eq!(store.cookie_header(&uri("http://example.com/"), now), None);
+    }
+
+    #[test]
+    fn it_should_remove_cookies_expiring_in_the_past() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1", "http://example.com/", now);
+        store_cookie(
+            &mut store,
+            "a=1; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            "http://example.com/",
+            now,
+        );
+
+        assert_eq!(store.cookie_header(&uri("http://example.com/"), now), None);
+    }
+
+    #[test]
+    fn it_should_not_send_cookies_after_max_age() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1; Max-Age=60", "http://example.com/", now);
+
+        let later = now + Duration::from_secs(61);
+        assert_eq!(
+            store.cookie_header(&uri("http://example.com/"), now),
+            Some("a=1".to_string())
+        );
+        assert_eq!(
+            store.cookie_header(&uri("http://example.com/"), later),
+            None
+        );
+    }
+
+    #[test]
+    fn it_should_replace_cookies_with_same_name_domain_and_path() {
+        let now = SystemTime::now();
+        let mut store = StoredCookies::default();
+        store_cookie(&mut store, "a=1", "http://example.com/", now);
+        store_cookie(&mut store, "a=2", "http://example.com/", now);
+        store_cookie(&mut store, "a=3; Path=/users", "http://example.com/", now);
+
+        assert_eq!(
+            store.cookie_header(&uri("http://example.com/users"), now),
+            Some("a=3; a=2".to_string())
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use ::anyhow::anyhow;
+use ::futures_util::StreamExt;
+use ::hyper::http::header;
+use ::hyper::http::HeaderMap;
+use ::hyper::http::StatusCode;
+use ::serde::Deserialize;
+use ::serde::Serialize;
+use ::std::ffi::OsString;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::tokio::fs::metadata;
+use ::tokio::fs::read_to_string;
+use ::tokio::fs::remove_file;
+use ::tokio::fs::rename;
+use ::tokio::fs::write;
+use ::tokio::fs::OpenOptions;
+use ::tokio::io::AsyncWriteExt;
+
+use crate::BoxError;
+use crate::Error;
+use crate::ExpectedStatus;
+use crate::StreamingResponse;
+
+/// A file downloaded using `Request::download_to`.
+#[derive(Debug, Clone)]
+pub struct Download {
+    path: PathBuf,
+    status_code: StatusCode,
+    length: u64,
+    resumed_from: u64,
+}
+
+impl Download {
+    /// Where the file was saved.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The status code of the response the file was downloaded from.
+    /// This is `206 Partial Content` when an earlier download was resumed.
+    #[must_use]
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    /// The length of the whole file, in bytes.
+    #[must_use]
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// How many bytes were already downloaded, from an earlier attempt.
+    ///
+    /// This is `0` when the download was not resumed.
+    #[must_use]
+    pub fn resumed_from(&self) -> u64 {
+        self.resumed_from
+    }
+
+    /// Returns true if this continued on from an earlier attempt.
+    #[must_use]
+    pub fn is_resumed(&self) -> bool {
+        self.resumed_from > 0
+    }
+}
+
+/// Where an earlier download stopped, and how to check the file is unchanged.
+#[derive(Debug, Clone)]
+pub(crate) struct ResumePoint {
+    pub offset: u64,
+    pub if_range: String,
+}
+
+/// The details saved next to a partial download, so it can be resumed.
+#[derive(Debug, Serialize, Deserialize)]
+struct PartialDownload {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl PartialDownload {
+    /// Builds the details to save from the response headers.
+    ///
+    /// `None` is returned when the server does not allow resuming,
+    /// either from not accepting byte ranges, or from having no way to check it is unchanged.
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let is_accepting_ranges = headers
+            .get(header::ACCEPT_RANGES)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().eq_ignore_ascii_case("bytes"))
+            .unwrap_or(false);
+        if !is_accepting_ranges {
+            return None;
+        }
+
+        // Weak ETags cannot be used with `If-Range`.
+        let etag = header_text(headers, &header::ETAG).filter(|etag| !etag.starts_with("W/"));
+        let last_modified = header_text(headers, &header::LAST_MODIFIED);
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            etag,
+            last_modified,
+        })
+    }
+
+    fn if_range(&self) -> Option<&str> {
+        self.etag.as_deref().or(self.last_modified.as_deref())
+    }
+}
+
+/// Returns where to resume a download to the path given,
+/// if an earlier attempt left a partial download that can be resumed.
+pub(crate) async fn find_resume_point(path: &Path) -> Option<ResumePoint> {
+    let state_text = read_to_string(state_path(path)).await.ok()?;
+    let partial_download: PartialDownload = serde_json::from_str(&state_text).ok()?;
+    let offset = metadata(partial_path(path)).await.ok()?.len();
+    if offset == 0 {
+        return None;
+    }
+
+    Some(ResumePoint {
+        offset,
+        if_range: partial_download.if_range()?.to_string(),
+    })
+}
+
+/// Streams the body of the response into the file at the path given.
+///
+/// The body is written to a partial file first, which is moved into place once complete.
+/// If the download fails part way, then the partial file is kept for resuming later.
+pub(crate) async fn download_response(
+    mut streaming_response: StreamingResponse,
+    path: &Path,
+    resume_point: Option<ResumePoint>,
+) -> Result<Download, Error> {
+    let status_code = streaming_response.status_code();
+    if status_code == StatusCode::RANGE_NOT_SATISFIABLE && resume_point.is_some() {
+        clear_partial_download(path).await;
+    }
+
+    if !status_code.is_success() {
+        let response = streaming_response.collect().await?;
+        return Err(Error::Status {
+            expected: ExpectedStatus::Success,
+            response: Box::new(response),
+        });
+    }
+
+    let headers = streaming_response.headers();
+    let content_length = header_text(headers, &header::CONTENT_LENGTH)
+        .and_then(|content_length| content_length.parse::<u64>().ok());
+    let resumed_from = match resume_point {
+        Some(resume_point) if status_code == StatusCode::PARTIAL_CONTENT => {
+            let range_start = header_text(headers, &header::CONTENT_RANGE)
+                .and_then(|content_range| parse_content_range_start(&content_range));
+            if range_start != Some(resume_point.offset) {
+                clear_partial_download(path).await;
+                return Err(download_error(
+                    path,
+                    anyhow!(
+                        "Expected the response to resume from byte {}",
+                        resume_point.offset
+                    ),
+                ));
+            }
+
+            resume_point.offset
+        }
+        _ => 0,
+    };
+
+    let partial_path = partial_path(path);
+    let mut open_options = OpenOptions::new();
+    if resumed_from > 0 {
+        open_options.append(true);
+    } else {
+        open_options.write(true).create(true).truncate(true);
+
+        match PartialDownload::from_headers(headers) {
+            Some(partial_download) => {
+                let state_text = serde_json::to_string(&partial_download)
+                    .map_err(|err| download_error(path, err))?;
+                write(state_path(path), state_text)
+                    .await
+                    .map_err(|err| download_error(path, err))?;
+            }
+            None => {
+                let _ = remove_file(state_path(path)).await;
+            }
+        }
+    }
+
+    let mut file = open_options
+        .open(&partial_path)
+        .await
+        .map_err(|err| download_error(path, err))?;
+
+    let mut received: u64 = 0;
+    while let Some(chunk) = streaming_response.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk)
+            .await
+            .map_err(|err| download_error(path, err))?;
+        received += chunk.len() as u64;
+    }
+    file.flush()
+        .await
+        .map_err(|err| download_error(path, err))?;
+    drop(file);
+
+    if let Some(content_length) = content_length {
+        if received != content_length {
+            return Err(download_error(
+                path,
+                anyhow!(
+                    "Expected {} bytes from Content-Length, received {}",
+                    content_length,
+                    received
+                ),
+            ));
+        }
+    }
+
+    rename(&partial_path, path)
+        .await
+        .map_err(|err| download_error(path, err))?;
+    let _ = remove_file(state_path(path)).await;
+
+    Ok(Download {
+        path: path.to_path_buf(),
+        status_code,
+        length: resumed_from + received,
+        resumed_from,
+    })
+}
+
+/// Removes any partial download, so the next attempt starts over.
+async fn clear_partial_download(path: &Path) {
+    let _ = remove_file(partial_path(path)).await;
+    let _ = remove_file(state_path(path)).await;
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    path_with_suffix(path, ".part")
+}
+
+fn state_path(path: &Path) -> PathBuf {
+    path_with_suffix(path, ".part.json")
+}
+
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path_text = OsString::from(path.as_os_str());
+    path_text.push(suffix);
+    PathBuf::from(path_text)
+}
+
+fn header_text(headers: &HeaderMap, name: &header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// Returns the first byte from a `Content-Range` header, such as `bytes 100-199/200`.
+fn parse_content_range_start(content_range: &str) -> Option<u64> {
+    let range = content_range.trim().strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+fn download_error<E>(path: &Path, err: E) -> Error
+where
+    E: Into<BoxError>,
+{
+    Error::Download {
+        path: path.to_path_buf(),
+        source: err.into(),
+    }
+}
+
+#[cfg(test)]
+mod test_parse_content_range_start {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_the_start_of_the_range() {
+        assert_eq!(parse_content_range_start("bytes 100-199/200"), Some(100));
+        assert_eq!(parse_content_range_start("bytes 0-9/*"), Some(0));
+    }
+
+    #[test]
+    fn it_should_not_parse_other_units() {
+        assert_eq!(parse_content_range_start("items 0-9/10"), None);
+        assert_eq!(parse_content_range_start("bytes */200"), None);
+    }
+}
@@ -0,0 +1,243 @@
+use ::anyhow::Result;
+use ::hyper::body::Bytes;
+use ::hyper::http::HeaderValue;
+
+#[cfg(feature = "brotli")]
+use ::async_compression::tokio::bufread::BrotliDecoder;
+#[cfg(feature = "brotli")]
+use ::async_compression::tokio::bufread::BrotliEncoder;
+#[cfg(feature = "gzip")]
+use ::async_compression::tokio::bufread::GzipDecoder;
+#[cfg(feature = "gzip")]
+use ::async_compression::tokio::bufread::GzipEncoder;
+#[cfg(feature = "deflate")]
+use ::async_compression::tokio::bufread::ZlibDecoder;
+#[cfg(feature = "deflate")]
+use ::async_compression::tokio::bufread::ZlibEncoder;
+#[cfg(feature = "zstd")]
+use ::async_compression::tokio::bufread::ZstdDecoder;
+#[cfg(feature = "zstd")]
+use ::async_compression::tokio::bufread::ZstdEncoder;
+
+/// A content coding used to compress the body of a request or response.
+///
+/// Each encoding is only available when its cargo feature is turned on.
+/// `gzip` and `deflate` are on by default, and `brotli` and `zstd` can be added.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `gzip`, from the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    Gzip,
+
+    /// `deflate`, from the `deflate` feature.
+    /// This is zlib wrapped deflate data, as the HTTP spec describes.
+    #[cfg(feature = "deflate")]
+    Deflate,
+
+    /// `br`, from the `brotli` feature.
+    #[cfg(feature = "brotli")]
+    Brotli,
+
+    /// `zstd`, from the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    /// All of the encodings turned on, in order of preference.
+    const ENABLED: &'static [Encoding] = &[
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd,
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli,
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip,
+        #[cfg(feature = "deflate")]
+        Encoding::Deflate,
+    ];
+
+    /// The name used for this encoding in `Accept-Encoding` and `Content-Encoding` headers.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Self::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            Self::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Finds the encoding with the name given, if it is turned on.
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ENABLED
+            .iter()
+            .copied()
+            .find(|encoding| encoding.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// Decompresses the bytes given.
+    ///
+    /// This fails if the decompressed data would be larger than `max_size`.
+    #[cfg_attr(
+        not(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        )),
+        allow(unused_variables)
+    )]
+    async fn decode(self, bytes: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => read_decoded(self, GzipDecoder::new(bytes), max_size).await,
+            #[cfg(feature = "deflate")]
+            Self::Deflate => read_decoded(self, ZlibDecoder::new(bytes), max_size).await,
+            #[cfg(feature = "brotli")]
+            Self::Brotli => read_decoded(self, BrotliDecoder::new(bytes), max_size).await,
+            #[cfg(feature = "zstd")]
+            Self::Zstd => read_decoded(self, ZstdDecoder::new(bytes), max_size).await,
+        }
+    }
+
+    /// Compresses the bytes given.
+    #[cfg_attr(
+        not(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        )),
+        allow(unused_variables)
+    )]
+    pub(crate) async fn encode(self, bytes: &[u8]) -> Result<Bytes> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => read_encoded(self, GzipEncoder::new(bytes)).await,
+            #[cfg(feature = "deflate")]
+            Self::Deflate => read_encoded(self, ZlibEncoder::new(bytes)).await,
+            #[cfg(feature = "brotli")]
+            Self::Brotli => read_encoded(self, BrotliEncoder::new(bytes)).await,
+            #[cfg(feature = "zstd")]
+            Self::Zstd => read_encoded(self, ZstdEncoder::new(bytes)).await,
+        }
+    }
+}
+
+/// Reads all of the compressed data from the encoder given.
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+async fn read_encoded<R>(encoding: Encoding, mut encoder: R) -> Result<Bytes>
+where
+    R: ::tokio::io::AsyncRead + Unpin,
+{
+    use ::anyhow::anyhow;
+    use ::tokio::io::AsyncReadExt;
+
+    let mut encoded = vec![];
+    encoder
+        .read_to_end(&mut encoded)
+        .await
+        .map_err(|err| anyhow!("Failed to encode {} body, {}", encoding.as_str(), err))?;
+
+    Ok(Bytes::from(encoded))
+}
+
+/// Reads all of the decompressed data from the decoder given.
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+async fn read_decoded<R>(encoding: Encoding, decoder: R, max_size: usize) -> Result<Vec<u8>>
+where
+    R: ::tokio::io::AsyncRead + Unpin,
+{
+    use ::anyhow::anyhow;
+    use ::tokio::io::AsyncReadExt;
+
+    // One byte more than the limit is read, to spot when it has been exceeded.
+    let limit = (max_size as u64).saturating_add(1);
+    let mut decoded = vec![];
+    decoder
+        .take(limit)
+        .read_to_end(&mut decoded)
+        .await
+        .map_err(|err| anyhow!("Failed to decode {} body, {}", encoding.as_str(), err))?;
+
+    if decoded.len() > max_size {
+        return Err(anyhow!(
+            "Decoded {} body is larger than the limit of {} bytes",
+            encoding.as_str(),
+            max_size
+        ));
+    }
+
+    Ok(decoded)
+}
+
+/// Returns the `Accept-Encoding` header advertising the encodings turned on.
+///
+/// `None` is returned when no encodings are turned on.
+pub(crate) fn accept_encoding_header() -> Option<HeaderValue> {
+    if Encoding::ENABLED.is_empty() {
+        return None;
+    }
+
+    let names = Encoding::ENABLED
+        .iter()
+        .map(|encoding| encoding.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    HeaderValue::from_str(&names).ok()
+}
+
+/// Decodes a response body sent using the `Content-Encoding` given.
+///
+/// When multiple encodings are listed, they are undone in reverse order.
+/// `None` is returned when the body is not encoded,
+/// or it uses an encoding which is not turned on. Such bodies are left as they are.
+pub(crate) async fn decode_body(
+    body: &Bytes,
+    content_encoding: &HeaderValue,
+    max_size: usize,
+) -> Result<Option<Bytes>> {
+    let Ok(content_encoding) = content_encoding.to_str() else {
+        return Ok(None);
+    };
+
+    let mut encodings = vec![];
+    for name in content_encoding.split(',').map(str::trim) {
+        if name.is_empty() || name.eq_ignore_ascii_case("identity") {
+            continue;
+        }
+
+        match Encoding::from_name(name) {
+            Some(encoding) => encodings.push(encoding),
+            None => return Ok(None),
+        }
+    }
+
+    // Such as from a `HEAD` request, where no body is sent.
+    if encodings.is_empty() || body.is_empty() {
+        return Ok(None);
+    }
+
+    let mut decoded = body.clone();
+    for encoding in encodings.into_iter().rev() {
+        decoded = Bytes::from(encoding.decode(&decoded, max_size).await?);
+    }
+
+    Ok(Some(decoded))
+}
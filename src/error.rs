@@ -0,0 +1,267 @@
+use ::hyper::http::Method;
+use ::hyper::http::StatusCode;
+use ::hyper::Uri;
+use ::std::error::Error as StdError;
+use ::std::fmt::Display;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+use ::std::path::PathBuf;
+
+use crate::ExpectedStatus;
+use crate::Response;
+use crate::TimeoutError;
+
+/// How much of the response body is shown when displaying an `Error::Status`.
+const BODY_EXCERPT_LEN: usize = 1024;
+
+/// The boxed error used as the source of an `Error`.
+pub type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// The errors returned by the non-panicking functions,
+/// such as `Request::try_send` and `Response::try_json`.
+///
+/// The panicking versions (such as awaiting a `Request`)
+/// will panic with the message of these errors.
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be built, such as from an invalid path.
+    InvalidRequest {
+        method: Method,
+        request_path: String,
+        source: BoxError,
+    },
+
+    /// A header could not be built for the request,
+    /// or a header in the response could not be read.
+    InvalidHeader { name: String, source: BoxError },
+
+    /// Connecting to the server, or sending the request, failed.
+    Connect {
+        method: Method,
+        request_path: Uri,
+        source: hyper::Error,
+    },
+
+    /// The request ran out of time.
+    Timeout(Box<TimeoutError>),
+
+    /// Reading the body of the response failed.
+    BodyRead {
+        method: Method,
+        request_path: Uri,
+        source: hyper::Error,
+    },
+
+    /// The body of the response could not be deserialized.
+    Decode { request_path: Uri, source: BoxError },
+
+    /// The body of the response could not be decompressed,
+    /// or it was larger than the maximum decompressed size.
+    Decompress { request_path: Uri, source: BoxError },
+
+    /// The response returned a status code which was not expected.
+    ///
+    /// The whole response is included, for inspecting what went wrong.
+    Status {
+        expected: ExpectedStatus,
+        response: Box<Response>,
+    },
+
+    /// More redirects were returned than the `RedirectPolicy` allows.
+    TooManyRedirects {
+        method: Method,
+        request_path: Uri,
+        max_redirects: usize,
+    },
+
+    /// A header was expected in the response, but it was not found.
+    MissingHeader { name: String, request_path: Uri },
+
+    /// A cookie was expected in the response, but it was not found.
+    MissingCookie { name: String, request_path: Uri },
+
+    /// An `AuthProvider` failed to provide credentials for the request,
+    /// such as when fetching an OAuth2 token fails.
+    Auth { request_path: Uri, source: BoxError },
+
+    /// Saving or loading a cookies file failed.
+    CookieFile { path: PathBuf, source: BoxError },
+
+    /// Downloading to a file failed, from `Request::download_to`.
+    ///
+    /// Any partial download is kept where possible, to be resumed by a later attempt.
+    Download { path: PathBuf, source: BoxError },
+
+    /// An unexpected failure within the `Server`.
+    Internal(anyhow::Error),
+}
+
+impl Error {
+    /// Returns the `TimeoutError`, if this is a timeout.
+    #[must_use]
+    pub fn as_timeout(&self) -> Option<&TimeoutError> {
+        match self {
+            Self::Timeout(timeout_error) => Some(timeout_error.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this error is from the request running out of time.
+    #[must_use]
+    pub fn is_timeout(&self) -> bool {
+        self.as_timeout().is_some()
+    }
+
+    /// Returns the status code, if this is from an unexpected status code.
+    #[must_use]
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            Self::Status { response, .. } => Some(response.status_code()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::InvalidRequest {
+                method,
+                request_path,
+                source,
+            } => write!(
+                f,
+                "Failed to build request for {} {}, {}",
+                method, request_path, source
+            ),
+            Self::InvalidHeader { name, source } => {
+                write!(f, "Invalid header '{}', {}", name, source)
+            }
+            Self::Connect {
+                method,
+                request_path,
+                source,
+            } => write!(
+                f,
+                "Failed to send request for {} {}, {}",
+                method, request_path, source
+            ),
+            Self::Timeout(timeout_error) => write!(f, "{}", timeout_error),
+            Self::BodyRead {
+                method,
+                request_path,
+                source,
+            } => write!(
+                f,
+                "Failed to read response body for {} {}, {}",
+                method, request_path, source
+            ),
+            Self::Decode {
+                request_path,
+                source,
+            } => write!(
+                f,
+                "Failed to deserialize response for request {}, {}",
+                request_path, source
+            ),
+            Self::Decompress {
+                request_path,
+                source,
+            } => write!(
+                f,
+                "Failed to decompress response for request {}, {}",
+                request_path, source
+            ),
+            Self::Status { expected, response } => {
+                let body_text = response.text();
+                let body_excerpt = match body_text.char_indices().nth(BODY_EXCERPT_LEN) {
+                    Some((index, _)) => format!("{}...", &body_text[..index]),
+                    None => body_text,
+                };
+
+                write!(
+                    f,
+                    "Expected {}, received {}, for {} {}\n\nResponse body:\n{}",
+                    expected,
+                    response.status_code(),
+                    response.request_method(),
+                    response.request_uri(),
+                    body_excerpt
+                )
+            }
+            Self::TooManyRedirects {
+                method,
+                request_path,
+                max_redirects,
+            } => write!(
+                f,
+                "Too many redirects for {} {}, the maximum is {}",
+                method, request_path, max_redirects
+            ),
+            Self::MissingHeader { name, request_path } => write!(
+                f,
+                "Cannot find header {} for response {}",
+                name, request_path
+            ),
+            Self::MissingCookie { name, request_path } => write!(
+                f,
+                "Cannot find cookie {} for response {}",
+                name, request_path
+            ),
+            Self::Auth {
+                request_path,
+                source,
+            } => write!(
+                f,
+                "Failed to authorize request {}, {}",
+                request_path, source
+            ),
+            Self::CookieFile { path, source } => {
+                write!(
+                    f,
+                    "Failed to use cookie file {}, {}",
+                    path.display(),
+                    source
+                )
+            }
+            Self::Download { path, source } => {
+                write!(f, "Failed to download to {}, {}", path.display(), source)
+            }
+            Self::Internal(source) => write!(f, "{:#}", source),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::InvalidRequest { source, .. } => Some(source.as_ref()),
+            Self::InvalidHeader { source, .. } => Some(source.as_ref()),
+            Self::Connect { source, .. } => Some(source),
+            Self::Timeout(timeout_error) => Some(timeout_error.as_ref()),
+            Self::BodyRead { source, .. } => Some(source),
+            Self::Decode { source, .. } => Some(source.as_ref()),
+            Self::Decompress { source, .. } => Some(source.as_ref()),
+            Self::Status { .. } => None,
+            Self::TooManyRedirects { .. } => None,
+            Self::MissingHeader { .. } => None,
+            Self::MissingCookie { .. } => None,
+            Self::Auth { source, .. } => Some(source.as_ref()),
+            Self::CookieFile { source, .. } => Some(source.as_ref()),
+            Self::Download { source, .. } => Some(source.as_ref()),
+            Self::Internal(source) => Some(source.as_ref()),
+        }
+    }
+}
+
+impl From<TimeoutError> for Error {
+    fn from(timeout_error: TimeoutError) -> Self {
+        Self::Timeout(Box::new(timeout_error))
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(source: anyhow::Error) -> Self {
+        Self::Internal(source)
+    }
+}
@@ -0,0 +1,42 @@
+use ::hyper::http::StatusCode;
+use ::std::fmt::Display;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+
+/// The status code a `Request` expects to receive back.
+///
+/// When the response does not match, awaiting the `Request` will panic,
+/// and `Request::try_send` will return an `Error::Status`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExpectedStatus {
+    /// Expects a successful status code, in the 2xx range.
+    Success,
+
+    /// Expects a status code outside of the 2xx range.
+    Failure,
+
+    /// Expects this exact status code.
+    Code(StatusCode),
+}
+
+impl ExpectedStatus {
+    /// Returns true if the status code given matches this expectation.
+    #[must_use]
+    pub fn matches(&self, status_code: StatusCode) -> bool {
+        match self {
+            Self::Success => status_code.is_success(),
+            Self::Failure => !status_code.is_success(),
+            Self::Code(expected_code) => *expected_code == status_code,
+        }
+    }
+}
+
+impl Display for ExpectedStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Success => write!(f, "a successful status code"),
+            Self::Failure => write!(f, "a failing status code"),
+            Self::Code(expected_code) => write!(f, "status code {}", expected_code),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 //!
-//! Kantan is a library for writing responses to servers.
+//! Kantan is a library for making requests to servers.
 //!
-//!  * You can spin up a `Server` within a test.
+//!  * Create a `Server` pointing at the address you want to talk to.
 //!  * Create requests that will run against that.
 //!  * Retrieve what they happen to return.
 //!  * Assert that the response works how you expect.
@@ -11,27 +11,30 @@
 //!
 //! ## Getting Started
 //!
-//! In essence; create your Axum application, create a `Server`,
+//! In essence; create a `Server` with the address of your service,
 //! and then make requests against it.
 //!
 //! ```rust
 //! # ::tokio_test::block_on(async {
-//! use ::axum::Router;
-//! use ::axum::extract::Json;
-//! use ::axum::routing::put;
-//! use ::axum_test::Server;
+//! # use ::axum::Router;
+//! # use ::axum::extract::Json;
+//! # use ::axum::routing::put;
+//! # use ::axum_test::TestServer;
+//! # use ::serde_json::Value;
+//! #
+//! # async fn put_user(Json(user): Json<Value>) -> () {
+//! # }
+//! #
+//! # let my_app = Router::new()
+//! #     .route("/users", put(put_user))
+//! #     .into_make_service();
+//! # let test_server = TestServer::new(my_app).unwrap();
+//! # let server_address = test_server.server_address();
+//! #
+//! use ::kantan::Server;
 //! use ::serde_json::json;
-//! use ::serde_json::Value;
-//!
-//! async fn put_user(Json(user): Json<Value>) -> () {
-//!     // todo
-//! }
 //!
-//! let my_app = Router::new()
-//!     .route("/users", put(put_user))
-//!     .into_make_service();
-//!
-//! let server = Server::new(my_app)
+//! let server = Server::new(server_address)
 //!     .unwrap();
 //!
 //! let response = server.put("/users")
@@ -50,28 +53,63 @@
 //! across requests. This is used for automatically saving things like session cookies.
 //!
 //! ```rust
-//! # ::tokio_test::block_on(async {
-//! use ::axum::Router;
-//! use ::axum_test::Server;
-//! use ::axum_test::ServerConfig;
-//!
-//! let my_app = Router::new()
-//!     .into_make_service();
+//! use ::kantan::Server;
+//! use ::kantan::ServerConfig;
 //!
 //! let config = ServerConfig {
 //!     save_cookies: true,
 //!     ..ServerConfig::default()
 //! };
-//! let server = Server::new_with_config(my_app, config)
+//! let server = Server::new_with_config("http://localhost:3000".to_string(), config)
 //!     .unwrap();
-//! # })
 //! ```
 //!
 //! Then when you make a request, any cookies that are returned will be reused
 //! by the next request. This is on a per server basis (it doesn't save across servers).
 //!
-//! You can turn this on or off per request, using `Request::do_save_cookies'
-//! and Request::do_not_save_cookies'.
+//! You can turn this on or off per request, using `Request::do_save_cookies`
+//! and `Request::do_not_save_cookies`.
+//!
+//! Cookies are held in a `MemoryCookieStore` by default.
+//! Your own store can be used instead by implementing the `CookieStore` trait,
+//! and passing it to `ServerConfig::cookie_store`.
+//!
+//! The cookies can also be kept between runs, using `Server::save_cookies_to`
+//! and `Server::load_cookies_from`. These support JSON and the Netscape `cookies.txt` format.
+//!
+//! ```rust,no_run
+//! use ::kantan::CookieFileFormat;
+//! use ::kantan::Server;
+//!
+//! let mut server = Server::new("http://localhost:3000".to_string())
+//!     .unwrap();
+//! server.load_cookies_from("cookies.txt", CookieFileFormat::Netscape)
+//!     .unwrap();
+//! ```
+//!
+//! ### Server Defaults ⚙️
+//!
+//! The `ServerConfig` holds defaults used by every `Request` the `Server` creates.
+//! Such as headers, query parameters, and the user agent.
+//! It can be built one option at a time using `ServerConfig::builder()`.
+//!
+//! ```rust
+//! use ::kantan::http::header::ACCEPT;
+//! use ::kantan::http::HeaderValue;
+//! use ::kantan::Server;
+//! use ::kantan::ServerConfig;
+//!
+//! let config = ServerConfig::builder()
+//!     .add_default_header(ACCEPT, HeaderValue::from_static("application/json"))
+//!     .add_default_query_param("api-version", 2)
+//!     .user_agent("my-app/1.0")
+//!     .build();
+//!
+//! let server = Server::new_with_config("http://localhost:3000".to_string(), config)
+//!     .unwrap();
+//! ```
+//!
+//! Each `Request` can then override these, such as with `Request::user_agent`.
 //!
 //! ### Content Type 📇
 //!
@@ -82,26 +120,21 @@
 //! When creating the `Server` instance, using `new_with_config`.
 //!
 //! ```rust
-//! # ::tokio_test::block_on(async {
-//! use ::axum::Router;
-//! use ::axum_test::Server;
-//! use ::axum_test::ServerConfig;
-//!
-//! let my_app = Router::new()
-//!     .into_make_service();
+//! use ::kantan::Server;
+//! use ::kantan::ServerConfig;
 //!
 //! let config = ServerConfig {
 //!     default_content_type: Some("application/json".to_string()),
 //!     ..ServerConfig::default()
 //! };
 //!
-//! let server = Server::new_with_config(my_app, config)
+//! let server = Server::new_with_config("http://localhost:3000".to_string(), config)
 //!     .unwrap();
-//! # })
 //! ```
 //!
 //! If there is no default, then a `Request` will try to guess the content type.
 //! Such as setting `application/json` when calling `Request::json`,
+//! `application/x-www-form-urlencoded` when calling `Request::form`,
 //! and `text/plain` when calling `Request::text`.
 //! This will never override any default content type provided.
 //!
@@ -110,22 +143,25 @@
 //!
 //! ```rust
 //! # ::tokio_test::block_on(async {
-//! use ::axum::Router;
-//! use ::axum::extract::Json;
-//! use ::axum::routing::put;
+//! # use ::axum::Router;
+//! # use ::axum::extract::Json;
+//! # use ::axum::routing::put;
+//! # use ::axum_test::TestServer;
+//! # use ::serde_json::Value;
+//! #
+//! # async fn put_user(Json(user): Json<Value>) -> () {
+//! # }
+//! #
+//! # let my_app = Router::new()
+//! #     .route("/users", put(put_user))
+//! #     .into_make_service();
+//! # let test_server = TestServer::new(my_app).unwrap();
+//! # let server_address = test_server.server_address();
+//! #
 //! use ::kantan::Server;
 //! use ::serde_json::json;
-//! use ::serde_json::Value;
-//!
-//! async fn put_user(Json(user): Json<Value>) -> () {
-//!     // todo
-//! }
 //!
-//! let my_app = Router::new()
-//!     .route("/users", put(put_user))
-//!     .into_make_service();
-//!
-//! let server = Server::new(my_app)
+//! let server = Server::new(server_address)
 //!     .unwrap();
 //!
 //! let response = server.put("/users")
@@ -137,25 +173,132 @@
 //! # })
 //! ```
 //!
+//! ### Compression 🗜️
+//!
+//! Requests send an `Accept-Encoding` header listing the encodings turned on,
+//! and responses using them are decompressed when read into a `Response`.
+//! Each is behind a cargo feature; `gzip` and `deflate` are on by default,
+//! and `brotli` and `zstd` can be turned on.
+//!
+//! The body as it was received is still available, using `Response::raw_bytes`.
+//! To guard against small responses which decompress into huge ones,
+//! bodies larger than `ServerConfig::max_decompressed_size` fail with an `Error::Decompress`.
+//!
+//! Request bodies can be compressed too, using `Request::compress`,
+//! or `ServerConfig::compression` for every request.
+//! Bodies below `ServerConfig::compression_threshold` are sent uncompressed.
+//!
+//! ### Retries 🔁
+//!
+//! Failed requests can be sent again using a `RetryPolicy`,
+//! set on the `ServerConfig` or on each `Request` with `Request::retry_policy`.
+//! Retries back off exponentially with jitter, and honour any `Retry-After` header.
+//!
+//! ### Middleware 🧅
+//!
+//! Implement the `Middleware` trait to run code around sending every request,
+//! such as for authentication, logging, or metrics.
+//! They are added using `ServerConfigBuilder::add_middleware` or `Server::add_middleware`,
+//! and run in the order they are added.
+//!
+//! ### Authentication 🔑
+//!
+//! Credentials can be sent using `bearer_auth`, `basic_auth`, and `digest_auth`,
+//! on a `Request` or for the whole `Server`.
+//! For credentials which expire, or which are worked out from each request,
+//! set an `AuthProvider` using `ServerConfigBuilder::auth_provider`.
+//! `OAuth2Provider` fetches and renews OAuth2 tokens from a token endpoint,
+//! and `SigV4Provider` signs requests for AWS and S3 compatible storage.
+//! Providers run after any middleware, so signatures cover the changes they make.
+//!
 //! ### Fail Fast
 //!
-//! This library is written to panic quickly. For example by default a response will presume to
-//! succeed and will panic if they don't (which you can change).
+//! This library is written to panic quickly. For example a request can be set to presume it will
+//! succeed, and will panic if it doesn't (using `Request::expect_success`, or `ServerConfig::expected_status`).
 //! Functions to retreive cookies and headers will by default panic if they aren't found.
 //!
 //! This behaviour is unorthodox for Rust, however it is intentional to aid with writing tests.
 //! Where you want the test to fail as quickly, and skip on writing error handling code.
 //!
+//! For use outside of tests, there are non-panicking versions which return an `Error`.
+//! Such as `Request::try_send`, `Response::try_json`, and `Response::try_header`.
+//!
+//! ```rust
+//! # ::tokio_test::block_on(async {
+//! use ::kantan::Error;
+//! use ::kantan::Server;
+//!
+//! let server = Server::new("http://127.0.0.1:1".to_string())
+//!     .unwrap();
+//!
+//! let result = server.get("/users").try_send().await;
+//! assert!(matches!(result, Err(Error::Connect { .. })));
+//! # })
+//! ```
+//!
+
+#![allow(clippy::double_must_use)]
+#![allow(clippy::needless_borrow)]
+#![allow(clippy::needless_borrows_for_generic_args)]
+#![allow(clippy::needless_lifetimes)]
+#![allow(clippy::partialeq_to_none)]
+#![allow(clippy::redundant_static_lifetimes)]
+#![allow(clippy::useless_format)]
 
 mod server;
 pub use self::server::*;
 
+mod cookie_store;
+pub use self::cookie_store::*;
+
 mod request;
 pub use self::request::*;
 
+mod middleware;
+pub use self::middleware::*;
+
+mod authorization;
+pub use self::authorization::*;
+
+mod auth_provider;
+pub use self::auth_provider::*;
+
+mod download;
+pub use self::download::*;
+
+mod multipart;
+pub use self::multipart::*;
+
+mod redirect;
+pub use self::redirect::*;
+
+mod retry_policy;
+pub use self::retry_policy::*;
+
+mod encoding;
+pub use self::encoding::*;
+
 mod response;
 pub use self::response::*;
 
+mod streaming_response;
+pub use self::streaming_response::*;
+
+mod error;
+pub use self::error::*;
+
+mod expected_status;
+pub use self::expected_status::*;
+
+mod timeout_error;
+pub use self::timeout_error::*;
+
+mod random;
+pub(crate) use self::random::*;
+
+#[cfg(test)]
+mod test_support;
+
 pub use ::hyper::http;
 
 #[cfg(test)]
@@ -308,6 +451,29 @@ mod test_cookies {
         assert_eq!(response_text, "cookie-not-found");
     }
 
+    #[tokio::test]
+    async fn it_should_pass_cookies_created_back_up_to_server_when_turned_on_for_server() {
+        // Build an application with a route.
+        let app = Router::new()
+            .route("/cookie", put(put_cookie))
+            .route("/cookie", get(get_cookie))
+            .into_make_service();
+
+        // Run the server.
+        let test_server = TestServer::new(app).expect("Should create test server");
+        let server_address = test_server.server_address();
+
+        // Create a cookie.
+        let config = ServerConfig::builder().save_cookies().build();
+        let server = Server::new_with_config(server_address, config).expect("Should create server");
+        server.put(&"/cookie").text(&"cookie-found!").await;
+
+        // Check it comes back.
+        let response_text = server.get(&"/cookie").await.text();
+
+        assert_eq!(response_text, "cookie-found!");
+    }
+
     #[tokio::test]
     async fn it_should_pass_cookies_created_back_up_to_server_when_turned_on_for_request() {
         // Build an application with a route.
@@ -334,3 +500,3957 @@ mod test_cookies {
         assert_eq!(response_text, "cookie-found!");
     }
 }
+
+#[cfg(test)]
+mod test_connection_pool {
+    use super::*;
+
+    use ::axum::extract::ConnectInfo;
+    use ::axum::http::StatusCode;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::std::net::SocketAddr;
+    use ::std::net::TcpListener;
+    use ::std::time::Duration;
+
+    async fn get_client_port(ConnectInfo(address): ConnectInfo<SocketAddr>) -> String {
+        address.port().to_string()
+    }
+
+    /// Fails with the client port, followed by a body too large to be read with the headers.
+    async fn get_unavailable_client_port(
+        ConnectInfo(address): ConnectInfo<SocketAddr>,
+    ) -> (StatusCode, String) {
+        let body = format!("{}\n{}", address.port(), "x".repeat(32 * 1024));
+        (StatusCode::SERVICE_UNAVAILABLE, body)
+    }
+
+    /// Fails with the client port, followed by a body too large to be read when retrying.
+    async fn get_unavailable_client_port_with_large_body(
+        ConnectInfo(address): ConnectInfo<SocketAddr>,
+    ) -> (StatusCode, String) {
+        let body = format!("{}\n{}", address.port(), "x".repeat(1024 * 1024));
+        (StatusCode::SERVICE_UNAVAILABLE, body)
+    }
+
+    /// Runs the app with the connection info available to handlers,
+    /// returning the address of the server.
+    fn spawn_app() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind to a port");
+        let address = listener.local_addr().expect("Should read bound address");
+        let app = Router::new()
+            .route("/port", get(get_client_port))
+            .route("/unavailable_port", get(get_unavailable_client_port))
+            .route(
+                "/unavailable_port_with_large_body",
+                get(get_unavailable_client_port_with_large_body),
+            )
+            .into_make_service_with_connect_info::<SocketAddr>();
+
+        let server = ::axum::Server::from_tcp(listener)
+            .expect("Should create server from listener")
+            .serve(app);
+        ::tokio::spawn(server);
+
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn it_should_reuse_connections_across_requests() {
+        let server = Server::new(spawn_app()).expect("Should create server");
+
+        let first_port = server.get("/port").await.text();
+        for _ in 0..10 {
+            let port = server.get("/port").await.text();
+            assert_eq!(port, first_port);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_not_reuse_connections_when_max_idle_is_zero() {
+        let config = ServerConfig {
+            pool_max_idle_per_host: 0,
+            ..ServerConfig::default()
+        };
+        let server = Server::new_with_config(spawn_app(), config).expect("Should create server");
+
+        let first_port = server.get("/port").await.text();
+        let second_port = server.get("/port").await.text();
+
+        assert_ne!(first_port, second_port);
+    }
+
+    #[tokio::test]
+    async fn it_should_reuse_connections_with_a_request_connect_timeout() {
+        let server = Server::new(spawn_app()).expect("Should create server");
+
+        let first_port = server
+            .get("/port")
+            .connect_timeout(Duration::from_secs(5))
+            .await
+            .text();
+        for _ in 0..10 {
+            let port = server
+                .get("/port")
+                .connect_timeout(Duration::from_secs(5))
+                .await
+                .text();
+            assert_eq!(port, first_port);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_drop_the_least_recently_used_connect_timeout_client() {
+        let server = Server::new(spawn_app()).expect("Should create server");
+
+        let first_port = server
+            .get("/port")
+            .connect_timeout(Duration::from_secs(1))
+            .await
+            .text();
+        for seconds in 2..=5 {
+            server
+                .get("/port")
+                .connect_timeout(Duration::from_secs(seconds))
+                .await;
+        }
+        let last_port = server
+            .get("/port")
+            .connect_timeout(Duration::from_secs(1))
+            .await
+            .text();
+
+        assert_ne!(last_port, first_port);
+    }
+
+    #[tokio::test]
+    async fn it_should_reuse_connections_when_retrying_responses() {
+        let server = Server::new(spawn_app()).expect("Should create server");
+        let retry_policy =
+            RetryPolicy::new(3).backoff(Duration::from_millis(1), Duration::from_millis(1));
+
+        let first_port = server.get("/port").await.text();
+        let last_retry_port = server
+            .get("/unavailable_port")
+            .retry_policy(retry_policy)
+            .expect_failure()
+            .await
+            .text();
+
+        assert_eq!(last_retry_port.lines().next(), Some(first_port.as_str()));
+    }
+
+    #[tokio::test]
+    async fn it_should_not_reuse_connections_when_retrying_responses_with_large_bodies() {
+        let server = Server::new(spawn_app()).expect("Should create server");
+        let retry_policy =
+            RetryPolicy::new(3).backoff(Duration::from_millis(1), Duration::from_millis(1));
+
+        let first_port = server.get("/port").await.text();
+        let last_retry_port = server
+            .get("/unavailable_port_with_large_body")
+            .retry_policy(retry_policy)
+            .expect_failure()
+            .await
+            .text();
+
+        assert_ne!(last_retry_port.lines().next(), Some(first_port.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod test_server_config {
+    use super::*;
+
+    use ::axum::extract::RawQuery;
+    use ::axum::http::header::CONTENT_TYPE;
+    use ::axum::http::header::USER_AGENT;
+    use ::axum::http::HeaderMap;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::hyper::header::HeaderName;
+    use ::hyper::header::HeaderValue;
+
+    use crate::test_support::new_test_server;
+
+    const TEST_HEADER_NAME: &str = "x-test-header";
+
+    async fn get_header(headers: HeaderMap) -> String {
+        headers
+            .get(TEST_HEADER_NAME)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_else(|| "header-not-found".to_string())
+    }
+
+    async fn get_user_agent(headers: HeaderMap) -> String {
+        headers
+            .get(USER_AGENT)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_else(|| "".to_string())
+    }
+
+    async fn get_content_type(headers: HeaderMap) -> String {
+        headers
+            .get(CONTENT_TYPE)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_else(|| "".to_string())
+    }
+
+    async fn get_query(RawQuery(query): RawQuery) -> String {
+        query.unwrap_or_default()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/header", get(get_header))
+            .route("/user_agent", get(get_user_agent))
+            .route("/content_type", get(get_content_type))
+            .route("/query", get(get_query))
+    }
+
+    #[tokio::test]
+    async fn it_should_send_default_headers() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_default_header(
+                HeaderName::from_static(TEST_HEADER_NAME),
+                HeaderValue::from_static("my-default"),
+            )
+            .build();
+
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        let text = server.get("/header").await.text();
+
+        assert_eq!(text, "my-default");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_default_user_agent() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder().user_agent("my-app/1.0").build();
+
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        let text = server.get("/user_agent").await.text();
+
+        assert_eq!(text, "my-app/1.0");
+    }
+
+    #[tokio::test]
+    async fn it_should_override_default_user_agent_on_request() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder().user_agent("my-app/1.0").build();
+
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        let text = server
+            .get("/user_agent")
+            .user_agent("other-app/2.0")
+            .await
+            .text();
+
+        assert_eq!(text, "other-app/2.0");
+    }
+
+    #[tokio::test]
+    async fn it_should_use_default_content_type_over_guessed_content_type() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .default_content_type("application/custom")
+            .build();
+
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        let text = server.get("/content_type").text("hello").await.text();
+
+        assert_eq!(text, "application/custom");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_default_query_params() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_default_query_param("api-version", 2)
+            .add_default_query_param("name", "Joe Bloggs")
+            .build();
+
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        let text = server.get("/query").await.text();
+
+        assert_eq!(text, "api-version=2&name=Joe+Bloggs");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_default_query_params_after_query_in_path() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_default_query_param("api-version", 2)
+            .build();
+
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        let text = server.get("/query?page=3").await.text();
+
+        assert_eq!(text, "page=3&api-version=2");
+    }
+}
+
+#[cfg(test)]
+mod test_timeouts {
+    use super::*;
+
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::std::time::Duration;
+    use ::tokio::time::sleep;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_slow() -> &'static str {
+        sleep(Duration::from_millis(500)).await;
+        "finally!"
+    }
+
+    fn router() -> Router {
+        Router::new().route("/slow", get(get_slow))
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Request timed out waiting for response headers after 50ms, for GET")]
+    async fn it_should_timeout_waiting_for_headers() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let _ = server
+            .get("/slow")
+            .headers_timeout(Duration::from_millis(50))
+            .await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Request timed out waiting for the response after 50ms, for GET")]
+    async fn it_should_timeout_using_total_timeout_from_server() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .total_timeout(Duration::from_millis(50))
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let _ = server.get("/slow").await;
+    }
+
+    #[tokio::test]
+    async fn it_should_override_server_timeout_on_request() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .headers_timeout(Duration::from_millis(50))
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let text = server
+            .get("/slow")
+            .headers_timeout(Duration::from_secs(5))
+            .await
+            .text();
+
+        assert_eq!(text, "finally!");
+    }
+
+    #[tokio::test]
+    async fn it_should_succeed_with_connect_timeout_override() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .get("/slow")
+            .connect_timeout(Duration::from_secs(5))
+            .await
+            .text();
+
+        assert_eq!(text, "finally!");
+    }
+}
+
+#[cfg(test)]
+mod test_try_send {
+    use super::*;
+
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::hyper::StatusCode;
+    use ::serde_json::Value;
+    use ::std::net::TcpListener;
+    use ::std::time::Duration;
+    use ::tokio::time::sleep;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_ping() -> &'static str {
+        "pong!"
+    }
+
+    async fn get_slow() -> &'static str {
+        sleep(Duration::from_millis(500)).await;
+        "finally!"
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/ping", get(get_ping))
+            .route("/slow", get(get_slow))
+    }
+
+    #[tokio::test]
+    async fn it_should_return_response_when_successful() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server
+            .get("/ping")
+            .try_send()
+            .await
+            .expect("Should send request");
+
+        assert_eq!(response.text(), "pong!");
+    }
+
+    #[tokio::test]
+    async fn it_should_return_timeout_error() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let err = server
+            .get("/slow")
+            .headers_timeout(Duration::from_millis(50))
+            .try_send()
+            .await
+            .expect_err("Should timeout");
+
+        let timeout_error = err.as_timeout().expect("Should be a timeout error");
+        assert_eq!(timeout_error.kind(), TimeoutKind::Headers);
+        assert_eq!(timeout_error.method(), ::hyper::Method::GET);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_connect_error_when_nothing_is_listening() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind to a port");
+        let address = listener.local_addr().expect("Should read bound address");
+        ::std::mem::drop(listener);
+
+        let server = Server::new(format!("http://{}", address)).expect("Should create server");
+        let err = server
+            .get("/ping")
+            .try_send()
+            .await
+            .expect_err("Should fail to connect");
+
+        assert!(matches!(err, Error::Connect { .. }));
+    }
+
+    #[tokio::test]
+    async fn it_should_return_decode_error_for_invalid_json() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server.get("/ping").await;
+        let err = response
+            .try_json::<Value>()
+            .expect_err("Should fail to decode");
+
+        assert!(matches!(err, Error::Decode { .. }));
+    }
+
+    #[tokio::test]
+    async fn it_should_return_missing_header_error() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server.get("/ping").await;
+        let err = response
+            .try_header("x-not-there")
+            .expect_err("Should not find header");
+
+        assert!(matches!(err, Error::MissingHeader { .. }));
+    }
+
+    #[tokio::test]
+    async fn it_should_return_status_error_for_not_found() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server.get("/not-found").await;
+        let err = response
+            .error_for_status()
+            .expect_err("Should be a status error");
+
+        assert_eq!(err.status_code(), Some(StatusCode::NOT_FOUND));
+    }
+}
+
+#[cfg(test)]
+mod test_expected_status {
+    use super::*;
+
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::hyper::StatusCode;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_ping() -> &'static str {
+        "pong!"
+    }
+
+    async fn get_teapot() -> (StatusCode, &'static str) {
+        (StatusCode::IM_A_TEAPOT, "I'm a teapot")
+    }
+
+    async fn get_broken() -> (StatusCode, &'static str) {
+        (StatusCode::INTERNAL_SERVER_ERROR, "it broke")
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/ping", get(get_ping))
+            .route("/teapot", get(get_teapot))
+            .route("/broken", get(get_broken))
+    }
+
+    #[tokio::test]
+    async fn it_should_accept_any_status_by_default() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server.get("/broken").await;
+
+        assert_eq!(response.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    #[should_panic(
+        expected = "Expected a successful status code, received 500 Internal Server Error, for GET"
+    )]
+    async fn it_should_panic_when_expecting_success_and_failing() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let _ = server.get("/broken").expect_success().await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Expected a failing status code, received 200 OK, for GET")]
+    async fn it_should_panic_when_expecting_failure_and_succeeding() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let _ = server.get("/ping").expect_failure().await;
+    }
+
+    #[tokio::test]
+    async fn it_should_pass_when_expected_status_matches() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .get("/teapot")
+            .expect_status(StatusCode::IM_A_TEAPOT)
+            .await
+            .text();
+
+        assert_eq!(text, "I'm a teapot");
+    }
+
+    #[tokio::test]
+    async fn it_should_override_server_expected_status_on_request() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder().expect_success().build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let response = server.get("/broken").expect_failure().await;
+
+        assert_eq!(response.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    #[should_panic(
+        expected = "Expected a successful status code, received 500 Internal Server Error"
+    )]
+    async fn it_should_panic_using_server_expected_status() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder().expect_success().build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let _ = server.get("/broken").await;
+    }
+
+    #[tokio::test]
+    async fn it_should_return_status_error_with_body_from_try_send() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let err = server
+            .get("/broken")
+            .expect_success()
+            .try_send()
+            .await
+            .expect_err("Should return status error");
+
+        assert_eq!(err.status_code(), Some(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(err.to_string().contains("it broke"));
+    }
+}
+
+#[cfg(test)]
+mod test_redirects {
+    use super::*;
+
+    use ::axum::extract::Path;
+    use ::axum::extract::RawBody;
+    use ::axum::http::Method;
+    use ::axum::response::Redirect as AxumRedirect;
+    use ::axum::routing::any;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::axum_extra::extract::cookie::Cookie as AxumCookie;
+    use ::axum_extra::extract::cookie::CookieJar;
+    use ::axum_test::TestServer;
+    use ::hyper::body::to_bytes;
+    use ::hyper::StatusCode;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_ping() -> &'static str {
+        "pong!"
+    }
+
+    async fn get_redirect_to_ping() -> AxumRedirect {
+        AxumRedirect::to("/ping")
+    }
+
+    async fn any_see_other() -> AxumRedirect {
+        AxumRedirect::to("/method")
+    }
+
+    async fn any_temporary() -> AxumRedirect {
+        AxumRedirect::temporary("/method")
+    }
+
+    async fn any_method(method: Method, RawBody(body): RawBody) -> String {
+        let body_bytes = to_bytes(body)
+            .await
+            .expect("Should turn the body into bytes");
+
+        format!("{} {}", method, String::from_utf8_lossy(&body_bytes))
+    }
+
+    async fn get_loop(Path(count): Path<u32>) -> AxumRedirect {
+        AxumRedirect::to(&format!("/loop/{}", count + 1))
+    }
+
+    async fn get_login(cookies: CookieJar) -> (CookieJar, AxumRedirect) {
+        let cookies = cookies.add(AxumCookie::new("session", "logged-in"));
+
+        (cookies, AxumRedirect::to("/session"))
+    }
+
+    async fn get_session(cookies: CookieJar) -> String {
+        cookies
+            .get("session")
+            .map(|c| c.value().to_string())
+            .unwrap_or_else(|| "no-session".to_string())
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/ping", get(get_ping))
+            .route("/redirect", get(get_redirect_to_ping))
+            .route("/see-other", any(any_see_other))
+            .route("/temporary", any(any_temporary))
+            .route("/method", any(any_method))
+            .route("/loop/:count", get(get_loop))
+            .route("/login", get(get_login))
+            .route("/session", get(get_session))
+    }
+
+    fn new_server(test_server: &TestServer, redirect_policy: RedirectPolicy) -> Server {
+        let config = ServerConfig::builder()
+            .redirect_policy(redirect_policy)
+            .build();
+
+        Server::new_with_config(test_server.server_address(), config).expect("Should create server")
+    }
+
+    #[tokio::test]
+    async fn it_should_not_follow_redirects_by_default() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server.get("/redirect").await;
+
+        assert_eq!(response.status_code(), StatusCode::SEE_OTHER);
+        assert!(response.redirect_history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_should_follow_redirects_when_turned_on() {
+        let test_server = new_test_server(router());
+        let server = new_server(&test_server, RedirectPolicy::Limited(5));
+
+        let response = server.get("/redirect").await;
+
+        assert_eq!(response.text(), "pong!");
+        assert_eq!(response.request_uri().path(), "/redirect");
+        assert_eq!(response.final_uri().path(), "/ping");
+
+        let history = response.redirect_history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status_code(), StatusCode::SEE_OTHER);
+        assert_eq!(history[0].request_uri().path(), "/redirect");
+        assert_eq!(history[0].location().path(), "/ping");
+    }
+
+    #[tokio::test]
+    async fn it_should_follow_redirects_set_on_request() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .get("/redirect")
+            .redirect_policy(RedirectPolicy::Limited(5))
+            .await
+            .text();
+
+        assert_eq!(text, "pong!");
+    }
+
+    #[tokio::test]
+    async fn it_should_switch_to_get_and_drop_body_on_see_other() {
+        let test_server = new_test_server(router());
+        let server = new_server(&test_server, RedirectPolicy::Limited(5));
+
+        let text = server.post("/see-other").text("my-body").await.text();
+
+        assert_eq!(text, "GET ");
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_method_and_body_on_temporary_redirect() {
+        let test_server = new_test_server(router());
+        let server = new_server(&test_server, RedirectPolicy::Limited(5));
+
+        let text = server.post("/temporary").text("my-body").await.text();
+
+        assert_eq!(text, "POST my-body");
+    }
+
+    #[tokio::test]
+    async fn it_should_error_when_over_the_redirect_limit() {
+        let test_server = new_test_server(router());
+        let server = new_server(&test_server, RedirectPolicy::Limited(3));
+
+        let err = server
+            .get("/loop/0")
+            .try_send()
+            .await
+            .expect_err("Should fail with too many redirects");
+
+        assert!(matches!(
+            err,
+            Error::TooManyRedirects {
+                max_redirects: 3,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_should_not_follow_redirects_to_other_origins_when_same_origin() {
+        let other_test_server = new_test_server(router());
+        let other_ping = format!("{}/ping", other_test_server.server_address());
+        let router = Router::new().route(
+            "/elsewhere",
+            get(move || async move { AxumRedirect::to(&other_ping) }),
+        );
+        let test_server = new_test_server(router);
+
+        let server = new_server(&test_server, RedirectPolicy::SameOrigin(5));
+        let response = server.get("/elsewhere").await;
+        assert_eq!(response.status_code(), StatusCode::SEE_OTHER);
+
+        let server = new_server(&test_server, RedirectPolicy::Limited(5));
+        let response = server.get("/elsewhere").await;
+        assert_eq!(response.text(), "pong!");
+    }
+
+    #[tokio::test]
+    async fn it_should_carry_saved_cookies_along_redirects() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .redirect_policy(RedirectPolicy::Limited(5))
+            .save_cookies()
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let text = server.get("/login").await.text();
+
+        assert_eq!(text, "logged-in");
+    }
+}
+
+#[cfg(test)]
+mod test_query {
+    use super::*;
+
+    use ::axum::extract::RawQuery;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::serde::Serialize;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_query(RawQuery(query): RawQuery) -> String {
+        query.unwrap_or_default()
+    }
+
+    fn router() -> Router {
+        Router::new().route("/query", get(get_query))
+    }
+
+    #[derive(Serialize)]
+    struct Search {
+        name: String,
+        page: u32,
+    }
+
+    #[tokio::test]
+    async fn it_should_send_query_from_struct() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .get("/query")
+            .query(&Search {
+                name: "Joe Bloggs & co".to_string(),
+                page: 2,
+            })
+            .await
+            .text();
+
+        assert_eq!(text, "name=Joe+Bloggs+%26+co&page=2");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_added_query_params() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .get("/query")
+            .add_query_param("q", "50% off?")
+            .add_query_param("page", 3)
+            .await
+            .text();
+
+        assert_eq!(text, "q=50%25+off%3F&page=3");
+    }
+
+    #[tokio::test]
+    async fn it_should_merge_with_query_in_path_and_server_defaults() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_default_query_param("api-version", 2)
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let text = server
+            .get("/query?sort=asc")
+            .add_query_param("page", 3)
+            .await
+            .text();
+
+        assert_eq!(text, "sort=asc&api-version=2&page=3");
+    }
+
+    #[tokio::test]
+    async fn it_should_override_server_default_with_same_key() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_default_query_param("api-version", 2)
+            .add_default_query_param("locale", "en")
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let text = server
+            .get("/query")
+            .add_query_param("api-version", 3)
+            .await
+            .text();
+
+        assert_eq!(text, "locale=en&api-version=3");
+    }
+
+    #[tokio::test]
+    async fn it_should_clear_query_params() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_default_query_param("api-version", 2)
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let text = server
+            .get("/query?sort=asc")
+            .add_query_param("page", 3)
+            .clear_query_params()
+            .await
+            .text();
+
+        assert_eq!(text, "sort=asc");
+    }
+}
+
+#[cfg(test)]
+mod test_headers {
+    use super::*;
+
+    use ::axum::http::header::CONTENT_TYPE;
+    use ::axum::http::HeaderMap;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::hyper::header::HeaderName;
+    use ::hyper::header::HeaderValue;
+
+    use crate::test_support::new_test_server;
+
+    const TEST_HEADER_NAME: &str = "x-test-header";
+
+    async fn get_test_headers(headers: HeaderMap) -> String {
+        headers
+            .get_all(TEST_HEADER_NAME)
+            .iter()
+            .map(|h| h.to_str().unwrap().to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    async fn get_content_type(headers: HeaderMap) -> String {
+        headers
+            .get(CONTENT_TYPE)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_else(|| "".to_string())
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/header", get(get_test_headers))
+            .route("/content_type", get(get_content_type))
+    }
+
+    fn test_header_name() -> HeaderName {
+        HeaderName::from_static(TEST_HEADER_NAME)
+    }
+
+    #[tokio::test]
+    async fn it_should_send_headers_added_to_request() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .get("/header")
+            .add_header(test_header_name(), HeaderValue::from_static("first"))
+            .add_header(test_header_name(), HeaderValue::from_static("second"))
+            .await
+            .text();
+
+        assert_eq!(text, "first,second");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_header_map_added_to_request() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let mut headers = HeaderMap::new();
+        headers.append(test_header_name(), HeaderValue::from_static("first"));
+        headers.append(test_header_name(), HeaderValue::from_static("second"));
+        let text = server.get("/header").headers(headers).await.text();
+
+        assert_eq!(text, "first,second");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_server_headers_on_all_requests() {
+        let test_server = new_test_server(router());
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server.add_header(test_header_name(), HeaderValue::from_static("from-server"));
+
+        let first_text = server.get("/header").await.text();
+        let second_text = server.get("/header").await.text();
+
+        assert_eq!(first_text, "from-server");
+        assert_eq!(second_text, "from-server");
+    }
+
+    #[tokio::test]
+    async fn it_should_replace_server_headers_with_request_headers() {
+        let test_server = new_test_server(router());
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server.add_header(test_header_name(), HeaderValue::from_static("from-server"));
+
+        let text = server
+            .get("/header")
+            .add_header(test_header_name(), HeaderValue::from_static("from-request"))
+            .await
+            .text();
+
+        assert_eq!(text, "from-request");
+    }
+
+    #[tokio::test]
+    async fn it_should_not_send_server_headers_after_clearing() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_default_header(test_header_name(), HeaderValue::from_static("from-config"))
+            .build();
+        let mut server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        server.add_header(test_header_name(), HeaderValue::from_static("from-server"));
+        server.clear_headers();
+
+        let text = server.get("/header").await.text();
+
+        assert_eq!(text, "");
+    }
+
+    #[tokio::test]
+    async fn it_should_replace_content_type_with_request_header() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .get("/content_type")
+            .json(&"hello")
+            .add_header(CONTENT_TYPE, HeaderValue::from_static("application/custom"))
+            .await
+            .text();
+
+        assert_eq!(text, "application/custom");
+    }
+}
+
+#[cfg(test)]
+mod test_cookie_store {
+    use super::*;
+
+    use ::axum::http::header::COOKIE;
+    use ::axum::http::header::SET_COOKIE;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::HeaderName;
+    use ::axum::response::AppendHeaders;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::axum_test::TestServer;
+    use ::cookie::Cookie;
+    use ::hyper::header::HeaderValue;
+    use ::hyper::Uri;
+    use ::std::sync::Arc;
+    use ::std::sync::Mutex;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_cookie_headers(headers: HeaderMap) -> String {
+        headers
+            .get_all(COOKIE)
+            .iter()
+            .map(|h| h.to_str().unwrap().to_string())
+            .collect::<Vec<String>>()
+            .join(" | ")
+    }
+
+    async fn login() -> AppendHeaders<[(HeaderName, &'static str); 2]> {
+        AppendHeaders([
+            (SET_COOKIE, "session=abc123; Path=/"),
+            (SET_COOKIE, "theme=dark; Path=/"),
+        ])
+    }
+
+    async fn logout() -> [(&'static str, &'static str); 1] {
+        [(SET_COOKIE.as_str(), "session=; Path=/; Max-Age=0")]
+    }
+
+    async fn login_admin() -> [(&'static str, &'static str); 1] {
+        [(SET_COOKIE.as_str(), "admin=yes; Path=/admin")]
+    }
+
+    async fn login_secure() -> [(&'static str, &'static str); 1] {
+        [(SET_COOKIE.as_str(), "secret=shh; Path=/; Secure; HttpOnly")]
+    }
+
+    async fn login_expired() -> [(&'static str, &'static str); 1] {
+        [(
+            SET_COOKIE.as_str(),
+            "old=gone; Path=/; Expires=Thu, 01 Jan 1970 00:00:01 GMT",
+        )]
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/cookies", get(get_cookie_headers))
+            .route("/admin/cookies", get(get_cookie_headers))
+            .route("/login", get(login))
+            .route("/logout", get(logout))
+            .route("/login_admin", get(login_admin))
+            .route("/login_secure", get(login_secure))
+            .route("/login_expired", get(login_expired))
+    }
+
+    fn new_saving_server(test_server: &TestServer) -> Server {
+        let config = ServerConfig::builder().save_cookies().build();
+        Server::new_with_config(test_server.server_address(), config).expect("Should create server")
+    }
+
+    #[tokio::test]
+    async fn it_should_send_cookies_as_a_single_header() {
+        let test_server = new_test_server(router());
+        let server = new_saving_server(&test_server);
+
+        server.get("/login").await;
+        let text = server.get("/cookies").await.text();
+
+        assert_eq!(text, "session=abc123; theme=dark");
+    }
+
+    #[tokio::test]
+    async fn it_should_include_request_cookies_in_the_single_header() {
+        let test_server = new_test_server(router());
+        let server = new_saving_server(&test_server);
+
+        server.get("/login").await;
+        let text = server
+            .get("/cookies")
+            .add_cookie(Cookie::new("extra", "1"))
+            .await
+            .text();
+
+        assert_eq!(text, "session=abc123; theme=dark; extra=1");
+    }
+
+    #[tokio::test]
+    async fn it_should_remove_cookies_on_max_age_zero() {
+        let test_server = new_test_server(router());
+        let server = new_saving_server(&test_server);
+
+        server.get("/login").await;
+        server.get("/logout").await;
+        let text = server.get("/cookies").await.text();
+
+        assert_eq!(text, "theme=dark");
+    }
+
+    #[tokio::test]
+    async fn it_should_not_store_expired_cookies() {
+        let test_server = new_test_server(router());
+        let server = new_saving_server(&test_server);
+
+        server.get("/login_expired").await;
+        let text = server.get("/cookies").await.text();
+
+        assert_eq!(text, "");
+    }
+
+    #[tokio::test]
+    async fn it_should_only_send_cookies_within_their_path() {
+        let test_server = new_test_server(router());
+        let server = new_saving_server(&test_server);
+
+        server.get("/login").await;
+        server.get("/login_admin").await;
+
+        let root_text = server.get("/cookies").await.text();
+        assert_eq!(root_text, "session=abc123; theme=dark");
+
+        let admin_text = server.get("/admin/cookies").await.text();
+        assert_eq!(admin_text, "admin=yes; session=abc123; theme=dark");
+    }
+
+    #[tokio::test]
+    async fn it_should_not_send_secure_cookies_over_http() {
+        let test_server = new_test_server(router());
+        let server = new_saving_server(&test_server);
+
+        server.get("/login_secure").await;
+        let text = server.get("/cookies").await.text();
+
+        assert_eq!(text, "");
+    }
+
+    #[tokio::test]
+    async fn it_should_not_send_server_cookies_when_cleared_on_request() {
+        let test_server = new_test_server(router());
+        let server = new_saving_server(&test_server);
+
+        server.get("/login").await;
+        let text = server.get("/cookies").clear_cookies().await.text();
+
+        assert_eq!(text, "");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_cookies_added_to_the_server() {
+        let test_server = new_test_server(router());
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server.add_cookie(Cookie::new("added", "yes"));
+        let text = server.get("/cookies").await.text();
+
+        assert_eq!(text, "added=yes");
+    }
+
+    #[tokio::test]
+    async fn it_should_share_a_memory_cookie_store_across_servers() {
+        let test_server = new_test_server(router());
+        let cookie_store = Arc::new(MemoryCookieStore::new());
+        let config = ServerConfig::builder()
+            .save_cookies()
+            .cookie_store(cookie_store.clone())
+            .build();
+
+        let login_server = Server::new_with_config(test_server.server_address(), config.clone())
+            .expect("Should create server");
+        let other_server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        login_server.get("/login").await;
+        let text = other_server.get("/cookies").await.text();
+
+        assert_eq!(text, "session=abc123; theme=dark");
+    }
+
+    #[derive(Debug, Default)]
+    struct TenantCookieStore {
+        set_cookies: Mutex<Vec<String>>,
+    }
+
+    impl CookieStore for TenantCookieStore {
+        fn store_set_cookies(
+            &self,
+            set_cookie_headers: &mut dyn Iterator<Item = &HeaderValue>,
+            _request_uri: &Uri,
+        ) {
+            let mut set_cookies = self.set_cookies.lock().unwrap();
+            for header in set_cookie_headers {
+                set_cookies.push(header.to_str().unwrap().to_string());
+            }
+        }
+
+        fn cookie_header(&self, _request_uri: &Uri) -> Option<HeaderValue> {
+            Some(HeaderValue::from_static("tenant=acme"))
+        }
+
+        fn clear(&self) {
+            self.set_cookies.lock().unwrap().clear();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_use_a_custom_cookie_store() {
+        let test_server = new_test_server(router());
+        let cookie_store = Arc::new(TenantCookieStore::default());
+        let config = ServerConfig::builder()
+            .save_cookies()
+            .cookie_store(cookie_store.clone())
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        server.get("/login").await;
+        let text = server.get("/cookies").await.text();
+
+        assert_eq!(text, "tenant=acme");
+        assert_eq!(
+            *cookie_store.set_cookies.lock().unwrap(),
+            vec!["session=abc123; Path=/", "theme=dark; Path=/"]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_error_saving_cookie_files_with_a_custom_cookie_store() {
+        let config = ServerConfig::builder()
+            .cookie_store(Arc::new(TenantCookieStore::default()))
+            .build();
+        let server = Server::new_with_config("http://localhost:3000".to_string(), config)
+            .expect("Should create server");
+
+        let result = server.save_cookies_to("cookies.json", CookieFileFormat::Json);
+
+        assert!(matches!(result, Err(Error::CookieFile { .. })));
+    }
+}
+
+#[cfg(test)]
+mod test_cookie_files {
+    use super::*;
+
+    use ::axum::http::header::COOKIE;
+    use ::axum::http::header::SET_COOKIE;
+    use ::axum::http::HeaderMap;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::cookie::Cookie;
+    use ::std::env::temp_dir;
+    use ::std::fs::remove_file;
+    use ::std::fs::write;
+    use ::std::path::PathBuf;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_cookie_header(headers: HeaderMap) -> String {
+        headers
+            .get(COOKIE)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    async fn login() -> [(&'static str, &'static str); 1] {
+        [(SET_COOKIE.as_str(), "session=abc123; Path=/; HttpOnly")]
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/cookies", get(get_cookie_header))
+            .route("/login", get(login))
+    }
+
+    fn temp_cookie_file(name: &str) -> PathBuf {
+        temp_dir().join(format!("kantan-{}-{}", std::process::id(), name))
+    }
+
+    async fn assert_cookies_survive_restart(format: CookieFileFormat, file_name: &str) {
+        let test_server = new_test_server(router());
+        let path = temp_cookie_file(file_name);
+
+        let config = ServerConfig::builder().save_cookies().build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        server.get("/login").await;
+        server
+            .save_cookies_to(&path, format)
+            .expect("Should save cookies");
+
+        let mut restarted_server =
+            Server::new(test_server.server_address()).expect("Should create server");
+        restarted_server
+            .load_cookies_from(&path, format)
+            .expect("Should load cookies");
+        let text = restarted_server.get("/cookies").await.text();
+        remove_file(&path).unwrap();
+
+        assert_eq!(text, "session=abc123");
+    }
+
+    #[tokio::test]
+    async fn it_should_restore_cookies_saved_as_json() {
+        assert_cookies_survive_restart(CookieFileFormat::Json, "cookies.json").await;
+    }
+
+    #[tokio::test]
+    async fn it_should_restore_cookies_saved_as_netscape() {
+        assert_cookies_survive_restart(CookieFileFormat::Netscape, "cookies.txt").await;
+    }
+
+    #[tokio::test]
+    async fn it_should_discard_expired_cookies_when_loading() {
+        let test_server = new_test_server(router());
+        let path = temp_cookie_file("expired-cookies.txt");
+        let host = test_server
+            .server_address()
+            .parse::<http::Uri>()
+            .unwrap()
+            .host()
+            .unwrap()
+            .to_string();
+        let contents = format!(
+            "# Netscape HTTP Cookie File\n\
+             {host}\tFALSE\t/\tFALSE\t1\told\tgone\n\
+             {host}\tFALSE\t/\tFALSE\t0\tsession\tkept\n"
+        );
+        write(&path, contents).unwrap();
+
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server
+            .load_cookies_from(&path, CookieFileFormat::Netscape)
+            .expect("Should load cookies");
+        let text = server.get("/cookies").await.text();
+        remove_file(&path).unwrap();
+
+        assert_eq!(text, "session=kept");
+    }
+
+    #[tokio::test]
+    async fn it_should_save_cookies_added_to_the_server() {
+        let test_server = new_test_server(router());
+        let path = temp_cookie_file("added-cookies.json");
+
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server.add_cookie(Cookie::new("added", "yes"));
+        server
+            .save_cookies_to(&path, CookieFileFormat::Json)
+            .expect("Should save cookies");
+
+        let mut restarted_server =
+            Server::new(test_server.server_address()).expect("Should create server");
+        restarted_server
+            .load_cookies_from(&path, CookieFileFormat::Json)
+            .expect("Should load cookies");
+        let text = restarted_server.get("/cookies").await.text();
+        remove_file(&path).unwrap();
+
+        assert_eq!(text, "added=yes");
+    }
+
+    #[tokio::test]
+    async fn it_should_error_when_the_file_is_missing() {
+        let mut server = Server::new("http://localhost:3000".to_string()).unwrap();
+        let path = temp_cookie_file("missing-cookies.json");
+
+        let result = server.load_cookies_from(&path, CookieFileFormat::Json);
+
+        assert!(matches!(result, Err(Error::CookieFile { .. })));
+    }
+
+    #[tokio::test]
+    async fn it_should_error_when_an_expiry_is_too_far_in_the_future() {
+        let mut server = Server::new("http://localhost:3000".to_string()).unwrap();
+        let path = temp_cookie_file("far-future-cookies.txt");
+        write(
+            &path,
+            "example.com\tFALSE\t/\tFALSE\t18446744073709551615\tsession\tabc123\n",
+        )
+        .unwrap();
+
+        let result = server.load_cookies_from(&path, CookieFileFormat::Netscape);
+        remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(Error::CookieFile { .. })));
+    }
+}
+
+#[cfg(test)]
+mod test_form {
+    use super::*;
+
+    use ::axum::http::header::CONTENT_TYPE;
+    use ::axum::http::HeaderMap;
+    use ::axum::routing::get;
+    use ::axum::routing::post;
+    use ::axum::Form;
+    use ::axum::Router;
+    use ::serde::Deserialize;
+    use ::serde::Serialize;
+
+    use crate::test_support::new_test_server;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Login {
+        username: String,
+        remember_me: bool,
+    }
+
+    async fn post_login(Form(login): Form<Login>) -> String {
+        format!("{} {}", login.username, login.remember_me)
+    }
+
+    async fn get_content_type(headers: HeaderMap) -> String {
+        headers
+            .get(CONTENT_TYPE)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    async fn get_form() -> ([(&'static str, &'static str); 1], &'static str) {
+        (
+            [(CONTENT_TYPE.as_str(), "application/x-www-form-urlencoded")],
+            "username=Joe+Bloggs&remember_me=true",
+        )
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/login", post(post_login))
+            .route("/content_type", post(get_content_type))
+            .route("/form", get(get_form))
+    }
+
+    fn joe_login() -> Login {
+        Login {
+            username: "Joe Bloggs".to_string(),
+            remember_me: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_send_forms() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server.post("/login").form(&joe_login()).await.text();
+
+        assert_eq!(text, "Joe Bloggs true");
+    }
+
+    #[tokio::test]
+    async fn it_should_default_to_form_content_type() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server.post("/content_type").form(&joe_login()).await.text();
+
+        assert_eq!(text, "application/x-www-form-urlencoded");
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_the_server_default_content_type() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .default_content_type("text/csv")
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let text = server.post("/content_type").form(&joe_login()).await.text();
+
+        assert_eq!(text, "text/csv");
+    }
+
+    #[tokio::test]
+    async fn it_should_read_forms_from_responses() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let login: Login = server.get("/form").await.form();
+
+        assert_eq!(login, joe_login());
+    }
+
+    #[tokio::test]
+    async fn it_should_return_decode_error_for_invalid_forms() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let result = server.get("/form").await.try_form::<Vec<u32>>();
+
+        assert!(matches!(result, Err(Error::Decode { .. })));
+    }
+}
+
+#[cfg(test)]
+mod test_multipart {
+    use super::*;
+
+    use ::axum::extract::Multipart;
+    use ::axum::http::header::CONTENT_TYPE;
+    use ::axum::http::HeaderMap;
+    use ::axum::routing::post;
+    use ::axum::Router;
+    use ::hyper::header::HeaderName;
+    use ::hyper::header::HeaderValue;
+    use ::std::env::temp_dir;
+    use ::std::fs::remove_file;
+    use ::std::fs::write;
+
+    use crate::test_support::new_test_server;
+
+    async fn post_parts(mut multipart: Multipart) -> String {
+        let mut lines = vec![];
+        while let Some(field) = multipart.next_field().await.unwrap() {
+            let name = field.name().unwrap_or_default().to_string();
+            let file_name = field.file_name().unwrap_or("-").to_string();
+            let content_type = field.content_type().unwrap_or("-").to_string();
+            let custom_header = field
+                .headers()
+                .get("x-part-id")
+                .map(|h| h.to_str().unwrap().to_string())
+                .unwrap_or_else(|| "-".to_string());
+            let text = field.text().await.unwrap();
+
+            lines.push(format!(
+                "{} {} {} {} {}",
+                name, file_name, content_type, custom_header, text
+            ));
+        }
+
+        lines.join("\n")
+    }
+
+    async fn get_content_type(headers: HeaderMap) -> String {
+        headers
+            .get(CONTENT_TYPE)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/parts", post(post_parts))
+            .route("/content_type", post(get_content_type))
+    }
+
+    #[tokio::test]
+    async fn it_should_send_text_fields() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let form = MultipartForm::new()
+            .add_text("username", "Joe")
+            .add_text("age", 42);
+        let text = server.post("/parts").multipart(form).await.text();
+
+        assert_eq!(text, "username - - - Joe\nage - - - 42");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_byte_parts_with_file_names_and_headers() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let part = Part::bytes("hello".as_bytes())
+            .file_name("hello.txt")
+            .mime_type("text/plain")
+            .add_header(
+                HeaderName::from_static("x-part-id"),
+                HeaderValue::from_static("123"),
+            );
+        let form = MultipartForm::new().add_part("greeting", part);
+        let text = server.post("/parts").multipart(form).await.text();
+
+        assert_eq!(text, "greeting hello.txt text/plain 123 hello");
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_files_with_inferred_mime_types() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_dir().join(format!("kantan-{}-upload.json", std::process::id()));
+        write(&path, r#"{"name":"Joe"}"#).unwrap();
+
+        let form = MultipartForm::new().add_part("upload", Part::file(&path));
+        let text = server.post("/parts").multipart(form).await.text();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        remove_file(&path).unwrap();
+
+        assert_eq!(
+            text,
+            format!(
+                r#"upload {} application/json - {{"name":"Joe"}}"#,
+                file_name
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_set_the_content_type_with_boundary() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .default_content_type("application/json")
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let form = MultipartForm::new().add_text("username", "Joe");
+        let expected = format!("multipart/form-data; boundary={}", form.boundary());
+        let text = server.post("/content_type").multipart(form).await.text();
+
+        assert_eq!(text, expected);
+    }
+
+    #[tokio::test]
+    async fn it_should_fail_to_send_missing_files() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_dir().join(format!("kantan-{}-missing.txt", std::process::id()));
+
+        let form = MultipartForm::new().add_part("upload", Part::file(&path));
+        let result = server.post("/parts").multipart(form).try_send().await;
+
+        assert!(matches!(result, Err(Error::InvalidRequest { .. })));
+    }
+}
+
+#[cfg(test)]
+mod test_streaming_bodies {
+    use super::*;
+
+    use ::axum::extract::RawBody;
+    use ::axum::http::header::CONTENT_LENGTH;
+    use ::axum::http::header::CONTENT_TYPE;
+    use ::axum::http::header::TRANSFER_ENCODING;
+    use ::axum::http::HeaderMap;
+    use ::axum::response::Redirect as AxumRedirect;
+    use ::axum::routing::post;
+    use ::axum::Router;
+    use ::futures_util::stream::iter;
+    use ::hyper::body::to_bytes;
+    use ::hyper::body::Bytes;
+    use ::hyper::header::HeaderName;
+    use ::hyper::header::HeaderValue;
+    use ::std::env::temp_dir;
+    use ::std::fs::remove_file;
+    use ::std::fs::write;
+    use ::std::io::Cursor;
+    use ::std::io::Error as IoError;
+
+    use crate::test_support::new_test_server;
+
+    fn header_text(headers: &HeaderMap, name: &HeaderName) -> String {
+        headers
+            .get(name)
+            .map(|h| h.to_str().unwrap().to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    async fn post_echo(headers: HeaderMap, RawBody(body): RawBody) -> String {
+        let body_bytes = to_bytes(body).await.unwrap();
+
+        format!(
+            "{} {} {} {}",
+            header_text(&headers, &CONTENT_TYPE),
+            header_text(&headers, &CONTENT_LENGTH),
+            header_text(&headers, &TRANSFER_ENCODING),
+            String::from_utf8_lossy(&body_bytes)
+        )
+    }
+
+    async fn post_redirect() -> AxumRedirect {
+        AxumRedirect::temporary("/echo")
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/echo", post(post_echo))
+            .route("/redirect", post(post_redirect))
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_files_with_content_length() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_dir().join(format!("kantan-{}-body.json", std::process::id()));
+        write(&path, r#"{"name":"Joe"}"#).unwrap();
+
+        let text = server.post("/echo").file(&path).await.text();
+        remove_file(&path).unwrap();
+
+        assert_eq!(text, r#"application/json 14 - {"name":"Joe"}"#);
+    }
+
+    #[tokio::test]
+    async fn it_should_fail_to_send_missing_files() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_dir().join(format!("kantan-{}-missing-body.json", std::process::id()));
+
+        let result = server.post("/echo").file(&path).try_send().await;
+
+        assert!(matches!(result, Err(Error::InvalidRequest { .. })));
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_readers_chunked() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .post("/echo")
+            .content_type("text/plain")
+            .reader(Cursor::new(b"hello reader".to_vec()))
+            .await
+            .text();
+
+        assert_eq!(text, "text/plain - chunked hello reader");
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_body_chunks() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let chunks = vec![
+            Ok::<Bytes, IoError>(Bytes::from("hello ")),
+            Ok(Bytes::from("stream")),
+        ];
+        let text = server.post("/echo").body_stream(iter(chunks)).await.text();
+
+        assert_eq!(text, "- - chunked hello stream");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_a_given_content_length_for_streams() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server
+            .post("/echo")
+            .add_header(CONTENT_LENGTH, HeaderValue::from_static("12"))
+            .reader(Cursor::new(b"hello reader".to_vec()))
+            .await
+            .text();
+
+        assert_eq!(text, "- 12 - hello reader");
+    }
+
+    #[tokio::test]
+    async fn it_should_resend_files_on_temporary_redirects() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_dir().join(format!("kantan-{}-redirect.txt", std::process::id()));
+        write(&path, "hello file").unwrap();
+
+        let text = server
+            .post("/redirect")
+            .redirect_policy(RedirectPolicy::Limited(1))
+            .file(&path)
+            .await
+            .text();
+        remove_file(&path).unwrap();
+
+        assert_eq!(text, "text/plain 10 - hello file");
+    }
+
+    #[tokio::test]
+    async fn it_should_fail_to_resend_streams_on_temporary_redirects() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let chunks = vec![Ok::<Bytes, IoError>(Bytes::from("hello"))];
+        let result = server
+            .post("/redirect")
+            .redirect_policy(RedirectPolicy::Limited(1))
+            .body_stream(iter(chunks))
+            .try_send()
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidRequest { .. })));
+    }
+}
+
+#[cfg(test)]
+mod test_streaming_response {
+    use super::*;
+
+    use ::axum::body::StreamBody;
+    use ::axum::http::StatusCode;
+    use ::axum::response::Redirect as AxumRedirect;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::futures_util::stream::iter;
+    use ::futures_util::stream::pending;
+    use ::futures_util::Stream;
+    use ::futures_util::StreamExt;
+    use ::hyper::body::Bytes;
+    use ::std::io::Error as IoError;
+    use ::std::time::Duration;
+
+    use crate::test_support::new_test_server;
+
+    async fn get_endless() -> StreamBody<impl Stream<Item = Result<Bytes, IoError>>> {
+        let chunks = iter(vec![Ok(Bytes::from("first"))]).chain(pending());
+        StreamBody::new(chunks)
+    }
+
+    async fn get_chunks() -> StreamBody<impl Stream<Item = Result<Bytes, IoError>>> {
+        let chunks = vec![
+            Ok(Bytes::from("a")),
+            Ok(Bytes::from("b")),
+            Ok(Bytes::from("c")),
+        ];
+        StreamBody::new(iter(chunks))
+    }
+
+    async fn get_not_found() -> (StatusCode, &'static str) {
+        (StatusCode::NOT_FOUND, "nothing here")
+    }
+
+    async fn get_redirect() -> AxumRedirect {
+        AxumRedirect::to("/chunks")
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/endless", get(get_endless))
+            .route("/chunks", get(get_chunks))
+            .route("/not_found", get(get_not_found))
+            .route("/redirect", get(get_redirect))
+    }
+
+    #[tokio::test]
+    async fn it_should_return_before_the_body_has_finished() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let mut streaming_response = server
+            .get("/endless")
+            .total_timeout(Duration::from_secs(5))
+            .send_streaming()
+            .await
+            .expect("Should receive headers");
+        let first_chunk = streaming_response.next().await.unwrap().unwrap();
+
+        assert_eq!(streaming_response.status_code(), StatusCode::OK);
+        assert_eq!(first_chunk, Bytes::from("first"));
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_all_chunks() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let streaming_response = server
+            .get("/chunks")
+            .send_streaming()
+            .await
+            .expect("Should receive headers");
+        let chunks: Vec<Bytes> = streaming_response
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.concat(), b"abc".to_vec());
+    }
+
+    #[tokio::test]
+    async fn it_should_collect_into_a_response() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server
+            .get("/chunks")
+            .send_streaming()
+            .await
+            .expect("Should receive headers")
+            .collect()
+            .await
+            .expect("Should read body");
+
+        assert_eq!(response.text(), "abc");
+    }
+
+    #[tokio::test]
+    async fn it_should_follow_redirects() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let streaming_response = server
+            .get("/redirect")
+            .redirect_policy(RedirectPolicy::Limited(1))
+            .send_streaming()
+            .await
+            .expect("Should receive headers");
+
+        assert_eq!(streaming_response.final_uri().path(), "/chunks");
+        assert_eq!(streaming_response.redirect_history().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_status_error_with_body_when_not_expected() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let result = server
+            .get("/not_found")
+            .expect_success()
+            .send_streaming()
+            .await;
+
+        match result {
+            Err(Error::Status { response, .. }) => {
+                assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+                assert_eq!(response.text(), "nothing here");
+            }
+            other => panic!("Expected a status error, received {:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_download {
+    use super::*;
+
+    use ::axum::body::StreamBody;
+    use ::axum::http::header::ACCEPT_RANGES;
+    use ::axum::http::header::CONTENT_LENGTH;
+    use ::axum::http::header::CONTENT_RANGE;
+    use ::axum::http::header::ETAG;
+    use ::axum::http::header::IF_RANGE;
+    use ::axum::http::header::RANGE;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::StatusCode;
+    use ::axum::response::IntoResponse;
+    use ::axum::response::Response as AxumResponse;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::futures_util::future::ready;
+    use ::futures_util::stream::once;
+    use ::futures_util::StreamExt;
+    use ::hyper::body::Bytes;
+    use ::std::env::temp_dir;
+    use ::std::fs::read;
+    use ::std::fs::remove_file;
+    use ::std::fs::write;
+    use ::std::io::Error as IoError;
+    use ::std::path::Path;
+    use ::std::path::PathBuf;
+    use ::std::time::Duration;
+    use ::tokio::time::sleep;
+
+    use crate::test_support::new_test_server;
+
+    const FILE_CONTENTS: &[u8] = b"0123456789abcdefghij";
+    const FILE_ETAG: &str = "\"v1\"";
+
+    async fn get_file(headers: HeaderMap) -> AxumResponse {
+        let maybe_range_start = headers
+            .get(RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.strip_suffix('-'))
+            .and_then(|start| start.parse::<usize>().ok());
+        let is_same_file = headers
+            .get(IF_RANGE)
+            .map(|if_range| if_range == FILE_ETAG)
+            .unwrap_or(true);
+
+        match maybe_range_start {
+            Some(start) if is_same_file => (
+                StatusCode::PARTIAL_CONTENT,
+                [
+                    (ACCEPT_RANGES.as_str(), "bytes".to_string()),
+                    (ETAG.as_str(), FILE_ETAG.to_string()),
+                    (CONTENT_RANGE.as_str(), format!("bytes {}-19/20", start)),
+                ],
+                &FILE_CONTENTS[start..],
+            )
+                .into_response(),
+            _ => (
+                [
+                    (ACCEPT_RANGES.as_str(), "bytes"),
+                    (ETAG.as_str(), FILE_ETAG),
+                ],
+                FILE_CONTENTS,
+            )
+                .into_response(),
+        }
+    }
+
+    /// Sends the first half of the file, and then fails.
+    async fn get_broken_file() -> AxumResponse {
+        let first_half = once(ready(Ok(Bytes::from_static(&FILE_CONTENTS[..10]))));
+        let failure = once(async {
+            sleep(Duration::from_millis(100)).await;
+            Err(IoError::other("connection lost"))
+        });
+        let chunks = first_half.chain(failure);
+
+        (
+            [
+                (ACCEPT_RANGES.as_str(), "bytes"),
+                (ETAG.as_str(), FILE_ETAG),
+                (CONTENT_LENGTH.as_str(), "20"),
+            ],
+            StreamBody::new(chunks),
+        )
+            .into_response()
+    }
+
+    async fn get_missing_file() -> StatusCode {
+        StatusCode::NOT_FOUND
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/file", get(get_file))
+            .route("/broken_file", get(get_broken_file))
+            .route("/missing_file", get(get_missing_file))
+    }
+
+    fn temp_download_path(name: &str) -> PathBuf {
+        temp_dir().join(format!("kantan-{}-{}", std::process::id(), name))
+    }
+
+    fn part_path(path: &Path, suffix: &str) -> PathBuf {
+        PathBuf::from(format!("{}{}", path.display(), suffix))
+    }
+
+    #[tokio::test]
+    async fn it_should_download_to_a_file() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_download_path("download.bin");
+
+        let download = server
+            .get("/file")
+            .download_to(&path)
+            .await
+            .expect("Should download");
+        let contents = read(&path).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(contents, FILE_CONTENTS);
+        assert_eq!(download.length(), 20);
+        assert!(!download.is_resumed());
+        assert!(!part_path(&path, ".part").exists());
+        assert!(!part_path(&path, ".part.json").exists());
+    }
+
+    #[tokio::test]
+    async fn it_should_resume_a_failed_download() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_download_path("resumed.bin");
+
+        let result = server.get("/broken_file").download_to(&path).await;
+        assert!(
+            matches!(result, Err(Error::BodyRead { .. })),
+            "{:?}",
+            result
+        );
+        assert_eq!(
+            read(part_path(&path, ".part")).unwrap(),
+            &FILE_CONTENTS[..10]
+        );
+
+        let download = server
+            .get("/file")
+            .download_to(&path)
+            .await
+            .expect("Should download");
+        let contents = read(&path).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(contents, FILE_CONTENTS);
+        assert_eq!(download.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(download.resumed_from(), 10);
+        assert_eq!(download.length(), 20);
+    }
+
+    #[tokio::test]
+    async fn it_should_restart_when_the_file_has_changed() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_download_path("changed.bin");
+        write(part_path(&path, ".part"), "old contents").unwrap();
+        write(
+            part_path(&path, ".part.json"),
+            r#"{"etag":"\"v0\"","last_modified":null}"#,
+        )
+        .unwrap();
+
+        let download = server
+            .get("/file")
+            .download_to(&path)
+            .await
+            .expect("Should download");
+        let contents = read(&path).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(contents, FILE_CONTENTS);
+        assert_eq!(download.status_code(), StatusCode::OK);
+        assert!(!download.is_resumed());
+    }
+
+    #[tokio::test]
+    async fn it_should_return_status_error_for_failed_responses() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_download_path("missing.bin");
+
+        let result = server.get("/missing_file").download_to(&path).await;
+
+        assert_eq!(
+            result.unwrap_err().status_code(),
+            Some(StatusCode::NOT_FOUND)
+        );
+        assert!(!path.exists());
+    }
+}
+
+#[cfg(test)]
+mod test_decompression {
+    use super::*;
+
+    use ::axum::extract::Path as AxumPath;
+    use ::axum::http::header::ACCEPT_ENCODING;
+    use ::axum::http::header::CONTENT_ENCODING;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::HeaderValue;
+    use ::axum::response::IntoResponse;
+    use ::axum::response::Response as AxumResponse;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::std::env::temp_dir;
+    use ::std::fs::read_to_string;
+    use ::std::fs::remove_file;
+
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    use ::tokio::io::AsyncReadExt;
+
+    use crate::test_support::new_test_server;
+
+    const TEXT: &str = "Hello, this is some text which has been compressed by the server";
+
+    /// Compresses the bytes given, using the `Content-Encoding` name given.
+    async fn encode(encoding: &str, bytes: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![];
+        match encoding {
+            #[cfg(feature = "gzip")]
+            "gzip" => ::async_compression::tokio::bufread::GzipEncoder::new(bytes)
+                .read_to_end(&mut encoded)
+                .await
+                .unwrap(),
+            #[cfg(feature = "deflate")]
+            "deflate" => ::async_compression::tokio::bufread::ZlibEncoder::new(bytes)
+                .read_to_end(&mut encoded)
+                .await
+                .unwrap(),
+            #[cfg(feature = "brotli")]
+            "br" => ::async_compression::tokio::bufread::BrotliEncoder::new(bytes)
+                .read_to_end(&mut encoded)
+                .await
+                .unwrap(),
+            #[cfg(feature = "zstd")]
+            "zstd" => ::async_compression::tokio::bufread::ZstdEncoder::new(bytes)
+                .read_to_end(&mut encoded)
+                .await
+                .unwrap(),
+            _ => {
+                encoded.extend_from_slice(bytes);
+                bytes.len()
+            }
+        };
+
+        encoded
+    }
+
+    async fn get_encoded(AxumPath(encoding): AxumPath<String>) -> AxumResponse {
+        let body = encode(&encoding, TEXT.as_bytes()).await;
+        ([(CONTENT_ENCODING, encoding)], body).into_response()
+    }
+
+    async fn get_accept_encoding(headers: HeaderMap) -> String {
+        headers
+            .get(ACCEPT_ENCODING)
+            .map(|accept_encoding| accept_encoding.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/encoded/:encoding", get(get_encoded))
+            .route("/accept_encoding", get(get_accept_encoding))
+    }
+
+    #[cfg(all(feature = "gzip", feature = "deflate"))]
+    #[tokio::test]
+    async fn it_should_advertise_accept_encoding() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let text = server.get("/accept_encoding").await.text();
+        let encodings: Vec<&str> = text.split(", ").collect();
+
+        assert!(encodings.contains(&"gzip"));
+        assert!(encodings.contains(&"deflate"));
+    }
+
+    #[tokio::test]
+    async fn it_should_not_replace_accept_encoding_set_on_the_request() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/accept_encoding")
+            .add_header(ACCEPT_ENCODING, HeaderValue::from_static("identity"))
+            .await
+            .assert_text("identity");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_decompress_gzip_responses() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server.get("/encoded/gzip").await;
+
+        assert_eq!(response.text(), TEXT);
+        assert!(response.is_decompressed());
+        assert_eq!(response.raw_bytes(), encode("gzip", TEXT.as_bytes()).await);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[tokio::test]
+    async fn it_should_decompress_deflate_responses() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server.get("/encoded/deflate").await.assert_text(TEXT);
+    }
+
+    #[cfg(feature = "brotli")]
+    #[tokio::test]
+    async fn it_should_decompress_brotli_responses() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server.get("/encoded/br").await.assert_text(TEXT);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn it_should_decompress_zstd_responses() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server.get("/encoded/zstd").await.assert_text(TEXT);
+    }
+
+    #[tokio::test]
+    async fn it_should_leave_unknown_encodings_as_they_are() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server.get("/encoded/unknown").await;
+
+        assert_eq!(response.text(), TEXT);
+        assert!(!response.is_decompressed());
+        assert_eq!(response.raw_bytes(), TEXT.as_bytes());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_error_when_decompressed_body_is_larger_than_the_maximum() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let result = server
+            .get("/encoded/gzip")
+            .max_decompressed_size(10)
+            .try_send()
+            .await;
+
+        assert!(matches!(result, Err(Error::Decompress { .. })));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_use_max_decompressed_size_from_server_config() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder().max_decompressed_size(10).build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let too_large_result = server.get("/encoded/gzip").try_send().await;
+        let response = server
+            .get("/encoded/gzip")
+            .max_decompressed_size(TEXT.len())
+            .await;
+
+        assert!(matches!(too_large_result, Err(Error::Decompress { .. })));
+        assert_eq!(response.text(), TEXT);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_decompress_with_no_maximum() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let response = server
+            .get("/encoded/gzip")
+            .max_decompressed_size(usize::MAX)
+            .await;
+
+        assert_eq!(response.text(), TEXT);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_stream_the_body_as_it_was_sent() {
+        use ::futures_util::StreamExt;
+        use ::hyper::body::Bytes;
+
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let streaming_response = server
+            .get("/encoded/gzip")
+            .send_streaming()
+            .await
+            .expect("Should receive headers");
+        let chunks: Vec<Bytes> = streaming_response
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.concat(), encode("gzip", TEXT.as_bytes()).await);
+    }
+
+    #[tokio::test]
+    async fn it_should_ask_for_uncompressed_downloads() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let path = temp_dir().join(format!("kantan-{}-accept-encoding.txt", std::process::id()));
+
+        server
+            .get("/accept_encoding")
+            .download_to(&path)
+            .await
+            .expect("Should download");
+        let contents = read_to_string(&path).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(contents, "identity");
+    }
+}
+
+#[cfg(test)]
+mod test_compression {
+    use super::*;
+
+    use ::axum::http::header::CONTENT_ENCODING;
+    use ::axum::http::HeaderMap;
+    use ::axum::routing::post;
+    use ::axum::Json;
+    use ::axum::Router;
+    use ::hyper::body::Bytes;
+    use ::serde::Deserialize;
+    use ::serde::Serialize;
+
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    use ::tokio::io::AsyncReadExt;
+
+    use crate::test_support::new_test_server;
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct ReceivedBody {
+        content_encoding: Option<String>,
+        text: String,
+    }
+
+    /// Decompresses the bytes given, using the `Content-Encoding` name given.
+    async fn decode(encoding: &str, bytes: &[u8]) -> Vec<u8> {
+        let mut decoded = vec![];
+        match encoding {
+            #[cfg(feature = "gzip")]
+            "gzip" => ::async_compression::tokio::bufread::GzipDecoder::new(bytes)
+                .read_to_end(&mut decoded)
+                .await
+                .unwrap(),
+            #[cfg(feature = "zstd")]
+            "zstd" => ::async_compression::tokio::bufread::ZstdDecoder::new(bytes)
+                .read_to_end(&mut decoded)
+                .await
+                .unwrap(),
+            _ => {
+                decoded.extend_from_slice(bytes);
+                bytes.len()
+            }
+        };
+
+        decoded
+    }
+
+    async fn post_body(headers: HeaderMap, body: Bytes) -> Json<ReceivedBody> {
+        let content_encoding = headers
+            .get(CONTENT_ENCODING)
+            .map(|content_encoding| content_encoding.to_str().unwrap().to_string());
+        let decoded = decode(content_encoding.as_deref().unwrap_or_default(), &body).await;
+
+        Json(ReceivedBody {
+            content_encoding,
+            text: String::from_utf8(decoded).unwrap(),
+        })
+    }
+
+    fn router() -> Router {
+        Router::new().route("/body", post(post_body))
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_compress_bodies_with_gzip() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let text = "a".repeat(2000);
+
+        let received: ReceivedBody = server
+            .post("/body")
+            .compress(Encoding::Gzip)
+            .text(&text)
+            .await
+            .json();
+
+        assert_eq!(
+            received,
+            ReceivedBody {
+                content_encoding: Some("gzip".to_string()),
+                text,
+            }
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn it_should_compress_bodies_with_zstd() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let text = "a".repeat(2000);
+
+        let received: ReceivedBody = server
+            .post("/body")
+            .compress(Encoding::Zstd)
+            .text(&text)
+            .await
+            .json();
+
+        assert_eq!(received.content_encoding.as_deref(), Some("zstd"));
+        assert_eq!(received.text, text);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_not_compress_bodies_below_the_threshold() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        let received: ReceivedBody = server
+            .post("/body")
+            .compress(Encoding::Gzip)
+            .compression_threshold(100)
+            .text("small")
+            .await
+            .json();
+
+        assert_eq!(
+            received,
+            ReceivedBody {
+                content_encoding: None,
+                text: "small".to_string(),
+            }
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_compress_using_the_server_config() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .compression(Encoding::Gzip)
+            .compression_threshold(0)
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let received: ReceivedBody = server
+            .post("/body")
+            .json(&serde_json::json!({ "name": "Joe" }))
+            .await
+            .json();
+
+        assert_eq!(received.content_encoding.as_deref(), Some("gzip"));
+        assert_eq!(received.text, r#"{"name":"Joe"}"#);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn it_should_not_compress_when_turned_off_on_the_request() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .compression(Encoding::Gzip)
+            .compression_threshold(0)
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let received: ReceivedBody = server
+            .post("/body")
+            .do_not_compress()
+            .text("hello")
+            .await
+            .json();
+
+        assert_eq!(received.content_encoding, None);
+        assert_eq!(received.text, "hello");
+    }
+
+    #[tokio::test]
+    async fn it_should_not_compress_when_turned_off() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let text = "a".repeat(2000);
+
+        let received: ReceivedBody = server.post("/body").text(&text).await.json();
+
+        assert_eq!(received.content_encoding, None);
+        assert_eq!(received.text, text);
+    }
+}
+
+#[cfg(test)]
+mod test_retries {
+    use super::*;
+
+    use ::axum::extract::State;
+    use ::axum::http::header::RETRY_AFTER;
+    use ::axum::http::Method;
+    use ::axum::http::StatusCode;
+    use ::axum::response::IntoResponse;
+    use ::axum::response::Response as AxumResponse;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::axum_test::TestServer;
+    use ::futures_util::stream::once;
+    use ::hyper::body::Bytes;
+    use ::std::convert::Infallible;
+    use ::std::sync::atomic::AtomicUsize;
+    use ::std::sync::atomic::Ordering;
+    use ::std::sync::Arc;
+    use ::std::time::Duration;
+    use ::std::time::Instant;
+
+    use crate::test_support::new_test_server;
+
+    /// Counts the requests received,
+    /// failing with `503 Service Unavailable` for the first few.
+    #[derive(Debug, Clone)]
+    struct FlakyState {
+        requests: Arc<AtomicUsize>,
+        failures: usize,
+    }
+
+    async fn route_flaky(State(state): State<FlakyState>) -> AxumResponse {
+        let request_number = state.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        if request_number <= state.failures {
+            return (StatusCode::SERVICE_UNAVAILABLE, "try again").into_response();
+        }
+
+        format!("succeeded on attempt {}", request_number).into_response()
+    }
+
+    async fn route_retry_after(State(state): State<FlakyState>) -> AxumResponse {
+        let request_number = state.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        if request_number <= state.failures {
+            return (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, "1")]).into_response();
+        }
+
+        "ok".into_response()
+    }
+
+    fn new_flaky_server(failures: usize) -> (TestServer, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let state = FlakyState {
+            requests: requests.clone(),
+            failures,
+        };
+        let router = Router::new()
+            .route(
+                "/flaky",
+                get(route_flaky).post(route_flaky).put(route_flaky),
+            )
+            .route("/retry_after", get(route_retry_after))
+            .with_state(state);
+
+        let test_server = new_test_server(router);
+        (test_server, requests)
+    }
+
+    fn fast_retry_policy(max_attempts: usize) -> RetryPolicy {
+        RetryPolicy::new(max_attempts).backoff(Duration::from_millis(1), Duration::from_millis(10))
+    }
+
+    #[tokio::test]
+    async fn it_should_retry_until_the_request_succeeds() {
+        let (test_server, requests) = new_flaky_server(2);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/flaky")
+            .retry_policy(fast_retry_policy(3))
+            .await
+            .assert_status_ok()
+            .assert_text("succeeded on attempt 3");
+
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_last_response_after_the_max_attempts() {
+        let (test_server, requests) = new_flaky_server(5);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/flaky")
+            .retry_policy(fast_retry_policy(3))
+            .await
+            .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn it_should_not_retry_by_default() {
+        let (test_server, requests) = new_flaky_server(1);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/flaky")
+            .await
+            .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_use_the_retry_policy_from_server_config() {
+        let (test_server, requests) = new_flaky_server(1);
+        let config = ServerConfig::builder()
+            .retry_policy(fast_retry_policy(2))
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        server.get("/flaky").await.assert_status_ok();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_not_retry_post_requests_by_default() {
+        let (test_server, requests) = new_flaky_server(1);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .post("/flaky")
+            .retry_policy(fast_retry_policy(3))
+            .await
+            .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_retry_the_methods_given() {
+        let (test_server, requests) = new_flaky_server(1);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .post("/flaky")
+            .text("hello")
+            .retry_policy(fast_retry_policy(3).methods([Method::POST]))
+            .await
+            .assert_status_ok();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_not_retry_streamed_bodies() {
+        let (test_server, requests) = new_flaky_server(1);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let chunks = once(async { Ok::<_, Infallible>(Bytes::from("hello")) });
+
+        server
+            .put("/flaky")
+            .body_stream(chunks)
+            .retry_policy(fast_retry_policy(3))
+            .await
+            .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_wait_for_retry_after() {
+        let (test_server, requests) = new_flaky_server(1);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let retry_policy =
+            RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_secs(5));
+
+        let start = Instant::now();
+        server
+            .get("/retry_after")
+            .retry_policy(retry_policy)
+            .await
+            .assert_status_ok();
+
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_not_retry_when_retry_after_is_longer_than_the_max_backoff() {
+        let (test_server, requests) = new_flaky_server(1);
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/retry_after")
+            .retry_policy(fast_retry_policy(2))
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_retry_connect_errors() {
+        let server = Server::new("http://127.0.0.1:1".to_string()).expect("Should create server");
+        let retry_policy =
+            RetryPolicy::new(3).backoff(Duration::from_millis(100), Duration::from_millis(100));
+
+        let start = Instant::now();
+        let result = server
+            .get("/flaky")
+            .retry_policy(retry_policy.without_jitter())
+            .try_send()
+            .await;
+
+        assert!(matches!(result, Err(Error::Connect { .. })));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
+
+#[cfg(test)]
+mod test_clone {
+    use super::*;
+
+    use ::axum::http::header::HeaderName;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::HeaderValue;
+    use ::axum::response::Redirect as AxumRedirect;
+    use ::axum::routing::post;
+    use ::axum::Router;
+    use ::futures_util::stream::iter;
+    use ::hyper::body::Bytes;
+    use ::std::convert::Infallible;
+
+    use crate::test_support::new_test_server;
+
+    const X_VARIANT: HeaderName = HeaderName::from_static("x-variant");
+
+    async fn post_echo(headers: HeaderMap, body: String) -> String {
+        let variant = headers
+            .get(X_VARIANT)
+            .map(|variant| variant.to_str().unwrap().to_string())
+            .unwrap_or_default();
+
+        format!("{}:{}", variant, body)
+    }
+
+    async fn post_redirect() -> AxumRedirect {
+        AxumRedirect::temporary("/echo")
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/echo", post(post_echo))
+            .route("/redirect", post(post_redirect))
+    }
+
+    #[tokio::test]
+    async fn it_should_send_variants_of_a_template() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let template = server.post("/echo").text("hello");
+
+        let first = template
+            .clone()
+            .add_header(X_VARIANT, HeaderValue::from_static("first"))
+            .await;
+        let second = template
+            .clone()
+            .add_header(X_VARIANT, HeaderValue::from_static("second"))
+            .await;
+        let original = template.await;
+
+        first.assert_text("first:hello");
+        second.assert_text("second:hello");
+        original.assert_text(":hello");
+    }
+
+    #[tokio::test]
+    async fn it_should_replay_stream_factory_bodies() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let template = server.post("/echo").body_stream_factory(|| {
+            let chunks = vec![Ok::<_, Infallible>(Bytes::from("a")), Ok(Bytes::from("b"))];
+            iter(chunks)
+        });
+
+        template.clone().await.assert_text(":ab");
+        template.await.assert_text(":ab");
+    }
+
+    #[tokio::test]
+    async fn it_should_follow_redirects_keeping_stream_factory_bodies() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .post("/redirect")
+            .redirect_policy(RedirectPolicy::Limited(1))
+            .body_stream_factory(|| iter(vec![Ok::<_, Infallible>(Bytes::from("again"))]))
+            .await
+            .assert_text(":again");
+    }
+
+    #[tokio::test]
+    async fn it_should_only_send_a_one_shot_stream_once() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+        let request = server
+            .post("/echo")
+            .body_stream(iter(vec![Ok::<_, Infallible>(Bytes::from("once"))]));
+
+        let first = request.clone().await;
+        let second_result = request.try_send().await;
+
+        first.assert_text(":once");
+        assert!(matches!(second_result, Err(Error::InvalidRequest { .. })));
+    }
+}
+
+#[cfg(test)]
+mod test_middleware {
+    use super::*;
+
+    use ::axum::http::header::HeaderName;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::HeaderValue;
+    use ::axum::http::StatusCode;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::futures_util::future::ready;
+    use ::hyper::http::request::Parts as RequestParts;
+    use ::std::sync::atomic::AtomicUsize;
+    use ::std::sync::atomic::Ordering;
+    use ::std::sync::Arc;
+
+    use crate::test_support::new_test_server;
+
+    const X_TRAIL: HeaderName = HeaderName::from_static("x-trail");
+
+    /// Appends its name to the `x-trail` header of the request,
+    /// and then to the `x-trail` header of the response.
+    #[derive(Debug)]
+    struct Trail(&'static str);
+
+    impl Middleware for Trail {
+        fn handle<'a>(&'a self, mut request: HttpRequest, next: Next<'a>) -> MiddlewareFuture<'a> {
+            Box::pin(async move {
+                append_trail(request.headers_mut(), self.0);
+                let mut response = next.run(request).await?;
+                append_trail(response.headers_mut(), self.0);
+
+                Ok(response)
+            })
+        }
+    }
+
+    fn append_trail(headers: &mut HeaderMap, name: &str) {
+        let trail = match headers.get(X_TRAIL) {
+            Some(trail) => format!("{},{}", trail.to_str().unwrap(), name),
+            None => name.to_string(),
+        };
+        headers.insert(X_TRAIL, HeaderValue::from_str(&trail).unwrap());
+    }
+
+    /// Returns a response of its own, without sending the request.
+    #[derive(Debug)]
+    struct ShortCircuit;
+
+    impl Middleware for ShortCircuit {
+        fn handle<'a>(&'a self, _request: HttpRequest, _next: Next<'a>) -> MiddlewareFuture<'a> {
+            Box::pin(async {
+                let response = ::hyper::http::Response::builder()
+                    .status(StatusCode::IM_A_TEAPOT)
+                    .body(Body::from("from middleware"))
+                    .unwrap();
+
+                Ok(response)
+            })
+        }
+    }
+
+    /// Appends `auth` to the `x-trail` header of the request, when authorizing it.
+    #[derive(Debug)]
+    struct AuthTrail;
+
+    impl AuthProvider for AuthTrail {
+        fn authorize<'a>(
+            &'a self,
+            request: &'a mut RequestParts,
+            _body: Option<&'a [u8]>,
+        ) -> AuthFuture<'a, ()> {
+            append_trail(&mut request.headers, "auth");
+            Box::pin(ready(Ok(())))
+        }
+    }
+
+    /// Counts each request that passes through it.
+    #[derive(Debug, Clone, Default)]
+    struct Counter(Arc<AtomicUsize>);
+
+    impl Middleware for Counter {
+        fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> MiddlewareFuture<'a> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            next.run(request)
+        }
+    }
+
+    async fn get_trail(headers: HeaderMap) -> String {
+        headers
+            .get(X_TRAIL)
+            .map(|trail| trail.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    async fn get_redirect() -> ::axum::response::Redirect {
+        ::axum::response::Redirect::to("/trail")
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/trail", get(get_trail))
+            .route("/redirect", get(get_redirect))
+    }
+
+    #[tokio::test]
+    async fn it_should_run_middleware_in_order() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_middleware(Trail("first"))
+            .add_middleware(Trail("second"))
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let response = server.get("/trail").await;
+
+        assert_eq!(response.header(X_TRAIL), "second,first");
+        response.assert_text("first,second");
+    }
+
+    #[tokio::test]
+    async fn it_should_authorize_requests_after_running_middleware() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_middleware(Trail("first"))
+            .add_middleware(Trail("second"))
+            .auth_provider(AuthTrail)
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        let text = server.get("/trail").await.text();
+
+        assert_eq!(text, "first,second,auth");
+    }
+
+    #[tokio::test]
+    async fn it_should_run_middleware_added_to_the_server() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder()
+            .add_middleware(Trail("config"))
+            .build();
+        let mut server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+        server.add_middleware(Trail("added"));
+
+        server.get("/trail").await.assert_text("config,added");
+    }
+
+    #[tokio::test]
+    async fn it_should_return_responses_from_middleware_without_sending() {
+        let config = ServerConfig::builder().add_middleware(ShortCircuit).build();
+        let server = Server::new_with_config("http://127.0.0.1:1".to_string(), config)
+            .expect("Should create server");
+
+        server
+            .get("/trail")
+            .await
+            .assert_status(StatusCode::IM_A_TEAPOT)
+            .assert_text("from middleware");
+    }
+
+    #[tokio::test]
+    async fn it_should_run_middleware_for_each_redirect() {
+        let test_server = new_test_server(router());
+        let counter = Counter::default();
+        let config = ServerConfig::builder()
+            .add_middleware(counter.clone())
+            .build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        server
+            .get("/redirect")
+            .redirect_policy(RedirectPolicy::Limited(1))
+            .await
+            .assert_status_ok();
+
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+    }
+}
+
+#[cfg(test)]
+mod test_auth {
+    use super::*;
+
+    use ::axum::http::header::AUTHORIZATION;
+    use ::axum::http::HeaderMap;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::futures_util::future::ready;
+    use ::hyper::http::request::Parts as RequestParts;
+
+    use crate::test_support::new_test_server;
+
+    /// A provider which never has any credentials to add.
+    #[derive(Debug)]
+    struct NoCredentials;
+
+    impl AuthProvider for NoCredentials {
+        fn authorize<'a>(
+            &'a self,
+            _request: &'a mut RequestParts,
+            _body: Option<&'a [u8]>,
+        ) -> AuthFuture<'a, ()> {
+            Box::pin(ready(Ok(())))
+        }
+    }
+
+    async fn get_authorization(headers: HeaderMap) -> String {
+        headers
+            .get(AUTHORIZATION)
+            .map(|authorization| authorization.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    fn router() -> Router {
+        Router::new().route("/authorization", get(get_authorization))
+    }
+
+    #[tokio::test]
+    async fn it_should_send_bearer_auth() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/authorization")
+            .bearer_auth("my-token")
+            .await
+            .assert_text("Bearer my-token");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_basic_auth() {
+        let test_server = new_test_server(router());
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/authorization")
+            .basic_auth("Aladdin", "open sesame")
+            .await
+            .assert_text("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_auth_from_server_config() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder().bearer_auth("server-token").build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        server
+            .get("/authorization")
+            .await
+            .assert_text("Bearer server-token");
+    }
+
+    #[tokio::test]
+    async fn it_should_send_auth_set_on_the_server() {
+        let test_server = new_test_server(router());
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server.basic_auth("user", "pass");
+
+        server
+            .get("/authorization")
+            .await
+            .assert_text("Basic dXNlcjpwYXNz");
+
+        server.clear_auth();
+        server.get("/authorization").await.assert_text("");
+    }
+
+    #[tokio::test]
+    async fn it_should_replace_server_auth_with_an_auth_provider() {
+        let test_server = new_test_server(router());
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server.bearer_auth("server-token");
+        server.auth_provider(NoCredentials);
+
+        server.get("/authorization").await.assert_text("");
+    }
+
+    #[tokio::test]
+    async fn it_should_replace_server_auth_with_request_auth() {
+        let test_server = new_test_server(router());
+        let config = ServerConfig::builder().bearer_auth("server-token").build();
+        let server = Server::new_with_config(test_server.server_address(), config)
+            .expect("Should create server");
+
+        server
+            .get("/authorization")
+            .bearer_auth("request-token")
+            .await
+            .assert_text("Bearer request-token");
+        server
+            .get("/authorization")
+            .clear_auth()
+            .await
+            .assert_text("");
+    }
+
+    #[tokio::test]
+    async fn it_should_not_show_secrets_in_debug_output() {
+        let config = ServerConfig::builder().bearer_auth("server-token").build();
+        let server = Server::new_with_config("http://localhost:3000".to_string(), config)
+            .expect("Should create server");
+        let request = server.get("/authorization").basic_auth("user", "hunter2");
+
+        let server_debug = format!("{:?}", server);
+        let request_debug = format!("{:?}", request);
+
+        assert!(!server_debug.contains("server-token"));
+        assert!(!request_debug.contains("server-token"));
+        assert!(!request_debug.contains("hunter2"));
+    }
+}
+
+#[cfg(test)]
+mod test_auth_provider {
+    use super::*;
+
+    use ::axum::extract::State;
+    use ::axum::http::header::AUTHORIZATION;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::StatusCode;
+    use ::axum::response::IntoResponse;
+    use ::axum::response::Response as AxumResponse;
+    use ::axum::routing::get;
+    use ::axum::routing::post;
+    use ::axum::Form;
+    use ::axum::Json;
+    use ::axum::Router;
+    use ::axum_test::TestServer;
+    use ::serde_json::json;
+    use ::std::collections::HashMap;
+    use ::std::sync::atomic::AtomicUsize;
+    use ::std::sync::atomic::Ordering;
+    use ::std::sync::Arc;
+    use ::std::sync::Mutex;
+    use ::std::time::Duration;
+    use ::tokio::time::sleep;
+
+    use crate::test_support::new_test_server;
+
+    /// "my-client:my-secret", base64 encoded.
+    const CLIENT_CREDENTIALS: &str = "Basic bXktY2xpZW50Om15LXNlY3JldA==";
+
+    /// A stand-in OAuth2 token endpoint, and a route protected by its tokens.
+    ///
+    /// Only the most recently issued token is accepted.
+    #[derive(Debug, Clone)]
+    struct TokenState {
+        expires_in: u64,
+        token_forms: Arc<Mutex<Vec<HashMap<String, String>>>>,
+        valid_token: Arc<Mutex<Option<String>>>,
+        protected_requests: Arc<AtomicUsize>,
+    }
+
+    impl TokenState {
+        fn token_requests(&self) -> usize {
+            self.token_forms.lock().unwrap().len()
+        }
+
+        fn is_authorized(&self, headers: &HeaderMap) -> Option<String> {
+            let authorization = headers.get(AUTHORIZATION)?.to_str().ok()?;
+            let token = authorization.strip_prefix("Bearer ")?;
+            let valid_token = self.valid_token.lock().unwrap();
+
+            (valid_token.as_deref() == Some(token)).then(|| token.to_string())
+        }
+    }
+
+    async fn route_token(
+        State(state): State<TokenState>,
+        headers: HeaderMap,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> AxumResponse {
+        if headers.get(AUTHORIZATION).map(|value| value.as_bytes())
+            != Some(CLIENT_CREDENTIALS.as_bytes())
+        {
+            let error = json!({ "error": "invalid_client" });
+            return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
+        }
+
+        let mut token_forms = state.token_forms.lock().unwrap();
+        token_forms.push(form);
+        let token_number = token_forms.len();
+
+        let access_token = format!("token-{}", token_number);
+        *state.valid_token.lock().unwrap() = Some(access_token.clone());
+
+        Json(json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": state.expires_in,
+            "refresh_token": format!("refresh-{}", token_number),
+        }))
+        .into_response()
+    }
+
+    async fn route_protected(State(state): State<TokenState>, headers: HeaderMap) -> AxumResponse {
+        state.protected_requests.fetch_add(1, Ordering::SeqCst);
+
+        match state.is_authorized(&headers) {
+            Some(token) => format!("hello {}", token).into_response(),
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    async fn route_always_unauthorized(State(state): State<TokenState>) -> AxumResponse {
+        state.protected_requests.fetch_add(1, Ordering::SeqCst);
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+
+    async fn route_revoke(State(state): State<TokenState>) {
+        *state.valid_token.lock().unwrap() = None;
+    }
+
+    fn new_token_server(expires_in: u64) -> (TestServer, TokenState) {
+        let state = TokenState {
+            expires_in,
+            token_forms: Arc::new(Mutex::new(vec![])),
+            valid_token: Arc::new(Mutex::new(None)),
+            protected_requests: Arc::new(AtomicUsize::new(0)),
+        };
+        let router = Router::new()
+            .route("/oauth/token", post(route_token))
+            .route("/protected", get(route_protected).post(route_protected))
+            .route("/always_unauthorized", get(route_always_unauthorized))
+            .route("/revoke", post(route_revoke))
+            .with_state(state.clone());
+
+        let test_server = new_test_server(router);
+        (test_server, state)
+    }
+
+    fn new_server(test_server: &TestServer, auth_provider: OAuth2Provider) -> Server {
+        let config = ServerConfig::builder().auth_provider(auth_provider).build();
+
+        Server::new_with_config(test_server.server_address(), config).expect("Should create server")
+    }
+
+    fn token_url(test_server: &TestServer) -> String {
+        format!("{}/oauth/token", test_server.server_address())
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_a_token_using_client_credentials() {
+        let (test_server, state) = new_token_server(3600);
+        let auth_provider =
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider")
+                .scopes(["orders:read", "orders:write"]);
+        let server = new_server(&test_server, auth_provider);
+
+        server.get("/protected").await.assert_text("hello token-1");
+
+        let token_forms = state.token_forms.lock().unwrap();
+        assert_eq!(token_forms[0]["grant_type"], "client_credentials");
+        assert_eq!(token_forms[0]["scope"], "orders:read orders:write");
+    }
+
+    #[tokio::test]
+    async fn it_should_reuse_the_token_until_it_expires() {
+        let (test_server, state) = new_token_server(3600);
+        let auth_provider =
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        server.get("/protected").await.assert_text("hello token-1");
+        server.get("/protected").await.assert_text("hello token-1");
+        server.post("/protected").await.assert_text("hello token-1");
+
+        assert_eq!(state.token_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_refresh_the_token_before_it_expires() {
+        let (test_server, state) = new_token_server(1);
+        let auth_provider =
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        // The token is refreshed after half of its one second lifetime.
+        server.get("/protected").await.assert_text("hello token-1");
+        sleep(Duration::from_millis(600)).await;
+        server.get("/protected").await.assert_text("hello token-2");
+
+        // Each token was replaced before the server could reject it.
+        assert_eq!(state.token_requests(), 2);
+        assert_eq!(state.protected_requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_reuse_short_lived_tokens() {
+        let (test_server, state) = new_token_server(10);
+        let auth_provider =
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        server.get("/protected").await.assert_text("hello token-1");
+        server.get("/protected").await.assert_text("hello token-1");
+
+        assert_eq!(state.token_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_reuse_tokens_expiring_too_far_in_the_future() {
+        let (test_server, state) = new_token_server(u64::MAX);
+        let auth_provider =
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        server.get("/protected").await.assert_text("hello token-1");
+        server.get("/protected").await.assert_text("hello token-1");
+
+        assert_eq!(state.token_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_should_use_and_rotate_refresh_tokens() {
+        let (test_server, state) = new_token_server(0);
+        let auth_provider = OAuth2Provider::refresh_token(
+            &token_url(&test_server),
+            "my-client",
+            "my-secret",
+            "initial-refresh",
+        )
+        .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        server.get("/protected").await.assert_text("hello token-1");
+        server.get("/protected").await.assert_text("hello token-2");
+
+        let token_forms = state.token_forms.lock().unwrap();
+        assert_eq!(token_forms[0]["grant_type"], "refresh_token");
+        assert_eq!(token_forms[0]["refresh_token"], "initial-refresh");
+        assert_eq!(token_forms[1]["grant_type"], "refresh_token");
+        assert_eq!(token_forms[1]["refresh_token"], "refresh-1");
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_a_new_token_and_retry_once_on_unauthorized() {
+        let (test_server, state) = new_token_server(3600);
+        let auth_provider =
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        server.get("/protected").await.assert_text("hello token-1");
+        server.post("/revoke").clear_auth().await;
+        server
+            .post("/protected")
+            .text("some body")
+            .await
+            .assert_text("hello token-2");
+
+        assert_eq!(state.token_requests(), 2);
+        assert_eq!(state.protected_requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn it_should_only_retry_unauthorized_requests_once() {
+        let (test_server, state) = new_token_server(3600);
+        let auth_provider =
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        server
+            .get("/always_unauthorized")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        assert_eq!(state.token_requests(), 2);
+        assert_eq!(state.protected_requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_error_when_fetching_the_token_fails() {
+        let (test_server, state) = new_token_server(3600);
+        let auth_provider = OAuth2Provider::client_credentials(
+            &token_url(&test_server),
+            "my-client",
+            "wrong-secret",
+        )
+        .expect("Should create provider");
+        let server = new_server(&test_server, auth_provider);
+
+        let result = server.get("/protected").try_send().await;
+
+        assert!(matches!(result, Err(Error::Auth { .. })));
+        assert_eq!(state.protected_requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn it_should_set_the_auth_provider_on_the_server() {
+        let (test_server, _) = new_token_server(3600);
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server.auth_provider(
+            OAuth2Provider::client_credentials(&token_url(&test_server), "my-client", "my-secret")
+                .expect("Should create provider"),
+        );
+
+        server.get("/protected").await.assert_text("hello token-1");
+
+        server.clear_auth();
+        server
+            .get("/protected")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[cfg(test)]
+mod test_digest_auth {
+    use super::*;
+
+    use ::axum::extract::State;
+    use ::axum::http::header::AUTHORIZATION;
+    use ::axum::http::header::WWW_AUTHENTICATE;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::Method;
+    use ::axum::http::StatusCode;
+    use ::axum::http::Uri;
+    use ::axum::response::IntoResponse;
+    use ::axum::response::Response as AxumResponse;
+    use ::axum::routing::get;
+    use ::axum::Router;
+    use ::axum_test::TestServer;
+    use ::sha2::Digest;
+    use ::sha2::Sha256;
+    use ::std::collections::HashMap;
+    use ::std::sync::Arc;
+    use ::std::sync::Mutex;
+
+    use crate::test_support::new_test_server;
+
+    const REALM: &str = "test@example.org";
+    const PASSWORD: &str = "Circle of Life";
+
+    /// A stand-in for a server using SHA-256 Digest authentication.
+    #[derive(Debug, Clone)]
+    struct DigestState {
+        nonce: Arc<Mutex<String>>,
+        challenges: Arc<Mutex<usize>>,
+        nonce_counts: Arc<Mutex<Vec<String>>>,
+        requests: Arc<Mutex<usize>>,
+    }
+
+    fn sha256_hex(text: &str) -> String {
+        ::hex::encode(Sha256::digest(text))
+    }
+
+    fn parse_authorization(headers: &HeaderMap) -> Option<HashMap<String, String>> {
+        let authorization = headers.get(AUTHORIZATION)?.to_str().ok()?;
+        let params = authorization
+            .strip_prefix("Digest ")?
+            .split(", ")
+            .filter_map(|param| param.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.trim_matches('"').to_string()))
+            .collect();
+
+        Some(params)
+    }
+
+    async fn route_digest(
+        State(state): State<DigestState>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+    ) -> AxumResponse {
+        *state.requests.lock().unwrap() += 1;
+        let nonce = state.nonce.lock().unwrap().clone();
+
+        let mut is_stale = false;
+        if let Some(params) = parse_authorization(&headers) {
+            let ha1 = sha256_hex(&format!("{}:{}:{}", params["username"], REALM, PASSWORD));
+            let ha2 = sha256_hex(&format!("{}:{}", method, params["uri"]));
+            let expected_response = sha256_hex(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, params["nonce"], params["nc"], params["cnonce"], ha2
+            ));
+
+            let is_valid = params["response"] == expected_response
+                && params["uri"] == uri.path_and_query().unwrap().as_str()
+                && params["opaque"] == "xyz";
+            if is_valid && params["nonce"] == nonce {
+                state
+                    .nonce_counts
+                    .lock()
+                    .unwrap()
+                    .push(params["nc"].clone());
+                return format!("hello {}", params["username"]).into_response();
+            }
+
+            is_stale = is_valid;
+        }
+
+        *state.challenges.lock().unwrap() += 1;
+        let challenge = format!(
+            r#"Basic realm="{}", Digest realm="{}", qop="auth", algorithm=SHA-256, nonce="{}", opaque="xyz", stale={}"#,
+            REALM, REALM, nonce, is_stale
+        );
+        (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, challenge)]).into_response()
+    }
+
+    fn new_digest_server() -> (TestServer, DigestState) {
+        let state = DigestState {
+            nonce: Arc::new(Mutex::new("nonce-1".to_string())),
+            challenges: Arc::new(Mutex::new(0)),
+            nonce_counts: Arc::new(Mutex::new(vec![])),
+            requests: Arc::new(Mutex::new(0)),
+        };
+        let router = Router::new()
+            .route("/digest", get(route_digest).post(route_digest))
+            .with_state(state.clone());
+
+        let test_server = new_test_server(router);
+        (test_server, state)
+    }
+
+    #[tokio::test]
+    async fn it_should_answer_the_digest_challenge() {
+        let (test_server, state) = new_digest_server();
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/digest?page=1")
+            .digest_auth("Mufasa", PASSWORD)
+            .await
+            .assert_text("hello Mufasa");
+
+        assert_eq!(*state.challenges.lock().unwrap(), 1);
+        assert_eq!(*state.requests.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_reuse_the_nonce_for_later_requests() {
+        let (test_server, state) = new_digest_server();
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/digest")
+            .digest_auth("Mufasa", PASSWORD)
+            .await
+            .assert_text("hello Mufasa");
+        server
+            .post("/digest")
+            .json(&"some body")
+            .digest_auth("Mufasa", PASSWORD)
+            .await
+            .assert_text("hello Mufasa");
+
+        assert_eq!(*state.challenges.lock().unwrap(), 1);
+        assert_eq!(*state.requests.lock().unwrap(), 3);
+        assert_eq!(
+            *state.nonce_counts.lock().unwrap(),
+            vec!["00000001".to_string(), "00000002".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_answer_a_new_challenge_when_the_nonce_is_stale() {
+        let (test_server, state) = new_digest_server();
+        let mut server = Server::new(test_server.server_address()).expect("Should create server");
+        server.digest_auth("Mufasa", PASSWORD);
+
+        server.get("/digest").await.assert_text("hello Mufasa");
+        *state.nonce.lock().unwrap() = "nonce-2".to_string();
+        server.get("/digest").await.assert_text("hello Mufasa");
+
+        assert_eq!(*state.challenges.lock().unwrap(), 2);
+        assert_eq!(
+            *state.nonce_counts.lock().unwrap(),
+            vec!["00000001".to_string(), "00000001".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_only_answer_the_challenge_once() {
+        let (test_server, state) = new_digest_server();
+        let server = Server::new(test_server.server_address()).expect("Should create server");
+
+        server
+            .get("/digest")
+            .digest_auth("Mufasa", "wrong password")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        assert_eq!(*state.requests.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_should_not_show_the_password_in_debug_output() {
+        let server =
+            Server::new("http://localhost:3000".to_string()).expect("Should create server");
+        let request = server.get("/digest").digest_auth("Mufasa", "hunter2");
+
+        assert!(!format!("{:?}", request).contains("hunter2"));
+    }
+}
+
+#[cfg(test)]
+mod test_sigv4_provider {
+    use super::*;
+
+    use ::axum::body::Bytes;
+    use ::axum::http::header::AUTHORIZATION;
+    use ::axum::http::HeaderMap;
+    use ::axum::http::Method;
+    use ::axum::http::StatusCode;
+    use ::axum::http::Uri;
+    use ::axum::response::IntoResponse;
+    use ::axum::response::Response as AxumResponse;
+    use ::axum::routing::put;
+    use ::axum::Router;
+    use ::axum_test::TestServer;
+    use ::futures_util::future::ready;
+    use ::futures_util::stream::once;
+    use ::hyper::body::to_bytes;
+    use ::hyper::header::HOST;
+    use ::hyper::http::request::Parts as RequestParts;
+    use ::hyper::http::HeaderValue;
+    use ::sha2::Digest;
+    use ::sha2::Sha256;
+    use ::std::convert::Infallible;
+
+    use crate::test_support::new_test_server;
+
+    const ACCESS_KEY_ID: &str = "minio-access-key";
+    const SECRET_ACCESS_KEY: &str = "minio-secret-key";
+    const AMZ_DATE: &str = "20150830T123600Z";
+
+    /// Signs at a fixed time, so the signatures can be checked against precomputed values.
+    #[derive(Debug)]
+    struct FixedDateSigner(SigV4Provider);
+
+    impl AuthProvider for FixedDateSigner {
+        fn authorize<'a>(
+            &'a self,
+            request: &'a mut RequestParts,
+            body: Option<&'a [u8]>,
+        ) -> AuthFuture<'a, ()> {
+            Box::pin(ready(self.0.sign(request, body, AMZ_DATE)))
+        }
+    }
+
+    /// The `Authorization` header expected for each request,
+    /// worked out separately from `SigV4Provider`.
+    fn expected_authorization(method: &Method, uri: &Uri) -> Option<String> {
+        let (signed_headers, signature) = match (method.as_str(), uri.path()) {
+            ("GET", "/bucket/some%20file.txt") => (
+                "host;x-amz-content-sha256;x-amz-date",
+                "6c8197d74a1126f16c259f0dec598aa7c8fec99f2888cf8e7b8e7f3daa5b1ebb",
+            ),
+            ("PUT", "/bucket/hello.txt") => (
+                "content-type;host;x-amz-content-sha256;x-amz-date",
+                "18b49c3189b8ba80c5e1899410a2a4ed309c678fed1128b9c81a9033e95e54e7",
+            ),
+            ("PUT", "/bucket/rewritten.txt") => (
+                "content-type;host;x-amz-content-sha256;x-amz-date",
+                "5944f80b58e71c255b501fa6c501eb74d781a3a592f59e63d0ca16cd4ac973cb",
+            ),
+            ("PUT", "/bucket/streamed.txt") => (
+                "host;x-amz-content-sha256;x-amz-date",
+                "b8ee43de7dd9bd3c6ad901dc07a3b029153c4cbc456f921c54a9e18ed423ed23",
+            ),
+            _ => return None,
+        };
+
+        Some(format!(
+            "AWS4-HMAC-SHA256 Credential={}/20150830/us-east-1/s3/aws4_request, SignedHeaders={}, Signature={}",
+            ACCESS_KEY_ID, signed_headers, signature
+        ))
+    }
+
+    /// A stand-in for S3 compatible storage,
+    /// which checks the request was signed as expected.
+    async fn route_object(
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> AxumResponse {
+        let header_text = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let authorization = header_text(AUTHORIZATION.as_str());
+        if expected_authorization(&method, &uri) != Some(authorization) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+
+        let content_sha256 = header_text("x-amz-content-sha256");
+        if content_sha256 != "UNSIGNED-PAYLOAD"
+            && content_sha256 != hex::encode(Sha256::digest(&body))
+        {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        format!("stored {} bytes, {}", body.len(), content_sha256).into_response()
+    }
+
+    /// Replaces the body with an uppercase copy, before it is signed and sent.
+    #[derive(Debug)]
+    struct UppercaseBody;
+
+    impl Middleware for UppercaseBody {
+        fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> MiddlewareFuture<'a> {
+            Box::pin(async move {
+                let (parts, body) = request.into_parts();
+                let body_bytes = to_bytes(body).await.expect("Should read request body");
+                let body = Body::from(body_bytes.to_ascii_uppercase());
+
+                next.run(HttpRequest::from_parts(parts, body)).await
+            })
+        }
+    }
+
+    fn new_server_with_secret(test_server: &TestServer, secret_access_key: &str) -> Server {
+        let signer = SigV4Provider::new(ACCESS_KEY_ID, secret_access_key, "us-east-1", "s3");
+        let config = ServerConfig::builder()
+            .add_default_header(HOST, HeaderValue::from_static("s3.example.com"))
+            .auth_provider(FixedDateSigner(signer))
+            .build();
+
+        Server::new_with_config(test_server.server_address(), config).expect("Should create server")
+    }
+
+    fn new_server() -> (TestServer, Server) {
+        let router = Router::new().route("/bucket/*key", put(route_object).get(route_object));
+        let test_server = new_test_server(router);
+        let server = new_server_with_secret(&test_server, SECRET_ACCESS_KEY);
+
+        (test_server, server)
+    }
+
+    #[tokio::test]
+    async fn it_should_sign_requests_without_a_body() {
+        let (_test_server, server) = new_server();
+
+        server
+            .get("/bucket/some%20file.txt")
+            .add_query_param("versionId", "2")
+            .add_query_param("acl", "")
+            .add_query_param("prefix", "my photos")
+            .await
+            .assert_text(
+                "stored 0 bytes, e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            );
+    }
+
+    #[tokio::test]
+    async fn it_should_sign_the_payload_of_bodies_in_memory() {
+        let (_test_server, server) = new_server();
+
+        server
+            .put("/bucket/hello.txt")
+            .text("hello world")
+            .await
+            .assert_text(
+                "stored 11 bytes, b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            );
+    }
+
+    #[tokio::test]
+    async fn it_should_sign_the_body_as_changed_by_middleware() {
+        let (_test_server, mut server) = new_server();
+        server.add_middleware(UppercaseBody);
+
+        server
+            .put("/bucket/rewritten.txt")
+            .text("hello world")
+            .await
+            .assert_text(
+                "stored 11 bytes, 787ec76dcafd20c1908eb0936a12f91edd105ab5cd7ecc2b1ae2032648345dff",
+            );
+    }
+
+    #[tokio::test]
+    async fn it_should_send_streamed_bodies_as_unsigned_payloads() {
+        let (_test_server, server) = new_server();
+        let stream = once(async { Ok::<_, Infallible>(Bytes::from_static(b"streamed")) });
+
+        server
+            .put("/bucket/streamed.txt")
+            .body_stream(stream)
+            .await
+            .assert_text("stored 8 bytes, UNSIGNED-PAYLOAD");
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_requests_signed_with_the_wrong_secret() {
+        let (test_server, _) = new_server();
+        let server = new_server_with_secret(&test_server, "wrong-secret");
+
+        server
+            .put("/bucket/hello.txt")
+            .text("hello world")
+            .expect_failure()
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+    }
+}
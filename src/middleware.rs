@@ -0,0 +1,218 @@
+use ::futures_util::future::BoxFuture;
+use ::hyper::body::to_bytes;
+use ::hyper::http::request::Parts as RequestParts;
+use ::std::fmt::Debug;
+use ::std::sync::Arc;
+use ::std::sync::Mutex;
+use ::std::time::Duration;
+
+use crate::is_connect_timeout;
+use crate::AuthProvider;
+use crate::Error;
+use crate::HttpClient;
+use crate::TimeoutError;
+use crate::TimeoutKind;
+
+pub use ::hyper::Body;
+
+/// The request passed through each `Middleware`, as it is about to be sent.
+pub type HttpRequest = ::hyper::http::Request<Body>;
+
+/// The response passed back through each `Middleware`, once the headers have arrived.
+pub type HttpResponse = ::hyper::http::Response<Body>;
+
+/// The future returned from `Middleware::handle`, and `Next::run`.
+pub type MiddlewareFuture<'a> = BoxFuture<'a, Result<HttpResponse, Error>>;
+
+/// A hook run around sending each request, for adding things like
+/// authentication, logging, signing, or metrics, in one place.
+///
+/// Middleware are added to a `Server`, using `ServerConfigBuilder::add_middleware`
+/// or `Server::add_middleware`. They run in the order they were added.
+///
+/// Each is given the request just before it is sent, with all of the headers and cookies in place.
+/// It can change the request and pass it on by calling `Next::run`,
+/// and then inspect or change the response it gets back.
+/// It can also return a response of its own, without calling `Next::run`,
+/// to stop the request from being sent.
+///
+/// Middleware are run for every request sent to the server.
+/// This includes each redirect followed, and each retry.
+///
+/// Any `AuthProvider` runs after all of the middleware, just before the request is sent.
+/// So changes made by middleware, such as adding headers or replacing the body,
+/// are included in signatures.
+///
+/// ```rust
+/// use ::kantan::http::HeaderValue;
+/// use ::kantan::HttpRequest;
+/// use ::kantan::Middleware;
+/// use ::kantan::MiddlewareFuture;
+/// use ::kantan::Next;
+///
+/// #[derive(Debug)]
+/// struct AddApiKey;
+///
+/// impl Middleware for AddApiKey {
+///     fn handle<'a>(&'a self, mut request: HttpRequest, next: Next<'a>) -> MiddlewareFuture<'a> {
+///         request
+///             .headers_mut()
+///             .insert("x-api-key", HeaderValue::from_static("my-api-key"));
+///
+///         next.run(request)
+///     }
+/// }
+/// ```
+///
+/// To do work after the response arrives, return a boxed `async` block.
+///
+/// ```rust
+/// use ::std::time::Instant;
+/// use ::kantan::HttpRequest;
+/// use ::kantan::Middleware;
+/// use ::kantan::MiddlewareFuture;
+/// use ::kantan::Next;
+///
+/// #[derive(Debug)]
+/// struct LogTiming;
+///
+/// impl Middleware for LogTiming {
+///     fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> MiddlewareFuture<'a> {
+///         Box::pin(async move {
+///             let path = request.uri().clone();
+///             let start = Instant::now();
+///             let response = next.run(request).await?;
+///             println!("{} took {:?}", path, start.elapsed());
+///
+///             Ok(response)
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware: Debug + Send + Sync {
+    /// Handles the request, passing it on with `Next::run` to send it.
+    fn handle<'a>(&'a self, request: HttpRequest, next: Next<'a>) -> MiddlewareFuture<'a>;
+}
+
+/// The rest of the middleware chain, passed to `Middleware::handle`.
+///
+/// Calling `Next::run` passes the request on to the next `Middleware`,
+/// or sends it to the server if this was the last.
+#[derive(Debug)]
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    auth_provider: Option<&'a dyn AuthProvider>,
+    is_body_in_memory: bool,
+    authorized_request: Option<&'a Mutex<Option<RequestParts>>>,
+    client: &'a HttpClient,
+    connect_timeout: Option<Duration>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middleware: &'a [Arc<dyn Middleware>],
+        client: &'a HttpClient,
+        connect_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            middleware,
+            auth_provider: None,
+            is_body_in_memory: false,
+            authorized_request: None,
+            client,
+            connect_timeout,
+        }
+    }
+
+    /// Sets the provider which authorizes the request, after all of the middleware have run.
+    ///
+    /// When the body is held in memory, the body left after the middleware
+    /// is passed on to `AuthProvider::authorize`. Streamed bodies are not read.
+    ///
+    /// The request is stored in `authorized_request` once it has been authorized,
+    /// without its body, for passing to `AuthProvider::retry_unauthorized`.
+    pub(crate) fn auth_provider(
+        mut self,
+        auth_provider: &'a dyn AuthProvider,
+        is_body_in_memory: bool,
+        authorized_request: &'a Mutex<Option<RequestParts>>,
+    ) -> Self {
+        self.auth_provider = Some(auth_provider);
+        self.is_body_in_memory = is_body_in_memory;
+        self.authorized_request = Some(authorized_request);
+        self
+    }
+
+    /// Runs the rest of the chain, returning the response once its headers arrive.
+    pub fn run(self, request: HttpRequest) -> MiddlewareFuture<'a> {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => {
+                let next = Self {
+                    middleware: rest,
+                    ..self
+                };
+
+                middleware.handle(request, next)
+            }
+            None => Box::pin(self.send(request)),
+        }
+    }
+
+    /// Authorizes the request, if there is an `AuthProvider`, and sends it to the server.
+    async fn send(self, mut request: HttpRequest) -> Result<HttpResponse, Error> {
+        if let Some(auth_provider) = self.auth_provider {
+            let (mut request_parts, mut request_body) = request.into_parts();
+
+            // The body is read as it is now, as middleware may have replaced it.
+            if self.is_body_in_memory {
+                let body_bytes = to_bytes(request_body).await.map_err(|err| Error::Connect {
+                    method: request_parts.method.clone(),
+                    request_path: request_parts.uri.clone(),
+                    source: err,
+                })?;
+
+                auth_provider
+                    .authorize(&mut request_parts, Some(&body_bytes))
+                    .await?;
+                request_body = Body::from(body_bytes);
+            } else {
+                auth_provider.authorize(&mut request_parts, None).await?;
+            }
+
+            if let Some(authorized_request) = self.authorized_request {
+                if let Ok(mut authorized_request) = authorized_request.lock() {
+                    *authorized_request = Some(clone_parts(&request_parts));
+                }
+            }
+
+            request = HttpRequest::from_parts(request_parts, request_body);
+        }
+
+        let method = request.method().clone();
+        let request_path = request.uri().clone();
+
+        match (self.client.request(request).await, self.connect_timeout) {
+            (Ok(response), _) => Ok(response),
+            (Err(err), Some(duration)) if is_connect_timeout(&err) => {
+                Err(TimeoutError::new(TimeoutKind::Connect, duration, method, request_path).into())
+            }
+            (Err(err), _) => Err(Error::Connect {
+                method,
+                request_path,
+                source: err,
+            }),
+        }
+    }
+}
+
+/// Copies the method, URI, version, and headers of a request.
+/// Extensions cannot be cloned, and are left out.
+fn clone_parts(request_parts: &RequestParts) -> RequestParts {
+    let (mut parts, ()) = ::hyper::http::Request::new(()).into_parts();
+    parts.method = request_parts.method.clone();
+    parts.uri = request_parts.uri.clone();
+    parts.version = request_parts.version;
+    parts.headers = request_parts.headers.clone();
+
+    parts
+}
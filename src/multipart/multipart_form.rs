@@ -0,0 +1,94 @@
+use ::anyhow::Result;
+use ::futures_util::future::ready;
+use ::futures_util::stream::iter;
+use ::futures_util::stream::once;
+use ::futures_util::StreamExt;
+use ::hyper::body::Body;
+use ::hyper::body::Bytes;
+
+use crate::random_u64;
+use crate::Part;
+
+/// A `multipart/form-data` body, for sending with `Request::multipart`.
+///
+/// Parts from files are streamed as the request is sent,
+/// rather than being read into memory up front.
+///
+/// ```rust
+/// use ::kantan::MultipartForm;
+/// use ::kantan::Part;
+///
+/// let form = MultipartForm::new()
+///     .add_text("username", "Joe")
+///     .add_part("avatar", Part::file("avatar.png"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultipartForm {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+impl MultipartForm {
+    /// Creates a new form, with no parts, and a randomly generated boundary.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            boundary: generate_boundary(),
+            parts: vec![],
+        }
+    }
+
+    /// Adds a text field to the form.
+    pub fn add_text<T>(self, name: &str, text: T) -> Self
+    where
+        T: ToString,
+    {
+        self.add_part(name, Part::text(text))
+    }
+
+    /// Adds a part to the form, under the name given.
+    pub fn add_part(mut self, name: &str, part: Part) -> Self {
+        self.parts.push((name.to_string(), part));
+        self
+    }
+
+    /// The boundary placed between each part.
+    #[must_use]
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The content type to send this form with, including the boundary.
+    pub(crate) fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Builds the body to send, which streams each part in turn.
+    ///
+    /// Any files are opened up front, returning an error if one cannot be opened.
+    /// This can be called multiple times, such as when following redirects.
+    pub(crate) async fn to_body(&self) -> Result<Body> {
+        let mut streams = vec![];
+        for (name, part) in self.parts.iter() {
+            let header_bytes = part.header_bytes(&self.boundary, name);
+            streams.push(once(ready(Ok(header_bytes))).boxed());
+            streams.push(part.body_stream().await?);
+            streams.push(once(ready(Ok(Bytes::from_static(b"\r\n")))).boxed());
+        }
+
+        let closing_bytes = Bytes::from(format!("--{}--\r\n", self.boundary));
+        streams.push(once(ready(Ok(closing_bytes))).boxed());
+
+        Ok(Body::wrap_stream(iter(streams).flatten()))
+    }
+}
+
+impl Default for MultipartForm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn generate_boundary() -> String {
+    format!("{:016x}{:016x}", random_u64(), random_u64())
+}
@@ -0,0 +1,189 @@
+use ::anyhow::anyhow;
+use ::anyhow::Result;
+use ::futures_util::future::ready;
+use ::futures_util::stream::once;
+use ::futures_util::stream::BoxStream;
+use ::futures_util::StreamExt;
+use ::hyper::body::Bytes;
+use ::hyper::http::header;
+use ::hyper::http::header::HeaderName;
+use ::hyper::http::HeaderValue;
+use ::std::io::Result as IoResult;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::tokio::fs::File;
+use ::tokio_util::io::ReaderStream;
+
+/// A single part within a `MultipartForm`.
+///
+/// ```rust
+/// use ::kantan::Part;
+///
+/// let part = Part::bytes(vec![0x89, 0x50, 0x4e, 0x47])
+///     .file_name("avatar.png")
+///     .mime_type("image/png");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Part {
+    body: PartBody,
+    file_name: Option<String>,
+    mime_type: Option<String>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+#[derive(Debug, Clone)]
+enum PartBody {
+    Bytes(Bytes),
+    File(PathBuf),
+}
+
+impl Part {
+    /// Creates a part holding the text given.
+    pub fn text<T>(text: T) -> Self
+    where
+        T: ToString,
+    {
+        Self::new(PartBody::Bytes(text.to_string().into()))
+    }
+
+    /// Creates a part holding the raw bytes given.
+    ///
+    /// No content type is sent for the part, unless one is set with `Part::mime_type`.
+    pub fn bytes<B>(bytes: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        Self::new(PartBody::Bytes(bytes.into()))
+    }
+
+    /// Creates a part which sends the contents of the file at the path given.
+    ///
+    /// The file is streamed when the request is sent, rather than read into memory.
+    /// The file name and MIME type are taken from the path,
+    /// and can be replaced using `Part::file_name` and `Part::mime_type`.
+    pub fn file<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string());
+        let mime_type = ::mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string();
+
+        Self {
+            file_name,
+            mime_type: Some(mime_type),
+            ..Self::new(PartBody::File(path.to_path_buf()))
+        }
+    }
+
+    fn new(body: PartBody) -> Self {
+        Self {
+            body,
+            file_name: None,
+            mime_type: None,
+            headers: vec![],
+        }
+    }
+
+    /// Sets the file name sent for this part.
+    pub fn file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    /// Sets the content type sent for this part.
+    pub fn mime_type(mut self, mime_type: &str) -> Self {
+        self.mime_type = Some(mime_type.to_string());
+        self
+    }
+
+    /// Adds a header to send with this part.
+    ///
+    /// Adding a `Content-Type` or `Content-Disposition` header
+    /// will replace the one built for the part.
+    pub fn add_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Builds the boundary line and headers, which are sent before the body of this part.
+    pub(crate) fn header_bytes(&self, boundary: &str, name: &str) -> Bytes {
+        let mut header_bytes = format!("--{}\r\n", boundary).into_bytes();
+
+        if !self.has_header(&header::CONTENT_DISPOSITION) {
+            let mut disposition = format!("form-data; name=\"{}\"", escape_quoted(name));
+            if let Some(file_name) = self.file_name.as_ref() {
+                disposition.push_str(&format!("; filename=\"{}\"", escape_quoted(file_name)));
+            }
+            push_header_line(
+                &mut header_bytes,
+                header::CONTENT_DISPOSITION.as_str(),
+                disposition.as_bytes(),
+            );
+        }
+
+        if let Some(mime_type) = self.mime_type.as_ref() {
+            if !self.has_header(&header::CONTENT_TYPE) {
+                push_header_line(
+                    &mut header_bytes,
+                    header::CONTENT_TYPE.as_str(),
+                    mime_type.as_bytes(),
+                );
+            }
+        }
+
+        for (header_name, header_value) in self.headers.iter() {
+            push_header_line(
+                &mut header_bytes,
+                header_name.as_str(),
+                header_value.as_bytes(),
+            );
+        }
+
+        header_bytes.extend_from_slice(b"\r\n");
+        header_bytes.into()
+    }
+
+    /// Returns the body of this part as a stream.
+    ///
+    /// Files are opened here, so a missing file is found before the request is sent.
+    pub(crate) async fn body_stream(&self) -> Result<BoxStream<'static, IoResult<Bytes>>> {
+        match &self.body {
+            PartBody::Bytes(bytes) => Ok(once(ready(Ok(bytes.clone()))).boxed()),
+            PartBody::File(path) => {
+                let file = File::open(path)
+                    .await
+                    .map_err(|err| anyhow!("Failed to open file {}, {}", path.display(), err))?;
+
+                Ok(ReaderStream::new(file).boxed())
+            }
+        }
+    }
+
+    fn has_header(&self, name: &HeaderName) -> bool {
+        self.headers
+            .iter()
+            .any(|(header_name, _)| header_name == name)
+    }
+}
+
+fn push_header_line(header_bytes: &mut Vec<u8>, name: &str, value: &[u8]) {
+    header_bytes.extend_from_slice(name.as_bytes());
+    header_bytes.extend_from_slice(b": ");
+    header_bytes.extend_from_slice(value);
+    header_bytes.extend_from_slice(b"\r\n");
+}
+
+/// Escapes names and file names, for use within a quoted string.
+///
+/// This follows the encoding used by browsers, in the HTML specification.
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
@@ -0,0 +1,20 @@
+use ::std::collections::hash_map::RandomState;
+use ::std::hash::BuildHasher;
+use ::std::hash::Hasher;
+use ::std::time::SystemTime;
+use ::std::time::UNIX_EPOCH;
+
+/// Returns a random number, for jitter and boundaries.
+///
+/// This uses the random keys from the standard library's `RandomState`,
+/// mixed with the current time. It is not suitable for cryptographic keys or nonces.
+pub(crate) fn random_u64() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(nanos);
+    hasher.finish()
+}
@@ -0,0 +1,356 @@
+use ::anyhow::anyhow;
+use ::anyhow::Result;
+use ::hyper::http::uri::Parts as UriParts;
+use ::hyper::http::uri::PathAndQuery;
+use ::hyper::http::Method;
+use ::hyper::http::StatusCode;
+use ::hyper::Uri;
+
+/// Decides which redirect responses a `Request` will follow.
+///
+/// When a redirect is not followed, the redirect response is returned as is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RedirectPolicy {
+    /// Redirects are never followed.
+    #[default]
+    None,
+
+    /// Follows up to the given number of redirects.
+    ///
+    /// Going over the limit will fail with an `Error::TooManyRedirects`.
+    Limited(usize),
+
+    /// Follows up to the given number of redirects,
+    /// but only whilst they stay on the same origin (scheme, host, and port)
+    /// as the original request.
+    ///
+    /// Going over the limit will fail with an `Error::TooManyRedirects`.
+    SameOrigin(usize),
+}
+
+impl RedirectPolicy {
+    /// Returns true if a redirect to the next URI should be followed,
+    /// for a request originally sent to the URI given.
+    pub(crate) fn is_following(&self, original_uri: &Uri, next_uri: &Uri) -> bool {
+        match self {
+            Self::None => false,
+            Self::Limited(_) => true,
+            Self::SameOrigin(_) => is_same_origin(original_uri, next_uri),
+        }
+    }
+
+    pub(crate) fn max_redirects(&self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Limited(max) => *max,
+            Self::SameOrigin(max) => *max,
+        }
+    }
+}
+
+/// A redirect which was followed, when sending a `Request`.
+///
+/// These are found on the `Response` from `Response::redirect_history`.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    request_method: Method,
+    request_uri: Uri,
+    status_code: StatusCode,
+    location: Uri,
+}
+
+impl Redirect {
+    pub(crate) fn new(
+        request_method: Method,
+        request_uri: Uri,
+        status_code: StatusCode,
+        location: Uri,
+    ) -> Self {
+        Self {
+            request_method,
+            request_uri,
+            status_code,
+            location,
+        }
+    }
+
+    /// The method of the request which was redirected.
+    #[must_use]
+    pub fn request_method(&self) -> &Method {
+        &self.request_method
+    }
+
+    /// The URI of the request which was redirected.
+    #[must_use]
+    pub fn request_uri(&self) -> &Uri {
+        &self.request_uri
+    }
+
+    /// The redirect status code returned, such as `302 Found`.
+    #[must_use]
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    /// Where the request was redirected to.
+    #[must_use]
+    pub fn location(&self) -> &Uri {
+        &self.location
+    }
+}
+
+/// Returns true for the status codes where the `Location` header is followed.
+pub(crate) fn is_followable_redirect(status_code: StatusCode) -> bool {
+    matches!(
+        status_code,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+/// Returns the method to use when following the redirect,
+/// and if the body should be sent again.
+///
+/// A `303 See Other` always switches to `GET` (apart from `HEAD` requests),
+/// and `301` and `302` switch `POST` requests to `GET` (as browsers do).
+/// The `307` and `308` redirects always keep the method and body.
+pub(crate) fn redirect_method(status_code: StatusCode, method: Method) -> (Method, bool) {
+    match status_code {
+        StatusCode::SEE_OTHER if method == Method::HEAD => (method, false),
+        StatusCode::SEE_OTHER => (Method::GET, false),
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND if method == Method::POST => {
+            (Method::GET, false)
+        }
+        _ => (method, true),
+    }
+}
+
+/// Returns true if both have the same scheme, host, and port.
+///
+/// A missing port is the same as the default port for the scheme,
+/// so `http://example.com/` and `http://example.com:80/` are the same origin.
+pub(crate) fn is_same_origin(a: &Uri, b: &Uri) -> bool {
+    a.scheme() == b.scheme()
+        && a.host().map(|host| host.to_ascii_lowercase())
+            == b.host().map(|host| host.to_ascii_lowercase())
+        && origin_port(a) == origin_port(b)
+}
+
+/// Returns the port of the URI, or the default port for its scheme when none is given.
+fn origin_port(uri: &Uri) -> Option<u16> {
+    uri.port_u16().or_else(|| match uri.scheme_str() {
+        Some("http") => Some(80),
+        Some("https") => Some(443),
+        _ => None,
+    })
+}
+
+/// Resolves the `Location` of a redirect, against the URI which was requested.
+///
+/// Any `.` and `..` segments are removed from the path,
+/// following section 5.2 of RFC 3986.
+pub(crate) fn resolve_location(base: &Uri, location: &str) -> Result<Uri> {
+    if let Ok(location_uri) = location.parse::<Uri>() {
+        if location_uri.scheme().is_some() {
+            return without_dot_segments(location_uri.into_parts(), location);
+        }
+    }
+
+    let mut parts = UriParts::default();
+    parts.scheme = base.scheme().cloned();
+
+    if let Some(scheme_relative) = location.strip_prefix("//") {
+        let (authority, path) = match scheme_relative.find('/') {
+            Some(index) => scheme_relative.split_at(index),
+            None => (scheme_relative, "/"),
+        };
+        parts.authority = Some(authority.parse()?);
+        parts.path_and_query = Some(path.parse()?);
+
+        return without_dot_segments(parts, location);
+    }
+
+    parts.authority = base.authority().cloned();
+    let path_and_query: PathAndQuery = if location.starts_with('/') {
+        location.parse()?
+    } else if location.starts_with('?') {
+        format!("{}{}", base.path(), location).parse()?
+    } else {
+        let base_path = base.path();
+        let base_directory = &base_path[..=base_path.rfind('/').unwrap_or(0)];
+        let base_directory = if base_directory.is_empty() {
+            "/"
+        } else {
+            base_directory
+        };
+
+        format!("{}{}", base_directory, location).parse()?
+    };
+    parts.path_and_query = Some(path_and_query);
+
+    without_dot_segments(parts, location)
+}
+
+/// Builds the URI from the parts given, with the `.` and `..` segments removed from its path.
+fn without_dot_segments(mut parts: UriParts, location: &str) -> Result<Uri> {
+    if let Some(path_and_query) = parts.path_and_query.take() {
+        let path = remove_dot_segments(path_and_query.path());
+        let path_and_query = match path_and_query.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        parts.path_and_query = Some(path_and_query.parse()?);
+    }
+
+    Uri::from_parts(parts)
+        .map_err(|err| anyhow!("Invalid redirect location '{}', {}", location, err))
+}
+
+/// Removes the `.` and `..` segments from an absolute path.
+///
+/// This follows section 5.2.4 of RFC 3986.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments = vec![];
+    let mut is_ending_in_dot_segment = false;
+
+    for segment in path.split('/').skip(1) {
+        is_ending_in_dot_segment = true;
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => {
+                is_ending_in_dot_segment = false;
+                segments.push(segment);
+            }
+        }
+    }
+
+    let mut path = format!("/{}", segments.join("/"));
+    if is_ending_in_dot_segment && !segments.is_empty() {
+        path.push('/');
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod test_is_same_origin {
+    use super::*;
+
+    fn is_same(a: &str, b: &str) -> bool {
+        is_same_origin(&a.parse().unwrap(), &b.parse().unwrap())
+    }
+
+    #[test]
+    fn it_should_match_the_same_scheme_host_and_port() {
+        assert!(is_same("http://example.com/a", "http://EXAMPLE.com/b"));
+        assert!(is_same(
+            "http://example.com:3000/a",
+            "http://example.com:3000/b"
+        ));
+    }
+
+    #[test]
+    fn it_should_match_implicit_and_explicit_default_ports() {
+        assert!(is_same("http://example.com/a", "http://example.com:80/b"));
+        assert!(is_same(
+            "https://example.com:443/a",
+            "https://example.com/b"
+        ));
+    }
+
+    #[test]
+    fn it_should_not_match_different_schemes_hosts_or_ports() {
+        assert!(!is_same("http://example.com/a", "https://example.com/a"));
+        assert!(!is_same("http://example.com/a", "http://other.com/a"));
+        assert!(!is_same(
+            "http://example.com/a",
+            "http://example.com:3000/a"
+        ));
+        assert!(!is_same(
+            "https://example.com/a",
+            "https://example.com:80/a"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test_resolve_location {
+    use super::*;
+
+    fn resolve(base: &str, location: &str) -> String {
+        let base_uri: Uri = base.parse().unwrap();
+        resolve_location(&base_uri, location).unwrap().to_string()
+    }
+
+    #[test]
+    fn it_should_use_absolute_locations_as_is() {
+        let uri = resolve("http://example.com/a/b", "https://other.com/c");
+        assert_eq!(uri, "https://other.com/c");
+    }
+
+    #[test]
+    fn it_should_resolve_scheme_relative_locations() {
+        let uri = resolve("https://example.com/a/b", "//other.com/c?d=1");
+        assert_eq!(uri, "https://other.com/c?d=1");
+    }
+
+    #[test]
+    fn it_should_resolve_absolute_paths() {
+        let uri = resolve("http://example.com:3000/a/b?q=1", "/login");
+        assert_eq!(uri, "http://example.com:3000/login");
+    }
+
+    #[test]
+    fn it_should_resolve_relative_paths() {
+        let uri = resolve("http://example.com/a/b", "c?d=1");
+        assert_eq!(uri, "http://example.com/a/c?d=1");
+    }
+
+    #[test]
+    fn it_should_remove_dot_segments_from_relative_paths() {
+        assert_eq!(
+            resolve("http://example.com/a/b", "../login"),
+            "http://example.com/login"
+        );
+        assert_eq!(
+            resolve("http://example.com/a/b/c", "./d/../e?f=1"),
+            "http://example.com/a/b/e?f=1"
+        );
+        assert_eq!(
+            resolve("http://example.com/a/b", "../../../login"),
+            "http://example.com/login"
+        );
+        assert_eq!(
+            resolve("http://example.com/a/b/c", ".."),
+            "http://example.com/a/"
+        );
+        assert_eq!(
+            resolve("http://example.com/a/b", "."),
+            "http://example.com/a/"
+        );
+    }
+
+    #[test]
+    fn it_should_remove_dot_segments_from_absolute_locations() {
+        assert_eq!(
+            resolve("http://example.com/a/b", "/c/./d/../e"),
+            "http://example.com/c/e"
+        );
+        assert_eq!(
+            resolve("http://example.com/a/b", "https://other.com/c/../d"),
+            "https://other.com/d"
+        );
+    }
+
+    #[test]
+    fn it_should_resolve_query_only_locations() {
+        let uri = resolve("http://example.com/a/b?q=1", "?page=2");
+        assert_eq!(uri, "http://example.com/a/b?page=2");
+    }
+}
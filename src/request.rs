@@ -1,36 +1,73 @@
-use ::anyhow::anyhow;
 use ::anyhow::Context;
-use ::anyhow::Result;
+use ::anyhow::Result as AnyhowResult;
 use ::auto_future::AutoFuture;
 use ::cookie::Cookie;
 use ::cookie::CookieJar;
-use ::hyper::body::to_bytes;
+use ::futures_util::Stream;
 use ::hyper::body::Body;
 use ::hyper::body::Bytes;
 use ::hyper::header;
 use ::hyper::header::HeaderName;
+use ::hyper::http::header::LOCATION;
 use ::hyper::http::header::SET_COOKIE;
+use ::hyper::http::response::Parts;
+use ::hyper::http::HeaderMap;
 use ::hyper::http::HeaderValue;
+use ::hyper::http::Method;
 use ::hyper::http::Request as HyperRequest;
-use ::hyper::Client;
-use ::hyper_tls::HttpsConnector;
+use ::hyper::http::StatusCode;
+use ::hyper::Uri;
 use ::serde::Serialize;
 use ::serde_json::to_vec as json_to_vec;
-use ::std::convert::AsRef;
 use ::std::fmt::Debug;
 use ::std::fmt::Display;
+use ::std::future::Future;
 use ::std::future::IntoFuture;
+use ::std::path::Path;
 use ::std::sync::Arc;
 use ::std::sync::Mutex;
+use ::std::time::Duration;
+use ::tokio::io::AsyncRead;
+use ::tokio::time::sleep;
+use ::tokio::time::timeout;
+use ::tokio_util::io::ReaderStream;
 
+use crate::accept_encoding_header;
+use crate::discard_body;
+use crate::download_response;
+use crate::find_resume_point;
+use crate::is_followable_redirect;
+use crate::is_same_origin;
+use crate::redirect_method;
+use crate::resolve_location;
+use crate::Authorization;
+use crate::BoxError;
+use crate::DigestAuth;
+use crate::Download;
+use crate::Encoding;
+use crate::Error;
+use crate::ExpectedStatus;
+use crate::HttpClient;
 use crate::InnerServer;
+use crate::MultipartForm;
+use crate::Next;
+use crate::Redirect;
+use crate::RedirectPolicy;
 use crate::Response;
+use crate::RetryPolicy;
+use crate::StreamingResponse;
+use crate::TimeoutError;
+use crate::TimeoutKind;
+
+mod request_body;
+pub(crate) use self::request_body::*;
 
 mod request_config;
 pub(crate) use self::request_config::*;
 
 const JSON_CONTENT_TYPE: &'static str = &"application/json";
 const TEXT_CONTENT_TYPE: &'static str = &"text/plain";
+const FORM_CONTENT_TYPE: &'static str = &"application/x-www-form-urlencoded";
 
 ///
 /// A `Request` represents a HTTP request to the test server.
@@ -53,6 +90,13 @@ const TEXT_CONTENT_TYPE: &'static str = &"text/plain";
 /// such as json, text, bytes, expect_failure, content_type, etc.
 /// The do_save_cookies and do_not_save_cookies methods are used to control cookie handling.
 ///
+/// When the same header is set in multiple places, then the last of these wins:
+///
+///  1. Headers on the `Server`, from `ServerConfig::default_headers` and `Server::add_header`.
+///  2. The user agent, content type, and credentials, from the `ServerConfig` and then the `Request`.
+///  3. Headers on the `Request`, from `Request::add_header` and `Request::headers`.
+///  4. The credentials from an `AuthProvider`, added just before the request is sent.
+///
 /// ## Sending
 ///
 /// Once fully configured you send the rquest by awaiting the request object.
@@ -64,46 +108,52 @@ const TEXT_CONTENT_TYPE: &'static str = &"text/plain";
 ///
 /// You will receive back a `Response`.
 ///
-#[derive(Debug)]
+/// ## Cloning
+///
+/// A `Request` can be cloned, to build a template once and then send variations of it.
+///
+/// ```rust,ignore
+/// let template = server.post(&"/events").json(&event);
+/// let first = template.clone().add_header(name, value).await;
+/// let second = template.await;
+/// ```
+///
+/// Bodies are kept so they can be sent again, by each clone.
+/// The exception is a one-shot stream from `Request::reader` or `Request::body_stream`,
+/// which only the first clone to be sent can use.
+///
+#[derive(Debug, Clone)]
 #[must_use = "futures do nothing unless polled"]
 pub struct Request {
     config: RequestConfig,
 
     inner_test_server: Arc<Mutex<InnerServer>>,
 
-    body: Option<Body>,
+    body: Option<RequestBody>,
     headers: Vec<(HeaderName, HeaderValue)>,
+    query_params: Vec<(String, String)>,
     cookies: CookieJar,
 
     is_saving_cookies: bool,
+    is_using_server_cookies: bool,
 }
 
 impl Request {
     pub(crate) fn new(
         inner_test_server: Arc<Mutex<InnerServer>>,
         config: RequestConfig,
-    ) -> Result<Self> {
+    ) -> AnyhowResult<Self> {
         let is_saving_cookies = config.save_cookies;
-        let server_locked = inner_test_server.as_ref().lock().map_err(|err| {
-            anyhow!(
-                "Failed to lock InternalServer for {} {}, received {:?}",
-                config.method,
-                config.request_path,
-                err
-            )
-        })?;
-
-        let cookies = server_locked.cookies().clone();
-
-        ::std::mem::drop(server_locked);
 
         Ok(Self {
             config,
             inner_test_server,
             body: None,
             headers: vec![],
-            cookies,
+            query_params: vec![],
+            cookies: CookieJar::new(),
             is_saving_cookies,
+            is_using_server_cookies: true,
         })
     }
 
@@ -125,25 +175,126 @@ impl Request {
     }
 
     /// Clears all cookies used internally within this Request.
+    ///
+    /// The cookies stored on the `Server` will not be sent with this request.
     pub fn clear_cookies(mut self) -> Self {
         self.cookies = CookieJar::new();
+        self.is_using_server_cookies = false;
         self
     }
 
     /// Adds a Cookie to be sent with this request.
+    ///
+    /// It is sent alongside the cookies stored on the `Server` which match the request,
+    /// and is not sent on redirects to other origins.
     pub fn add_cookie<'c>(mut self, cookie: Cookie<'c>) -> Self {
         self.cookies.add(cookie.into_owned());
         self
     }
 
+    /// Adds a header to be sent with this request.
+    ///
+    /// Adding multiple headers with the same name will send all of them.
+    /// They replace any headers of the same name from the `Server`,
+    /// and from `Request::content_type` and `Request::user_agent`.
+    pub fn add_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Adds all of the headers given, to be sent with this request.
+    ///
+    /// This follows the same rules as `Request::add_header`.
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        let mut last_name = None;
+        for (maybe_name, value) in headers {
+            if let Some(name) = maybe_name {
+                last_name = Some(name);
+            }
+
+            if let Some(name) = last_name.as_ref() {
+                self.headers.push((name.clone(), value));
+            }
+        }
+
+        self
+    }
+
+    /// Clears all of the headers added to this request,
+    /// including the defaults from the `Server`.
+    pub fn clear_headers(mut self) -> Self {
+        self.config.headers.clear();
+        self.headers.clear();
+        self
+    }
+
+    /// Adds the contents of the value given to the query of the request.
+    ///
+    /// The value is serialised using `serde_urlencoded`, so it needs to be
+    /// a struct, a map, or a sequence of key value pairs.
+    ///
+    /// ```rust
+    /// # ::tokio_test::block_on(async {
+    /// use ::kantan::Server;
+    /// use ::serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Search {
+    ///     name: String,
+    ///     page: u32,
+    /// }
+    ///
+    /// let server = Server::new("http://localhost:3000".to_string())
+    ///     .unwrap();
+    ///
+    /// let request = server.get("/users")
+    ///     .query(&Search {
+    ///         name: "Joe Bloggs".to_string(),
+    ///         page: 2,
+    ///     });
+    /// # })
+    /// ```
+    pub fn query<Q>(mut self, query: &Q) -> Self
+    where
+        Q: ?Sized + Serialize,
+    {
+        let query_string =
+            serde_urlencoded::to_string(query).expect("It should serialize the query");
+        let query_params: Vec<(String, String)> = serde_urlencoded::from_str(&query_string)
+            .expect("It should read back the serialized query");
+
+        self.query_params.extend(query_params);
+        self
+    }
+
+    /// Adds a single key value pair to the query of the request.
+    ///
+    /// Both are percent-encoded when the request is sent.
+    pub fn add_query_param<V>(mut self, key: &str, value: V) -> Self
+    where
+        V: Display,
+    {
+        self.query_params.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Clears all of the query parameters added to this request,
+    /// including the defaults from the `Server`.
+    ///
+    /// Any query written within the path is kept.
+    pub fn clear_query_params(mut self) -> Self {
+        self.config.query_params.clear();
+        self.query_params.clear();
+        self
+    }
+
     /// Set the body of the request to send up as Json.
     pub fn json<J>(mut self, body: &J) -> Self
     where
         J: ?Sized + Serialize,
     {
         let body_bytes = json_to_vec(body).expect("It should serialize the content into JSON");
-        let body: Body = body_bytes.into();
-        self.body = Some(body);
+        self.body = Some(RequestBody::Bytes(body_bytes.into()));
 
         if self.config.content_type == None {
             self.config.content_type = Some(JSON_CONTENT_TYPE.to_string());
@@ -152,6 +303,25 @@ impl Request {
         self
     }
 
+    /// Set the body of the request to send up as a URL encoded form.
+    ///
+    /// If there isn't a content type set,
+    /// this will default to `application/x-www-form-urlencoded`.
+    pub fn form<F>(mut self, body: &F) -> Self
+    where
+        F: ?Sized + Serialize,
+    {
+        let body_text =
+            serde_urlencoded::to_string(body).expect("It should serialize the content into a form");
+        self.body = Some(RequestBody::Bytes(body_text.into()));
+
+        if self.config.content_type == None {
+            self.config.content_type = Some(FORM_CONTENT_TYPE.to_string());
+        }
+
+        self
+    }
+
     /// Set raw text as the body of the request.
     ///
     /// If there isn't a content type set, this will default to `text/plain`.
@@ -173,9 +343,106 @@ impl Request {
     ///
     /// The content type is left unchanged.
     pub fn bytes(mut self, body_bytes: Bytes) -> Self {
-        let body: Body = body_bytes.into();
+        self.body = Some(RequestBody::Bytes(body_bytes));
+        self
+    }
+
+    /// Set the body of the request to the contents of the file at the path given.
+    ///
+    /// The file is streamed when the request is sent, rather than read into memory,
+    /// and the `Content-Length` is taken from the size of the file.
+    ///
+    /// If there isn't a content type set, this will default to one guessed from the path.
+    /// Such as `image/png` for a `.png` file.
+    pub fn file<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if self.config.content_type == None {
+            let mime_type = ::mime_guess::from_path(path).first_or_octet_stream();
+            self.config.content_type = Some(mime_type.to_string());
+        }
 
-        self.body = Some(body);
+        self.body = Some(RequestBody::File(path.to_path_buf()));
+        self
+    }
+
+    /// Set the body of the request to the contents read from the reader given.
+    ///
+    /// It is read as the request is sent, using chunked transfer encoding.
+    /// To send a `Content-Length` instead, add it using `Request::add_header`.
+    ///
+    /// The content type is left unchanged.
+    pub fn reader<R>(self, reader: R) -> Self
+    where
+        R: AsyncRead + Send + 'static,
+    {
+        self.body_stream(ReaderStream::new(reader))
+    }
+
+    /// Set the body of the request to the chunks from the stream given.
+    ///
+    /// It is read as the request is sent, using chunked transfer encoding.
+    /// To send a `Content-Length` instead, add it using `Request::add_header`.
+    ///
+    /// A streamed body can only be sent once. Following a redirect which keeps the body
+    /// (a `307` or `308`) will fail with an `Error::InvalidRequest`,
+    /// and so will sending a clone of this request after the first has been sent.
+    /// Use `Request::body_stream_factory` for a stream which can be sent again.
+    ///
+    /// The content type is left unchanged.
+    pub fn body_stream<S, E>(mut self, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        self.body = Some(RequestBody::from_stream(Body::wrap_stream(stream)));
+        self
+    }
+
+    /// Set the body of the request to the chunks from a stream,
+    /// built by calling the function given.
+    ///
+    /// A new stream is built each time the body is sent.
+    /// This allows the request to be cloned, retried, and to follow redirects which keep the body.
+    ///
+    /// It is read as the request is sent, using chunked transfer encoding.
+    /// The content type is left unchanged.
+    pub fn body_stream_factory<F, S, E>(mut self, make_stream: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        let stream_factory = StreamFactory::new(move || Body::wrap_stream(make_stream()));
+        self.body = Some(RequestBody::StreamFactory(stream_factory));
+        self
+    }
+
+    /// Set the body of the request to the multipart form given.
+    ///
+    /// The content type is set to `multipart/form-data`, with the boundary of the form.
+    /// This replaces any default content type, as the boundary is needed to read the body.
+    ///
+    /// ```rust
+    /// # ::tokio_test::block_on(async {
+    /// use ::kantan::MultipartForm;
+    /// use ::kantan::Part;
+    /// use ::kantan::Server;
+    ///
+    /// let server = Server::new("http://localhost:3000".to_string())
+    ///     .unwrap();
+    ///
+    /// let form = MultipartForm::new()
+    ///     .add_text("title", "Quarterly Report")
+    ///     .add_part("report", Part::file("report.pdf"));
+    /// let request = server.post("/reports").multipart(form);
+    /// # })
+    /// ```
+    pub fn multipart(mut self, multipart_form: MultipartForm) -> Self {
+        self.config.content_type = Some(multipart_form.content_type());
+        self.body = Some(RequestBody::Multipart(multipart_form));
         self
     }
 
@@ -185,65 +452,693 @@ impl Request {
         self
     }
 
+    /// Sends the token given as `Authorization: Bearer <token>`.
+    ///
+    /// This replaces any credentials or `AuthProvider` set on the `Server`.
+    /// The token is hidden when the request is printed using `Debug`.
+    pub fn bearer_auth<T>(mut self, token: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.config.authorization = Some(Authorization::bearer(token));
+        self.config.auth_provider = None;
+        self
+    }
+
+    /// Sends the username and password using HTTP Basic authentication.
+    ///
+    /// This replaces any credentials or `AuthProvider` set on the `Server`.
+    /// The password is hidden when the request is printed using `Debug`.
+    pub fn basic_auth<U, P>(mut self, username: U, password: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        self.config.authorization = Some(Authorization::basic(username, password));
+        self.config.auth_provider = None;
+        self
+    }
+
+    /// Sends the username and password using HTTP Digest authentication, from RFC 7616.
+    ///
+    /// When the server has not yet sent a challenge, the request is sent without credentials,
+    /// and then sent again in response to the challenge in the `401 Unauthorized`.
+    /// The challenge is stored on the `Server`, so later requests reuse its nonce,
+    /// skipping the extra round trip.
+    ///
+    /// Only `qop=auth` is supported, using the `MD5`, `SHA-256`, or `SHA-512-256` algorithms.
+    /// Bodies from `Request::reader` or `Request::body_stream` cannot be sent again,
+    /// so they only succeed once a challenge is stored from an earlier request.
+    ///
+    /// This replaces any credentials or `AuthProvider` set on the `Server`.
+    /// The password is hidden when the request is printed using `Debug`.
+    pub fn digest_auth<U, P>(mut self, username: U, password: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        let digest_auth = DigestAuth::new(
+            username.into(),
+            password.into(),
+            self.config.digest_nonces.clone(),
+        );
+        self.config.authorization = None;
+        self.config.auth_provider = Some(Arc::new(digest_auth));
+        self
+    }
+
+    /// Sends this request without the credentials or `AuthProvider` set on the `Server`.
+    pub fn clear_auth(mut self) -> Self {
+        self.config.authorization = None;
+        self.config.auth_provider = None;
+        self
+    }
+
+    /// Set the `User-Agent` header for this request.
+    ///
+    /// This replaces any user agent set on the `Server`.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.config.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Expects the response to have a successful status code (2xx).
+    /// If it doesn't, then awaiting this request will panic.
+    pub fn expect_success(mut self) -> Self {
+        self.config.expected_status = Some(ExpectedStatus::Success);
+        self
+    }
+
+    /// Expects the response to have a status code outside of 2xx.
+    /// If it doesn't, then awaiting this request will panic.
+    pub fn expect_failure(mut self) -> Self {
+        self.config.expected_status = Some(ExpectedStatus::Failure);
+        self
+    }
+
+    /// Expects the response to have the status code given.
+    /// If it doesn't, then awaiting this request will panic.
+    pub fn expect_status(mut self, status_code: StatusCode) -> Self {
+        self.config.expected_status = Some(ExpectedStatus::Code(status_code));
+        self
+    }
+
+    /// Accepts any status code in the response.
+    ///
+    /// This turns off any expected status set on the `Server`.
+    pub fn expect_any_status(mut self) -> Self {
+        self.config.expected_status = None;
+        self
+    }
+
+    /// Sets which redirects will be followed when sending this request.
+    ///
+    /// This replaces the redirect policy set on the `Server`.
+    pub fn redirect_policy(mut self, redirect_policy: RedirectPolicy) -> Self {
+        self.config.redirect_policy = redirect_policy;
+        self
+    }
+
+    /// Sets how long to wait for a connection to the server to be opened.
+    ///
+    /// This replaces the connect timeout set on the `Server`.
+    /// When this differs from the `Server`, the request uses a separate connection pool
+    /// shared with other requests using the same timeout.
+    /// Only the pools for the few most recently used timeouts are kept.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait for the response headers to arrive.
+    ///
+    /// This replaces the headers timeout set on the `Server`.
+    pub fn headers_timeout(mut self, timeout: Duration) -> Self {
+        self.config.headers_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait for the whole request to finish,
+    /// including reading the response body.
+    ///
+    /// This replaces the total timeout set on the `Server`.
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.config.total_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets when this request is sent again, if it fails.
+    ///
+    /// This replaces the retry policy set on the `Server`.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.config.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the largest size a compressed response body is allowed to decompress to.
+    ///
+    /// This replaces the maximum set on the `Server`.
+    pub fn max_decompressed_size(mut self, max_size: usize) -> Self {
+        self.config.max_decompressed_size = max_size;
+        self
+    }
+
+    /// Compresses the body using the encoding given, and sets the `Content-Encoding` to match.
+    ///
+    /// This applies to bodies set using `Request::json`, `Request::text`,
+    /// `Request::bytes`, and `Request::form`. Files, streams, and multipart forms are sent as they are.
+    /// Bodies smaller than the compression threshold are also sent uncompressed.
+    ///
+    /// This replaces the compression set on the `Server`.
+    pub fn compress(mut self, encoding: Encoding) -> Self {
+        self.config.compression = Some(encoding);
+        self
+    }
+
+    /// Sends the body uncompressed, even when compression is set on the `Server`.
+    pub fn do_not_compress(mut self) -> Self {
+        self.config.compression = None;
+        self
+    }
+
+    /// Sets the smallest body which is compressed, in bytes.
+    ///
+    /// This replaces the threshold set on the `Server`.
+    pub fn compression_threshold(mut self, threshold: usize) -> Self {
+        self.config.compression_threshold = threshold;
+        self
+    }
+
     async fn send_or_panic(self) -> Response {
-        self.send().await.expect("Sending request failed")
+        self.try_send()
+            .await
+            .unwrap_or_else(|err| panic!("Sending request failed, {}", err))
+    }
+
+    /// Sends the request, returning an `Error` if anything fails.
+    ///
+    /// When the status code does not match what is expected,
+    /// then an `Error::Status` is returned.
+    ///
+    /// Unlike awaiting the `Request`, this will never panic.
+    pub async fn try_send(self) -> Result<Response, Error> {
+        let expected_status = self.config.expected_status;
+        let total_timeout = self.config.total_timeout;
+        let method = self.config.method.clone();
+        let request_path = self.full_request_path()?;
+
+        let response_future = async {
+            self.send_without_total_timeout(request_path.clone())
+                .await?
+                .collect()
+                .await
+        };
+        let response =
+            with_total_timeout(total_timeout, &method, &request_path, response_future).await?;
+
+        match expected_status {
+            Some(expected_status) => response.error_for_expected_status(expected_status),
+            None => Ok(response),
+        }
+    }
+
+    /// Sends the request, returning a `StreamingResponse` once the response headers arrive.
+    /// The body is then read from it as a `Stream`.
+    ///
+    /// The total timeout only covers waiting for the response headers,
+    /// as the body may be read over any length of time.
+    ///
+    /// When the status code does not match what is expected,
+    /// the body is read, and an `Error::Status` is returned.
+    pub async fn send_streaming(self) -> Result<StreamingResponse, Error> {
+        let expected_status = self.config.expected_status;
+        let total_timeout = self.config.total_timeout;
+        let method = self.config.method.clone();
+        let request_path = self.full_request_path()?;
+
+        let response_future = self.send_without_total_timeout(request_path.clone());
+        let streaming_response =
+            with_total_timeout(total_timeout, &method, &request_path, response_future).await?;
+
+        match expected_status {
+            Some(expected_status) if !expected_status.matches(streaming_response.status_code()) => {
+                let response = streaming_response.collect().await?;
+                Err(Error::Status {
+                    expected: expected_status,
+                    response: Box::new(response),
+                })
+            }
+            _ => Ok(streaming_response),
+        }
+    }
+
+    /// Sends the request, and streams the body of the response into the file at the path given.
+    ///
+    /// The body is written to a `.part` file next to the path, which is moved into place
+    /// once the whole body has been received, and it matches the `Content-Length`.
+    ///
+    /// When an earlier attempt failed part way, then calling this again will resume it.
+    /// This is only done when the server advertised `Accept-Ranges: bytes`, along with
+    /// an `ETag` or `Last-Modified`. The rest of the file is requested using a `Range` header,
+    /// and `If-Range` so the server will send the whole file again if it has changed.
+    ///
+    /// The file is saved exactly as it is sent, so `Accept-Encoding: identity`
+    /// is sent to ask for it uncompressed. Unless another `Accept-Encoding` is set on the request.
+    ///
+    /// A successful status code is always expected,
+    /// and any other status code is returned as an `Error::Status`.
+    pub async fn download_to<P>(mut self, path: P) -> Result<Download, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let has_accept_encoding = self
+            .headers
+            .iter()
+            .any(|(header_name, _)| header_name == header::ACCEPT_ENCODING);
+        if !has_accept_encoding {
+            self.headers.push((
+                header::ACCEPT_ENCODING,
+                HeaderValue::from_static("identity"),
+            ));
+        }
+
+        let resume_point = find_resume_point(path).await;
+        if let Some(resume_point) = resume_point.as_ref() {
+            let range = format!("bytes={}-", resume_point.offset);
+            self.headers
+                .push((header::RANGE, build_header_value(&header::RANGE, &range)?));
+            self.headers.push((
+                header::IF_RANGE,
+                build_header_value(&header::IF_RANGE, &resume_point.if_range)?,
+            ));
+        }
+
+        let streaming_response = self.expect_any_status().send_streaming().await?;
+
+        download_response(streaming_response, path, resume_point).await
+    }
+
+    /// Returns the path to send the request to, including all of the query parameters.
+    fn full_request_path(&self) -> Result<Uri, Error> {
+        append_query_params(
+            self.config.request_path.clone(),
+            &self.merged_query_params(),
+        )
+        .map_err(|err| Error::InvalidRequest {
+            method: self.config.method.clone(),
+            request_path: self.config.request_path.to_string(),
+            source: err.into(),
+        })
+    }
+
+    /// Sends the request, retrying it as the `RetryPolicy` allows.
+    async fn send_without_total_timeout(
+        mut self,
+        request_path: Uri,
+    ) -> Result<StreamingResponse, Error> {
+        let client = InnerServer::client_for_connect_timeout(
+            &self.inner_test_server,
+            self.config.connect_timeout,
+        )?;
+
+        let mut headers = self.build_headers()?;
+        let body = self.body.take();
+        let body = self.compress_body(&mut headers, body).await?;
+        let is_replayable = body.as_ref().is_none_or(RequestBody::is_replayable);
+
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .send_attempt(&client, &request_path, headers.clone(), body.clone())
+                .await;
+
+            let maybe_delay = if is_replayable {
+                self.config
+                    .retry_policy
+                    .retry_delay(attempt, &self.config.method, &result)
+            } else {
+                None
+            };
+            let Some(delay) = maybe_delay else {
+                return result;
+            };
+
+            // The body of the response being retried is read, so the connection can be reused.
+            // Failing to read it is ignored, as the request is being sent again anyway.
+            if let Ok(streaming_response) = result {
+                let _ = streaming_response.discard().await;
+            }
+
+            sleep(delay).await;
+            attempt += 1;
+        }
     }
 
-    async fn send(mut self) -> Result<Response> {
-        let request_path = self.config.request_path;
-        let method = self.config.method;
-        let content_type = self.config.content_type;
+    /// Sends the request once, following any redirects.
+    async fn send_attempt(
+        &self,
+        client: &HttpClient,
+        request_path: &Uri,
+        mut headers: HeaderMap,
+        mut body: Option<RequestBody>,
+    ) -> Result<StreamingResponse, Error> {
+        let original_method = self.config.method.clone();
+        let redirect_policy = self.config.redirect_policy;
         let save_cookies = self.is_saving_cookies;
-        let body = self.body.unwrap_or(Body::empty());
 
-        let mut request_builder = HyperRequest::builder().uri(&request_path).method(method);
+        let mut method = original_method.clone();
+        let mut hop_path = request_path.clone();
+        let mut redirect_history = vec![];
+
+        loop {
+            let is_hop_same_origin = is_same_origin(request_path, &hop_path);
+            let (parts, response_body) = self
+                .send_hop(
+                    client,
+                    &method,
+                    &hop_path,
+                    &headers,
+                    body.as_ref(),
+                    is_hop_same_origin,
+                )
+                .await?;
+
+            if save_cookies {
+                let mut cookie_headers = parts.headers.get_all(SET_COOKIE).into_iter();
+                self.config
+                    .cookie_store
+                    .store_set_cookies(&mut cookie_headers, &hop_path);
+            }
+
+            let is_following =
+                redirect_policy != RedirectPolicy::None && is_followable_redirect(parts.status);
+            let maybe_next_path = match parts.headers.get(LOCATION) {
+                Some(location) if is_following => Some(resolve_redirect(&hop_path, location)?),
+                _ => None,
+            };
+            let next_path = match maybe_next_path {
+                Some(next_path) if redirect_policy.is_following(request_path, &next_path) => {
+                    next_path
+                }
+                _ => {
+                    return Ok(StreamingResponse::new(
+                        original_method,
+                        request_path.clone(),
+                        hop_path,
+                        redirect_history,
+                        parts,
+                        response_body,
+                        self.config.max_decompressed_size,
+                    ));
+                }
+            };
+
+            // The body of the redirect is read, so the connection can be reused.
+            discard_body(response_body)
+                .await
+                .map_err(|err| Error::BodyRead {
+                    method: method.clone(),
+                    request_path: hop_path.clone(),
+                    source: err,
+                })?;
+
+            if redirect_history.len() >= redirect_policy.max_redirects() {
+                return Err(Error::TooManyRedirects {
+                    method: original_method,
+                    request_path: request_path.clone(),
+                    max_redirects: redirect_policy.max_redirects(),
+                });
+            }
+
+            let (next_method, is_keeping_body) = redirect_method(parts.status, method.clone());
+            if !is_keeping_body {
+                body = None;
+                headers.remove(header::CONTENT_TYPE);
+                headers.remove(header::CONTENT_LENGTH);
+                headers.remove(header::CONTENT_ENCODING);
+            }
+
+            // Credentials are not passed on to other origins.
+            if !is_same_origin(&hop_path, &next_path) {
+                headers.remove(header::AUTHORIZATION);
+            }
+
+            redirect_history.push(Redirect::new(
+                method,
+                hop_path,
+                parts.status,
+                next_path.clone(),
+            ));
+            method = next_method;
+            hop_path = next_path;
+        }
+    }
+
+    /// Compresses the body, when compression is turned on and the body is large enough.
+    ///
+    /// Bodies which already have a `Content-Encoding` header are left as they are.
+    async fn compress_body(
+        &self,
+        headers: &mut HeaderMap,
+        body: Option<RequestBody>,
+    ) -> Result<Option<RequestBody>, Error> {
+        let Some(encoding) = self.config.compression else {
+            return Ok(body);
+        };
+        let bytes = match body {
+            Some(RequestBody::Bytes(bytes)) => bytes,
+            body => return Ok(body),
+        };
+        if bytes.len() < self.config.compression_threshold
+            || headers.contains_key(header::CONTENT_ENCODING)
+        {
+            return Ok(Some(RequestBody::Bytes(bytes)));
+        }
+
+        let compressed_bytes =
+            encoding
+                .encode(&bytes)
+                .await
+                .map_err(|err| Error::InvalidRequest {
+                    method: self.config.method.clone(),
+                    request_path: self.config.request_path.to_string(),
+                    source: err.into(),
+                })?;
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+
+        Ok(Some(RequestBody::Bytes(compressed_bytes)))
+    }
+
+    /// Returns the defaults from the `Server`, followed by those set on this request.
+    ///
+    /// Defaults are dropped when this request sets a parameter with the same key.
+    fn merged_query_params(&self) -> Vec<(String, String)> {
+        let defaults = self
+            .config
+            .query_params
+            .iter()
+            .filter(|(key, _)| !self.query_params.iter().any(|(other, _)| other == key));
+
+        defaults.chain(self.query_params.iter()).cloned().collect()
+    }
+
+    /// Builds all of the headers to send, apart from the cookies.
+    fn build_headers(&mut self) -> Result<HeaderMap, Error> {
+        // Start with the defaults from the `Server`.
+        let mut headers = ::std::mem::take(&mut self.config.headers);
+        if let Some(user_agent) = self.config.user_agent.as_ref() {
+            let header_value = build_header_value(&header::USER_AGENT, user_agent)?;
+            headers.insert(header::USER_AGENT, header_value);
+        }
+
+        if let Some(content_type) = self.config.content_type.as_ref() {
+            let header_value = build_header_value(&header::CONTENT_TYPE, content_type)?;
+            headers.insert(header::CONTENT_TYPE, header_value);
+        }
+
+        if let Some(authorization) = self.config.authorization.as_ref() {
+            let header_value =
+                authorization
+                    .to_header_value()
+                    .map_err(|err| Error::InvalidHeader {
+                        name: header::AUTHORIZATION.to_string(),
+                        source: Box::new(err),
+                    })?;
+            headers.insert(header::AUTHORIZATION, header_value);
+        }
+
+        // Headers set on the request replace all of those with the same name.
+        for (header_name, _) in self.headers.iter() {
+            headers.remove(header_name);
+        }
 
-        // Add all the headers we have.
-        let mut headers = self.headers;
-        if let Some(content_type) = content_type {
-            let header = build_content_type_header(content_type)?;
-            headers.push(header);
+        for (header_name, header_value) in self.headers.drain(..) {
+            headers.append(header_name, header_value);
         }
 
-        // Add all the cookies as headers
-        for cookie in self.cookies.iter() {
-            let cookie_raw = cookie.to_string();
-            let header_value = HeaderValue::from_str(&cookie_raw)?;
-            headers.push((header::COOKIE, header_value));
+        if !headers.contains_key(header::ACCEPT_ENCODING) {
+            if let Some(accept_encoding) = accept_encoding_header() {
+                headers.insert(header::ACCEPT_ENCODING, accept_encoding);
+            }
         }
 
+        Ok(headers)
+    }
+
+    /// Sends a single HTTP request, and returns the response once the headers arrive.
+    ///
+    /// This does not follow any redirects.
+    async fn send_hop(
+        &self,
+        client: &HttpClient,
+        method: &Method,
+        request_path: &Uri,
+        headers: &HeaderMap,
+        body: Option<&RequestBody>,
+        is_hop_same_origin: bool,
+    ) -> Result<(Parts, Body), Error> {
+        let connect_timeout = self.config.connect_timeout;
+        let headers_timeout = self.config.headers_timeout;
+
+        // Credentials are only provided to the origin of the original request.
+        let auth_provider = self
+            .config
+            .auth_provider
+            .as_deref()
+            .filter(|_| is_hop_same_origin);
+        let mut is_retrying_unauthorized = body.is_none_or(RequestBody::is_replayable);
+
+        loop {
+            let request = self
+                .build_hop_request(method, request_path, headers, body, is_hop_same_origin)
+                .await?;
+
+            // The request is passed through the middleware, which ends with authorizing and sending it.
+            let authorized_request = Mutex::new(None);
+            let mut next = Next::new(&self.config.middleware, client, connect_timeout);
+            if let Some(auth_provider) = auth_provider {
+                let is_body_in_memory = body.is_none_or(|body| body.as_bytes().is_some());
+                next = next.auth_provider(auth_provider, is_body_in_memory, &authorized_request);
+            }
+            let response_future = next.run(request);
+            let hyper_response = match headers_timeout {
+                None => response_future.await?,
+                Some(duration) => timeout(duration, response_future).await.map_err(|_| {
+                    TimeoutError::new(
+                        TimeoutKind::Headers,
+                        duration,
+                        method.clone(),
+                        request_path.clone(),
+                    )
+                })??,
+            };
+
+            let (parts, response_body) = hyper_response.into_parts();
+            let is_unauthorized = parts.status == StatusCode::UNAUTHORIZED;
+            // Requests which middleware answered without sending are never authorized,
+            // so they are not retried.
+            let maybe_authorized_request = authorized_request
+                .into_inner()
+                .ok()
+                .flatten()
+                .filter(|_| is_unauthorized && is_retrying_unauthorized);
+            if let (Some(auth_provider), Some(authorized_request)) =
+                (auth_provider, maybe_authorized_request)
+            {
+                is_retrying_unauthorized = false;
+
+                if auth_provider
+                    .retry_unauthorized(&authorized_request, &parts)
+                    .await?
+                {
+                    // The body of the rejection is read, so the connection can be reused.
+                    discard_body(response_body)
+                        .await
+                        .map_err(|err| Error::BodyRead {
+                            method: method.clone(),
+                            request_path: request_path.clone(),
+                            source: err,
+                        })?;
+
+                    continue;
+                }
+            }
+
+            return Ok((parts, response_body));
+        }
+    }
+
+    /// Builds the HTTP request for a single hop, with the headers, cookies, and body.
+    async fn build_hop_request(
+        &self,
+        method: &Method,
+        request_path: &Uri,
+        headers: &HeaderMap,
+        body: Option<&RequestBody>,
+        is_sending_request_cookies: bool,
+    ) -> Result<HyperRequest<Body>, Error> {
+        let mut request_builder = HyperRequest::builder()
+            .uri(request_path)
+            .method(method.clone());
+
         // Put headers into the request
-        for (header_name, header_value) in headers {
+        for (header_name, header_value) in headers.iter() {
             request_builder = request_builder.header(header_name, header_value);
         }
 
-        let request = request_builder.body(body).with_context(|| {
-            format!(
-                "Expect valid hyper Request to be built on request to {}",
-                request_path
-            )
-        })?;
+        // Add all the cookies, as a single header
+        let mut cookie_pairs = vec![];
+        if self.is_using_server_cookies {
+            let maybe_server_cookies = self.config.cookie_store.cookie_header(request_path);
+            if let Some(server_cookies) = maybe_server_cookies {
+                let server_cookies =
+                    server_cookies
+                        .to_str()
+                        .map_err(|err| Error::InvalidHeader {
+                            name: header::COOKIE.to_string(),
+                            source: Box::new(err),
+                        })?;
+                cookie_pairs.push(server_cookies.to_string());
+            }
+        }
 
-        let https = HttpsConnector::new();
-        let client = Client::builder().build::<_, hyper::Body>(https);
+        if is_sending_request_cookies {
+            for cookie in self.cookies.iter() {
+                cookie_pairs.push(format!("{}={}", cookie.name(), cookie.value()));
+            }
+        }
 
-        let hyper_response = client.request(request).await.with_context(|| {
-            format!(
-                "Expect Hyper Response to succeed on request to {}",
-                request_path
-            )
-        })?;
+        if !cookie_pairs.is_empty() {
+            let header_value = build_header_value(&header::COOKIE, &cookie_pairs.join("; "))?;
+            request_builder = request_builder.header(header::COOKIE, header_value);
+        }
 
-        let (parts, response_body) = hyper_response.into_parts();
-        let response_bytes = to_bytes(response_body).await?;
+        let (body, maybe_content_length) = match body {
+            None => (Body::empty(), None),
+            Some(body) => body.to_body().await.map_err(|err| Error::InvalidRequest {
+                method: method.clone(),
+                request_path: request_path.to_string(),
+                source: err.into(),
+            })?,
+        };
 
-        if save_cookies {
-            let cookie_headers = parts.headers.get_all(SET_COOKIE).into_iter();
-            InnerServer::add_cookies_by_header(&mut self.inner_test_server, cookie_headers)?;
+        if let Some(content_length) = maybe_content_length {
+            if !headers.contains_key(header::CONTENT_LENGTH) {
+                request_builder = request_builder.header(header::CONTENT_LENGTH, content_length);
+            }
         }
 
-        let response = Response::new(request_path, parts, response_bytes);
-        Ok(response)
+        request_builder
+            .body(body)
+            .map_err(|err| Error::InvalidRequest {
+                method: method.clone(),
+                request_path: request_path.to_string(),
+                source: err.into(),
+            })
     }
 }
 
@@ -259,9 +1154,69 @@ impl IntoFuture for Request {
     }
 }
 
-fn build_content_type_header(content_type: String) -> Result<(HeaderName, HeaderValue)> {
-    let header_value = HeaderValue::from_str(&content_type)
-        .with_context(|| format!("Failed to store header content type '{}'", content_type))?;
+/// Runs the future given, failing with a timeout if it takes longer than the total timeout.
+async fn with_total_timeout<F, T>(
+    total_timeout: Option<Duration>,
+    method: &Method,
+    request_path: &Uri,
+    future: F,
+) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    match total_timeout {
+        None => future.await,
+        Some(duration) => timeout(duration, future).await.map_err(|_| {
+            TimeoutError::new(
+                TimeoutKind::Total,
+                duration,
+                method.clone(),
+                request_path.clone(),
+            )
+        })?,
+    }
+}
+
+fn resolve_redirect(request_path: &Uri, location: &HeaderValue) -> Result<Uri, Error> {
+    let location_str = location.to_str().map_err(|err| Error::InvalidHeader {
+        name: LOCATION.to_string(),
+        source: err.into(),
+    })?;
+
+    resolve_location(request_path, location_str).map_err(|err| Error::InvalidHeader {
+        name: LOCATION.to_string(),
+        source: err.into(),
+    })
+}
+
+fn build_header_value(header_name: &HeaderName, value: &str) -> Result<HeaderValue, Error> {
+    HeaderValue::from_str(value).map_err(|err| Error::InvalidHeader {
+        name: header_name.to_string(),
+        source: err.into(),
+    })
+}
+
+/// Adds the query parameters given onto the end of the path,
+/// after any query which is already there.
+fn append_query_params(request_path: Uri, query_params: &[(String, String)]) -> AnyhowResult<Uri> {
+    if query_params.is_empty() {
+        return Ok(request_path);
+    }
+
+    let encoded_query = serde_urlencoded::to_string(query_params)
+        .with_context(|| format!("Failed to encode query parameters for {}", request_path))?;
+
+    let mut parts = request_path.into_parts();
+    let path_and_query = match parts.path_and_query.as_ref() {
+        Some(path_and_query) => match path_and_query.query() {
+            Some(query) if !query.is_empty() => {
+                format!("{}?{}&{}", path_and_query.path(), query, encoded_query)
+            }
+            _ => format!("{}?{}", path_and_query.path(), encoded_query),
+        },
+        None => format!("/?{}", encoded_query),
+    };
+    parts.path_and_query = Some(path_and_query.try_into()?);
 
-    Ok((header::CONTENT_TYPE, header_value))
+    Ok(Uri::from_parts(parts)?)
 }
@@ -0,0 +1,104 @@
+use ::anyhow::anyhow;
+use ::anyhow::Result;
+use ::hyper::body::Body;
+use ::hyper::body::Bytes;
+use ::std::fmt::Debug;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+use ::std::path::PathBuf;
+use ::std::sync::Arc;
+use ::std::sync::Mutex;
+use ::tokio::fs::File;
+use ::tokio_util::io::ReaderStream;
+
+use crate::MultipartForm;
+
+/// The body to send with a `Request`.
+#[derive(Debug, Clone)]
+pub(crate) enum RequestBody {
+    Bytes(Bytes),
+    Multipart(MultipartForm),
+
+    /// A file which is streamed from disk, when the request is sent.
+    File(PathBuf),
+
+    /// A body streamed from elsewhere, which can only be sent once.
+    Stream(Arc<Mutex<Option<Body>>>),
+
+    /// Builds a new stream each time the body is sent.
+    StreamFactory(StreamFactory),
+}
+
+/// A function which builds the body to stream, each time it is sent.
+#[derive(Clone)]
+pub(crate) struct StreamFactory(Arc<dyn Fn() -> Body + Send + Sync>);
+
+impl StreamFactory {
+    pub(crate) fn new<F>(make_body: F) -> Self
+    where
+        F: Fn() -> Body + Send + Sync + 'static,
+    {
+        Self(Arc::new(make_body))
+    }
+}
+
+impl Debug for StreamFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "StreamFactory")
+    }
+}
+
+impl RequestBody {
+    pub(crate) fn from_stream(body: Body) -> Self {
+        Self::Stream(Arc::new(Mutex::new(Some(body))))
+    }
+
+    /// Returns the body, when it is already held in memory.
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the body can be sent more than once.
+    pub(crate) fn is_replayable(&self) -> bool {
+        !matches!(self, Self::Stream(_))
+    }
+
+    /// Builds the body to send, along with its length when it is known up front.
+    /// When the length is not known, the body is sent using chunked transfer encoding.
+    ///
+    /// This can be called multiple times, such as when following redirects or retrying,
+    /// apart from for one-shot streamed bodies.
+    pub(crate) async fn to_body(&self) -> Result<(Body, Option<u64>)> {
+        match self {
+            Self::Bytes(bytes) => Ok((Body::from(bytes.clone()), Some(bytes.len() as u64))),
+            Self::Multipart(multipart_form) => Ok((multipart_form.to_body().await?, None)),
+            Self::File(path) => {
+                let file = File::open(path)
+                    .await
+                    .map_err(|err| anyhow!("Failed to open file {}, {}", path.display(), err))?;
+                let metadata = file
+                    .metadata()
+                    .await
+                    .map_err(|err| anyhow!("Failed to read file {}, {}", path.display(), err))?;
+
+                let body = Body::wrap_stream(ReaderStream::new(file));
+                Ok((body, Some(metadata.len())))
+            }
+            Self::Stream(maybe_body) => {
+                let body = maybe_body
+                    .lock()
+                    .map_err(|err| anyhow!("Failed to lock streamed body, {:?}", err))?
+                    .take()
+                    .ok_or_else(|| {
+                        anyhow!("The streamed body has already been sent, and cannot be sent again")
+                    })?;
+
+                Ok((body, None))
+            }
+            Self::StreamFactory(StreamFactory(make_body)) => Ok((make_body(), None)),
+        }
+    }
+}
@@ -1,10 +1,40 @@
+use ::hyper::http::HeaderMap;
 use ::hyper::http::Method;
 use ::hyper::Uri;
+use ::std::sync::Arc;
+use ::std::time::Duration;
+
+use crate::AuthProvider;
+use crate::Authorization;
+use crate::CookieStore;
+use crate::DigestNonces;
+use crate::Encoding;
+use crate::ExpectedStatus;
+use crate::Middleware;
+use crate::RedirectPolicy;
+use crate::RetryPolicy;
 
 #[derive(Debug, Clone)]
 pub(crate) struct RequestConfig {
     pub method: Method,
     pub request_path: Uri,
     pub save_cookies: bool,
+    pub cookie_store: Arc<dyn CookieStore>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
     pub content_type: Option<String>,
+    pub headers: HeaderMap,
+    pub query_params: Vec<(String, String)>,
+    pub authorization: Option<Authorization>,
+    pub auth_provider: Option<Arc<dyn AuthProvider>>,
+    pub digest_nonces: Arc<DigestNonces>,
+    pub user_agent: Option<String>,
+    pub expected_status: Option<ExpectedStatus>,
+    pub redirect_policy: RedirectPolicy,
+    pub retry_policy: RetryPolicy,
+    pub connect_timeout: Option<Duration>,
+    pub headers_timeout: Option<Duration>,
+    pub total_timeout: Option<Duration>,
+    pub max_decompressed_size: usize,
+    pub compression: Option<Encoding>,
+    pub compression_threshold: usize,
 }
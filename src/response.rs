@@ -1,4 +1,3 @@
-use ::anyhow::Context;
 use ::cookie::Cookie;
 use ::cookie::CookieJar;
 use ::hyper::body::Bytes;
@@ -8,6 +7,7 @@ use ::hyper::http::header::SET_COOKIE;
 use ::hyper::http::response::Parts;
 use ::hyper::http::HeaderMap;
 use ::hyper::http::HeaderValue;
+use ::hyper::http::Method;
 use ::hyper::http::StatusCode;
 use ::serde::Deserialize;
 use ::std::convert::AsRef;
@@ -15,6 +15,10 @@ use ::std::fmt::Debug;
 use ::std::fmt::Display;
 use hyper::Uri;
 
+use crate::Error;
+use crate::ExpectedStatus;
+use crate::Redirect;
+
 ///
 /// The `Response` represents the result of a `Request`.
 /// It is returned when you call await on a `Request` object.
@@ -26,34 +30,99 @@ use hyper::Uri;
 ///
 #[derive(Clone, Debug)]
 pub struct Response {
+    request_method: Method,
     request_uri: Uri,
+    final_uri: Uri,
+    redirect_history: Vec<Redirect>,
     headers: HeaderMap<HeaderValue>,
     status_code: StatusCode,
     response_body: Bytes,
+    raw_response_body: Option<Bytes>,
 }
 
 impl Response {
-    pub(crate) fn new(request_uri: Uri, parts: Parts, response_body: Bytes) -> Self {
+    pub(crate) fn new(
+        request_method: Method,
+        request_uri: Uri,
+        parts: Parts,
+        response_body: Bytes,
+    ) -> Self {
         Self {
+            request_method,
+            final_uri: request_uri.clone(),
+            redirect_history: vec![],
             request_uri,
             headers: parts.headers,
             status_code: parts.status,
             response_body,
+            raw_response_body: None,
         }
     }
 
-    /// The URL that was used to produce this response.
+    /// The HTTP method that was used to produce this response.
+    #[must_use]
+    pub fn request_method(&self) -> &Method {
+        &self.request_method
+    }
+
+    pub(crate) fn set_redirect_history(&mut self, final_uri: Uri, redirect_history: Vec<Redirect>) {
+        self.final_uri = final_uri;
+        self.redirect_history = redirect_history;
+    }
+
+    /// The URL that was requested.
+    ///
+    /// When redirects are followed, this is the URL before any redirects.
     #[must_use]
     pub fn request_uri<'a>(&'a self) -> &'a Uri {
         &self.request_uri
     }
 
+    /// The URL that produced this response,
+    /// after following any redirects.
+    #[must_use]
+    pub fn final_uri(&self) -> &Uri {
+        &self.final_uri
+    }
+
+    /// The redirects that were followed to produce this response, in order.
+    ///
+    /// This is empty when no redirects were followed.
+    #[must_use]
+    pub fn redirect_history(&self) -> &[Redirect] {
+        &self.redirect_history
+    }
+
+    pub(crate) fn set_decompressed_body(&mut self, decompressed_body: Bytes) {
+        let raw_response_body = ::std::mem::replace(&mut self.response_body, decompressed_body);
+        self.raw_response_body = Some(raw_response_body);
+    }
+
     /// Returns the raw underlying response, as it's raw bytes.
+    ///
+    /// When the response was compressed, this is after decompressing it.
     #[must_use]
     pub fn bytes<'a>(&'a self) -> &'a [u8] {
         &self.response_body
     }
 
+    /// Returns the body exactly as it was received, before any decompressing.
+    ///
+    /// This is the same as `Response::bytes` when the response was not compressed.
+    #[must_use]
+    pub fn raw_bytes(&self) -> &[u8] {
+        self.raw_response_body
+            .as_ref()
+            .unwrap_or(&self.response_body)
+    }
+
+    /// Returns true if the body was decompressed,
+    /// using the encoding from the `Content-Encoding` header.
+    #[must_use]
+    pub fn is_decompressed(&self) -> bool {
+        self.raw_response_body.is_some()
+    }
+
     /// Returns the underlying response, as a raw UTF-8 string.
     #[must_use]
     pub fn text(&self) -> String {
@@ -93,19 +162,29 @@ impl Response {
     #[must_use]
     pub fn header<N>(&self, header_name: N) -> HeaderValue
     where
-        N: AsHeaderName + Display + Clone,
+        N: AsHeaderName + Display,
     {
-        let debug_header = header_name.clone();
+        self.try_header(header_name)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Finds a header with the given name.
+    /// If there are multiple headers with the same name,
+    /// then only the first will be returned.
+    ///
+    /// If no header is found, then an `Error::MissingHeader` is returned.
+    pub fn try_header<N>(&self, header_name: N) -> Result<HeaderValue, Error>
+    where
+        N: AsHeaderName + Display,
+    {
+        let debug_header = header_name.to_string();
         self.headers
             .get(header_name)
             .map(|h| h.to_owned())
-            .with_context(|| {
-                format!(
-                    "Cannot find header {} for response {}",
-                    debug_header, self.request_uri
-                )
+            .ok_or_else(|| Error::MissingHeader {
+                name: debug_header,
+                request_path: self.request_uri.clone(),
             })
-            .unwrap()
     }
 
     /// Iterates over all of the headers contained in the response.
@@ -124,6 +203,9 @@ impl Response {
         self.headers.get_all(header_name).iter()
     }
 
+    /// Finds a cookie with the given name.
+    ///
+    /// `None` is returned when no cookie was found.
     #[must_use]
     pub fn maybe_cookie(&self, cookie_name: &str) -> Option<Cookie<'static>> {
         for cookie in self.iter_cookies() {
@@ -135,16 +217,30 @@ impl Response {
         None
     }
 
+    /// Finds a cookie with the given name.
+    ///
+    /// If no cookie is found, then this will panic.
     #[must_use]
     pub fn cookie(&self, cookie_name: &str) -> Cookie<'static> {
-        self.maybe_cookie(cookie_name)
-            .with_context(|| {
-                format!(
-                    "Cannot find cookie {} for response {}",
-                    cookie_name, self.request_uri
-                )
-            })
-            .unwrap()
+        self.try_cookie(cookie_name)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Finds a cookie with the given name.
+    ///
+    /// If no cookie is found, then an `Error::MissingCookie` is returned.
+    pub fn try_cookie(&self, cookie_name: &str) -> Result<Cookie<'static>, Error> {
+        for maybe_cookie in self.try_iter_cookies() {
+            let cookie = maybe_cookie?;
+            if cookie.name() == cookie_name {
+                return Ok(cookie.into_owned());
+            }
+        }
+
+        Err(Error::MissingCookie {
+            name: cookie_name.to_string(),
+            request_path: self.request_uri.clone(),
+        })
     }
 
     /// Returns all of the cookies contained in the response,
@@ -153,37 +249,45 @@ impl Response {
     /// See the `cookie` crate for details.
     #[must_use]
     pub fn cookies(&self) -> CookieJar {
+        self.try_cookies().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns all of the cookies contained in the response,
+    /// within a `CookieJar` object.
+    ///
+    /// An `Error` is returned if any of the cookies cannot be parsed.
+    pub fn try_cookies(&self) -> Result<CookieJar, Error> {
         let mut cookies = CookieJar::new();
 
-        for cookie in self.iter_cookies() {
-            cookies.add(cookie.into_owned());
+        for cookie in self.try_iter_cookies() {
+            cookies.add(cookie?.into_owned());
         }
 
-        cookies
+        Ok(cookies)
     }
 
     /// Iterate over all of the cookies in the response.
+    ///
+    /// This will panic if a cookie cannot be parsed.
     #[must_use]
     pub fn iter_cookies<'a>(&'a self) -> impl Iterator<Item = Cookie<'a>> {
+        self.try_iter_cookies()
+            .map(|cookie| cookie.unwrap_or_else(|err| panic!("{}", err)))
+    }
+
+    /// Iterate over all of the cookies in the response,
+    /// returning an `Error` for each cookie which cannot be parsed.
+    pub fn try_iter_cookies<'a>(&'a self) -> impl Iterator<Item = Result<Cookie<'a>, Error>> {
         self.iter_headers_by_name(SET_COOKIE).map(|header| {
-            let header_str = header
-                .to_str()
-                .with_context(|| {
-                    format!(
-                        "Reading header 'Set-Cookie' as string for response {}",
-                        self.request_uri
-                    )
-                })
-                .unwrap();
-
-            Cookie::parse(header_str)
-                .with_context(|| {
-                    format!(
-                        "Parsing 'Set-Cookie' header for response {}",
-                        self.request_uri
-                    )
-                })
-                .unwrap()
+            let header_str = header.to_str().map_err(|err| Error::InvalidHeader {
+                name: SET_COOKIE.to_string(),
+                source: err.into(),
+            })?;
+
+            Cookie::parse(header_str).map_err(|err| Error::InvalidHeader {
+                name: SET_COOKIE.to_string(),
+                source: err.into(),
+            })
         })
     }
 
@@ -194,14 +298,45 @@ impl Response {
     where
         for<'de> T: Deserialize<'de>,
     {
-        serde_json::from_slice::<T>(&self.response_body)
-            .with_context(|| {
-                format!(
-                    "Deserializing response from JSON for request {}",
-                    self.request_uri
-                )
-            })
-            .unwrap()
+        self.try_json().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Reads the response from the server as JSON text,
+    /// and then deserialise the contents into the structure given.
+    ///
+    /// An `Error::Decode` is returned if the contents cannot be deserialised.
+    pub fn try_json<T>(&self) -> Result<T, Error>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        serde_json::from_slice::<T>(&self.response_body).map_err(|err| Error::Decode {
+            request_path: self.request_uri.clone(),
+            source: err.into(),
+        })
+    }
+
+    /// Reads the response from the server as a URL encoded form,
+    /// and then deserialise the contents into the structure given.
+    #[must_use]
+    pub fn form<T>(&self) -> T
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.try_form().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Reads the response from the server as a URL encoded form,
+    /// and then deserialise the contents into the structure given.
+    ///
+    /// An `Error::Decode` is returned if the contents cannot be deserialised.
+    pub fn try_form<T>(&self) -> Result<T, Error>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        serde_urlencoded::from_bytes::<T>(&self.response_body).map_err(|err| Error::Decode {
+            request_path: self.request_uri.clone(),
+            source: err.into(),
+        })
     }
 
     /// This performs an assertion comparing the whole body of the response,
@@ -234,6 +369,36 @@ impl Response {
         self
     }
 
+    /// Returns an `Error::Status` if the status code
+    /// is a client error (4xx), or a server error (5xx).
+    ///
+    /// Otherwise the response is returned as is.
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        if self.status_code.is_client_error() || self.status_code.is_server_error() {
+            return Err(Error::Status {
+                expected: ExpectedStatus::Success,
+                response: Box::new(self),
+            });
+        }
+
+        Ok(self)
+    }
+
+    /// Returns an `Error::Status` if the status code
+    /// does not match the expectation given.
+    ///
+    /// Otherwise the response is returned as is.
+    pub fn error_for_expected_status(self, expected: ExpectedStatus) -> Result<Self, Error> {
+        if !expected.matches(self.status_code) {
+            return Err(Error::Status {
+                expected,
+                response: Box::new(self),
+            });
+        }
+
+        Ok(self)
+    }
+
     pub fn assert_status_bad_request(self) -> Self {
         self.assert_status(StatusCode::BAD_REQUEST)
     }
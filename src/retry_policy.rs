@@ -0,0 +1,327 @@
+use ::hyper::http::header::RETRY_AFTER;
+use ::hyper::http::HeaderValue;
+use ::hyper::http::Method;
+use ::hyper::http::StatusCode;
+use ::std::time::Duration;
+use ::std::time::SystemTime;
+
+use crate::random_u64;
+use crate::Error;
+use crate::StreamingResponse;
+use crate::TimeoutKind;
+
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Decides when a `Request` is sent again, after it fails.
+///
+/// Each retry waits longer than the last, doubling from the initial backoff up to the maximum.
+/// A random jitter is added to the wait, so many clients failing at once do not all retry together.
+///
+/// By default only idempotent methods are retried (`GET`, `HEAD`, `OPTIONS`, `PUT`, `DELETE`, and `TRACE`).
+/// They are retried when connecting fails, on connect and header timeouts,
+/// and on the statuses `408`, `429`, `500`, `502`, `503`, and `504`.
+///
+/// ```rust
+/// use ::std::time::Duration;
+/// use ::kantan::RetryPolicy;
+/// use ::kantan::ServerConfig;
+///
+/// let config = ServerConfig::builder()
+///     .retry_policy(
+///         RetryPolicy::new(3).backoff(Duration::from_millis(200), Duration::from_secs(5)),
+///     )
+///     .build();
+/// ```
+///
+/// Requests with a body from `Request::reader` or `Request::body_stream` are never retried,
+/// as the body can only be sent once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    is_jittered: bool,
+    methods: Vec<Method>,
+    statuses: Vec<StatusCode>,
+    is_retrying_connect_errors: bool,
+    is_retrying_timeouts: bool,
+    is_using_retry_after: bool,
+}
+
+impl RetryPolicy {
+    /// A policy which never retries, sending each request only once.
+    pub fn none() -> Self {
+        Self::new(1)
+    }
+
+    /// A policy which sends each request up to `max_attempts` times,
+    /// including the first attempt.
+    pub fn new(max_attempts: usize) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            is_jittered: true,
+            methods: vec![
+                Method::GET,
+                Method::HEAD,
+                Method::OPTIONS,
+                Method::PUT,
+                Method::DELETE,
+                Method::TRACE,
+            ],
+            statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            is_retrying_connect_errors: true,
+            is_retrying_timeouts: true,
+            is_using_retry_after: true,
+        }
+    }
+
+    /// Sets how long to wait before the first retry,
+    /// and the most that will be waited before any retry.
+    ///
+    /// **Defaults** to 100 milliseconds, up to 10 seconds.
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// Waits exactly the backoff between retries, without adding any random jitter.
+    pub fn without_jitter(mut self) -> Self {
+        self.is_jittered = false;
+        self
+    }
+
+    /// Sets which methods are retried. Any others are only sent once.
+    pub fn methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Sets which status codes cause the request to be retried.
+    pub fn statuses<I>(mut self, statuses: I) -> Self
+    where
+        I: IntoIterator<Item = StatusCode>,
+    {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Sets if requests are retried when connecting to the server fails.
+    ///
+    /// **Defaults** to true.
+    pub fn retry_connect_errors(mut self, is_retrying: bool) -> Self {
+        self.is_retrying_connect_errors = is_retrying;
+        self
+    }
+
+    /// Sets if requests are retried when connecting, or waiting for the response headers, times out.
+    ///
+    /// The total timeout is never retried, as it covers all of the attempts.
+    ///
+    /// **Defaults** to true.
+    pub fn retry_timeouts(mut self, is_retrying: bool) -> Self {
+        self.is_retrying_timeouts = is_retrying;
+        self
+    }
+
+    /// Ignores any `Retry-After` header sent by the server,
+    /// and always waits using the backoff.
+    pub fn ignore_retry_after(mut self) -> Self {
+        self.is_using_retry_after = false;
+        self
+    }
+
+    /// The most times a request will be sent, including the first attempt.
+    #[must_use]
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Returns how long to wait before sending the request again,
+    /// after the attempt given produced the result given.
+    ///
+    /// `None` is returned when the request should not be retried.
+    ///
+    /// When the server sends a `Retry-After` header, that is waited instead if it is longer.
+    /// If it asks to wait longer than the maximum backoff, then the request is not retried.
+    pub(crate) fn retry_delay(
+        &self,
+        attempt: usize,
+        method: &Method,
+        result: &Result<StreamingResponse, Error>,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.methods.contains(method) {
+            return None;
+        }
+
+        let backoff = self.jittered_backoff(attempt);
+        match result {
+            Ok(response) if self.statuses.contains(&response.status_code()) => {
+                let maybe_retry_after = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .filter(|_| self.is_using_retry_after)
+                    .and_then(|retry_after| parse_retry_after(retry_after, SystemTime::now()));
+
+                match maybe_retry_after {
+                    Some(retry_after) if retry_after > self.max_backoff => None,
+                    Some(retry_after) => Some(retry_after.max(backoff)),
+                    None => Some(backoff),
+                }
+            }
+            Ok(_) => None,
+            Err(Error::Connect { .. }) if self.is_retrying_connect_errors => Some(backoff),
+            Err(Error::Timeout(timeout_error))
+                if self.is_retrying_timeouts && timeout_error.kind() != TimeoutKind::Total =>
+            {
+                Some(backoff)
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// The backoff before retrying, after the attempt given, before any jitter.
+    fn backoff_for_attempt(&self, attempt: usize) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(31) as u32;
+        self.initial_backoff
+            .saturating_mul(2_u32.pow(doublings))
+            .min(self.max_backoff)
+    }
+
+    /// The backoff with jitter, which is between half and the whole of the backoff.
+    fn jittered_backoff(&self, attempt: usize) -> Duration {
+        let backoff = self.backoff_for_attempt(attempt);
+        if !self.is_jittered {
+            return backoff;
+        }
+
+        let half_backoff = backoff / 2;
+        let jitter_nanos = random_u64() % (half_backoff.as_nanos() as u64 + 1);
+        half_backoff + Duration::from_nanos(jitter_nanos)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Reads a `Retry-After` header,
+/// which is either a number of seconds or a HTTP date.
+fn parse_retry_after(retry_after: &HeaderValue, now: SystemTime) -> Option<Duration> {
+    let retry_after = retry_after.to_str().ok()?.trim();
+    if let Ok(seconds) = retry_after.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let retry_at = ::httpdate::parse_http_date(retry_after).ok()?;
+    Some(retry_at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod test_backoff {
+    use super::*;
+
+    #[test]
+    fn it_should_double_up_to_the_maximum() {
+        let retry_policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(100), Duration::from_millis(500));
+
+        assert_eq!(
+            retry_policy.backoff_for_attempt(1),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            retry_policy.backoff_for_attempt(2),
+            Duration::from_millis(200)
+        );
+        assert_eq!(
+            retry_policy.backoff_for_attempt(3),
+            Duration::from_millis(400)
+        );
+        assert_eq!(
+            retry_policy.backoff_for_attempt(4),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            retry_policy.backoff_for_attempt(100),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn it_should_jitter_between_half_and_the_whole_backoff() {
+        let retry_policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(100), Duration::from_millis(500));
+
+        for _ in 0..100 {
+            let backoff = retry_policy.jittered_backoff(2);
+            assert!(backoff >= Duration::from_millis(100));
+            assert!(backoff <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn it_should_not_jitter_when_turned_off() {
+        let retry_policy = RetryPolicy::new(10).without_jitter();
+
+        assert_eq!(retry_policy.jittered_backoff(1), DEFAULT_INITIAL_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod test_parse_retry_after {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_seconds() {
+        let retry_after = HeaderValue::from_static("120");
+
+        assert_eq!(
+            parse_retry_after(&retry_after, SystemTime::now()),
+            Some(Duration::from_secs(120))
+        );
+    }
+
+    #[test]
+    fn it_should_parse_http_dates() {
+        let retry_after = HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT");
+        let now = ::httpdate::parse_http_date("Wed, 21 Oct 2015 07:27:30 GMT").unwrap();
+
+        assert_eq!(
+            parse_retry_after(&retry_after, now),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn it_should_not_wait_for_dates_in_the_past() {
+        let retry_after = HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT");
+
+        assert_eq!(
+            parse_retry_after(&retry_after, SystemTime::now()),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn it_should_not_parse_other_values() {
+        let retry_after = HeaderValue::from_static("soon");
+
+        assert_eq!(parse_retry_after(&retry_after, SystemTime::now()), None);
+    }
+}
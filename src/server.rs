@@ -2,15 +2,30 @@ use ::anyhow::Context;
 use ::anyhow::Result;
 use ::cookie::Cookie;
 use ::cookie::CookieJar;
+use ::hyper::http::header::HeaderName;
+use ::hyper::http::HeaderValue;
 use ::hyper::http::Method;
+use ::std::path::Path;
 use ::std::sync::Arc;
 use ::std::sync::Mutex;
 
+use crate::AuthProvider;
+use crate::Authorization;
+use crate::CookieFileFormat;
+use crate::Error;
+use crate::MemoryCookieStore;
+use crate::Middleware;
 use crate::Request;
 
 mod inner_server;
 pub(crate) use self::inner_server::*;
 
+mod server_config;
+pub use self::server_config::*;
+
+mod server_config_builder;
+pub use self::server_config_builder::*;
+
 ///
 /// The `Server` represents your application, running as a web server,
 /// and you can make web requests to your application.
@@ -33,7 +48,15 @@ impl Server {
     /// This is the same as creating a new `Server` with a configuration,
     /// and passing `ServerConfig::default()`.
     pub fn new(server_address: String) -> Result<Self> {
-        let inner_test_server = InnerServer::new(server_address)?;
+        Self::new_with_config(server_address, ServerConfig::default())
+    }
+
+    /// Creates a `Server` for the address given, using the configuration provided.
+    ///
+    /// All requests made through this `Server` share one connection pool,
+    /// which is set up using the configuration.
+    pub fn new_with_config(server_address: String, config: ServerConfig) -> Result<Self> {
+        let inner_test_server = InnerServer::new(server_address, config)?;
         let inner_mutex = Mutex::new(inner_test_server);
         let inner = Arc::new(inner_mutex);
 
@@ -67,6 +90,139 @@ impl Server {
             .unwrap()
     }
 
+    /// Saves all of the cookies stored to the file given, in the format given.
+    ///
+    /// Cookies which have expired are not saved.
+    /// Session cookies (which have no expiry) are saved,
+    /// so they can be reused by a later run using `Server::load_cookies_from`.
+    ///
+    /// This is only supported when using the default `MemoryCookieStore`.
+    pub fn save_cookies_to<P>(&self, path: P, format: CookieFileFormat) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        self.memory_cookie_store_for_file(path.as_ref())?
+            .save_to(path, format)
+    }
+
+    /// Loads cookies from the file given, in the format given,
+    /// to be included on *all* future requests.
+    ///
+    /// They are stored over the top of the existing cookies.
+    /// Cookies in the file which have expired are discarded.
+    ///
+    /// This is only supported when using the default `MemoryCookieStore`.
+    pub fn load_cookies_from<P>(&mut self, path: P, format: CookieFileFormat) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        self.memory_cookie_store_for_file(path.as_ref())?
+            .load_from(path, format)
+    }
+
+    fn memory_cookie_store_for_file(&self, path: &Path) -> Result<Arc<MemoryCookieStore>, Error> {
+        InnerServer::memory_cookie_store(&self.inner)?.ok_or_else(|| Error::CookieFile {
+            path: path.to_path_buf(),
+            source:
+                "the Server is using a custom CookieStore, save or load cookies through it instead"
+                    .into(),
+        })
+    }
+
+    /// Adds a header to be sent on *all* future requests.
+    ///
+    /// If a header with the same name already exists,
+    /// then both will be sent.
+    ///
+    /// Any header set on a `Request` will replace
+    /// the headers here with the same name.
+    pub fn add_header(&mut self, name: HeaderName, value: HeaderValue) {
+        InnerServer::add_header(&mut self.inner, name, value)
+            .with_context(|| format!("Trying to add_header"))
+            .unwrap()
+    }
+
+    /// Sends the token given as `Authorization: Bearer <token>` on *all* future requests.
+    ///
+    /// This replaces any credentials or `AuthProvider` already set on the server.
+    pub fn bearer_auth<T>(&mut self, token: T)
+    where
+        T: Into<String>,
+    {
+        InnerServer::set_authorization(&mut self.inner, Some(Authorization::bearer(token)))
+            .with_context(|| format!("Trying to set bearer_auth"))
+            .unwrap()
+    }
+
+    /// Sends the username and password using HTTP Basic authentication on *all* future requests.
+    ///
+    /// This replaces any credentials or `AuthProvider` already set on the server.
+    pub fn basic_auth<U, P>(&mut self, username: U, password: P)
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        let authorization = Authorization::basic(username, password);
+        InnerServer::set_authorization(&mut self.inner, Some(authorization))
+            .with_context(|| format!("Trying to set basic_auth"))
+            .unwrap()
+    }
+
+    /// Sends the username and password using HTTP Digest authentication on *all* future requests.
+    ///
+    /// The challenge from the server is stored, so later requests reuse its nonce.
+    /// This replaces any credentials or `AuthProvider` already set on the server.
+    pub fn digest_auth<U, P>(&mut self, username: U, password: P)
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        InnerServer::set_digest_auth(&mut self.inner, username.into(), password.into())
+            .with_context(|| format!("Trying to set digest_auth"))
+            .unwrap()
+    }
+
+    /// Uses the `AuthProvider` given to provide the credentials for *all* future requests,
+    /// such as an `OAuth2Provider`.
+    ///
+    /// This replaces any credentials or `AuthProvider` already set on the server.
+    pub fn auth_provider<P>(&mut self, auth_provider: P)
+    where
+        P: AuthProvider + 'static,
+    {
+        InnerServer::set_auth_provider(&mut self.inner, Some(Arc::new(auth_provider)))
+            .with_context(|| format!("Trying to set auth_provider"))
+            .unwrap()
+    }
+
+    /// Stops sending the credentials set using `Server::bearer_auth`, `Server::basic_auth`,
+    /// `Server::digest_auth`, or `Server::auth_provider`.
+    pub fn clear_auth(&mut self) {
+        InnerServer::set_authorization(&mut self.inner, None)
+            .with_context(|| format!("Trying to clear_auth"))
+            .unwrap()
+    }
+
+    /// Adds a `Middleware` to run around sending *all* future requests.
+    ///
+    /// It runs after any middleware already added.
+    pub fn add_middleware<M>(&mut self, middleware: M)
+    where
+        M: Middleware + 'static,
+    {
+        InnerServer::add_middleware(&mut self.inner, Arc::new(middleware))
+            .with_context(|| format!("Trying to add_middleware"))
+            .unwrap()
+    }
+
+    /// Clears all of the headers stored internally,
+    /// including the default headers from the `ServerConfig`.
+    pub fn clear_headers(&mut self) {
+        InnerServer::clear_headers(&mut self.inner)
+            .with_context(|| format!("Trying to clear_headers"))
+            .unwrap()
+    }
+
     /// Creates a HTTP GET request to the path.
     pub fn get(&self, path: &str) -> Request {
         self.method(Method::GET, path)
@@ -3,89 +3,266 @@ use ::anyhow::Context;
 use ::anyhow::Result;
 use ::cookie::Cookie;
 use ::cookie::CookieJar;
+use ::hyper::client::HttpConnector;
+use ::hyper::http::header::HeaderName;
+use ::hyper::http::HeaderMap;
 use ::hyper::http::HeaderValue;
 use ::hyper::http::Method;
 use ::hyper::http::Uri;
+use ::hyper::Body;
+use ::hyper::Client;
+use ::hyper_tls::HttpsConnector;
 use ::std::sync::Arc;
 use ::std::sync::Mutex;
+use ::std::time::Duration;
 
+use crate::AuthProvider;
+use crate::Authorization;
+use crate::CookieStore;
+use crate::DigestAuth;
+use crate::DigestNonces;
+use crate::Encoding;
+use crate::ExpectedStatus;
+use crate::MemoryCookieStore;
+use crate::Middleware;
+use crate::RedirectPolicy;
 use crate::Request;
 use crate::RequestConfig;
+use crate::RetryPolicy;
+use crate::ServerConfig;
+
+/// The Hyper client used for sending requests.
+///
+/// It holds a pool of connections internally, and is cheap to clone.
+pub(crate) type HttpClient = Client<HttpsConnector<HttpConnector>, Body>;
+
+/// The most clients kept for requests with their own connect timeout.
+/// Past this the least recently used client is dropped, along with its connections.
+const MAX_CONNECT_TIMEOUT_CLIENTS: usize = 4;
 
 /// The `InnerServer` is the real server that runs.
 #[derive(Debug)]
 pub(crate) struct InnerServer {
     server_address: String,
-    cookies: CookieJar,
+    server_uri: Uri,
+    client: HttpClient,
+    connect_timeout: Option<Duration>,
+    /// Clients for requests with their own connect timeout,
+    /// with the most recently used at the end.
+    connect_timeout_clients: Vec<(Option<Duration>, HttpClient)>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: usize,
+    cookie_store: Arc<dyn CookieStore>,
+    memory_cookie_store: Option<Arc<MemoryCookieStore>>,
+    middleware: Vec<Arc<dyn Middleware>>,
     save_cookies: bool,
     default_content_type: Option<String>,
+    default_headers: HeaderMap,
+    default_query_params: Vec<(String, String)>,
+    authorization: Option<Authorization>,
+    auth_provider: Option<Arc<dyn AuthProvider>>,
+    digest_nonces: Arc<DigestNonces>,
+    user_agent: Option<String>,
+    expected_status: Option<ExpectedStatus>,
+    redirect_policy: RedirectPolicy,
+    retry_policy: RetryPolicy,
+    headers_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+    max_decompressed_size: usize,
+    compression: Option<Encoding>,
+    compression_threshold: usize,
 }
 
 impl InnerServer {
     /// Creates a `Server` running your app on the address given.
-    pub(crate) fn new(server_address: String) -> Result<Self> {
+    pub(crate) fn new(server_address: String, config: ServerConfig) -> Result<Self> {
+        let server_uri: Uri = server_address
+            .parse()
+            .with_context(|| format!("Parsing server address '{}'", server_address))?;
+
+        let (cookie_store, memory_cookie_store) = match config.cookie_store {
+            Some(cookie_store) => (cookie_store, None),
+            None => {
+                let memory_cookie_store = Arc::new(MemoryCookieStore::new());
+                let cookie_store: Arc<dyn CookieStore> = memory_cookie_store.clone();
+                (cookie_store, Some(memory_cookie_store))
+            }
+        };
+
         let test_server = Self {
             server_address,
-            cookies: CookieJar::new(),
-            save_cookies: false,
-            default_content_type: None,
+            server_uri,
+            client: build_client(
+                config.connect_timeout,
+                config.pool_idle_timeout,
+                config.pool_max_idle_per_host,
+            ),
+            connect_timeout: config.connect_timeout,
+            connect_timeout_clients: Vec::new(),
+            pool_idle_timeout: config.pool_idle_timeout,
+            pool_max_idle_per_host: config.pool_max_idle_per_host,
+            cookie_store,
+            memory_cookie_store,
+            middleware: config.middleware,
+            save_cookies: config.save_cookies,
+            default_content_type: config.default_content_type,
+            default_headers: config.default_headers,
+            default_query_params: config.default_query_params,
+            authorization: config.authorization,
+            auth_provider: config.auth_provider,
+            digest_nonces: Arc::new(DigestNonces::default()),
+            user_agent: config.user_agent,
+            expected_status: config.expected_status,
+            redirect_policy: config.redirect_policy,
+            retry_policy: config.retry_policy,
+            headers_timeout: config.headers_timeout,
+            total_timeout: config.total_timeout,
+            max_decompressed_size: config.max_decompressed_size,
+            compression: config.compression,
+            compression_threshold: config.compression_threshold,
         };
 
         Ok(test_server)
     }
 
-    pub(crate) fn cookies<'a>(&'a self) -> &'a CookieJar {
-        &self.cookies
-    }
-
-    /// Adds the given cookies.
+    /// Returns a client which connects using the timeout given.
     ///
-    /// They will be stored over the top of the existing cookies.
-    pub(crate) fn add_cookies_by_header<'a, I>(
-        this: &mut Arc<Mutex<Self>>,
-        cookie_headers: I,
-    ) -> Result<()>
-    where
-        I: Iterator<Item = &'a HeaderValue>,
-    {
-        InnerServer::with_this_mut(this, "add_cookies_by_header", |this| {
-            for cookie_header in cookie_headers {
-                let cookie_header_str = cookie_header
-                    .to_str()
-                    .context(&"Reading cookie header for storing in the `Server`")
-                    .unwrap();
-
-                let cookie: Cookie<'static> = Cookie::parse(cookie_header_str)?.into_owned();
-                this.cookies.add(cookie);
+    /// When it matches the `Server`'s connect timeout, the shared pooled client is returned.
+    /// Otherwise a pooled client is built for that timeout, and kept for later requests using it.
+    /// Only the few most recently used of these are kept.
+    pub(crate) fn client_for_connect_timeout(
+        this: &Arc<Mutex<Self>>,
+        connect_timeout: Option<Duration>,
+    ) -> Result<HttpClient> {
+        InnerServer::with_this(this, "client_for_connect_timeout", |this| {
+            if this.connect_timeout == connect_timeout {
+                return this.client.clone();
             }
 
-            Ok(()) as Result<()>
-        })?
+            let maybe_index = this
+                .connect_timeout_clients
+                .iter()
+                .position(|(client_timeout, _)| *client_timeout == connect_timeout);
+            let client = match maybe_index {
+                Some(index) => this.connect_timeout_clients.remove(index).1,
+                None => build_client(
+                    connect_timeout,
+                    this.pool_idle_timeout,
+                    this.pool_max_idle_per_host,
+                ),
+            };
+
+            if this.connect_timeout_clients.len() >= MAX_CONNECT_TIMEOUT_CLIENTS {
+                this.connect_timeout_clients.remove(0);
+            }
+            this.connect_timeout_clients
+                .push((connect_timeout, client.clone()));
+
+            client
+        })
     }
 
-    /// Adds the given cookies.
+    /// Returns the default `MemoryCookieStore` used by this server.
     ///
-    /// They will be stored over the top of the existing cookies.
+    /// `None` is returned when a different `CookieStore` was given in the config.
+    pub(crate) fn memory_cookie_store(
+        this: &Arc<Mutex<Self>>,
+    ) -> Result<Option<Arc<MemoryCookieStore>>> {
+        InnerServer::with_this(this, "memory_cookie_store", |this| {
+            this.memory_cookie_store.clone()
+        })
+    }
+
+    /// Removes all of the cookies stored.
     pub(crate) fn clear_cookies(this: &mut Arc<Mutex<Self>>) -> Result<()> {
         InnerServer::with_this_mut(this, "clear_cookies", |this| {
-            this.cookies = CookieJar::new();
+            this.cookie_store.clear();
         })
     }
 
     /// Adds the given cookies.
     ///
     /// They will be stored over the top of the existing cookies.
+    /// Cookies without a domain are sent to the server address only.
     pub(crate) fn add_cookies(this: &mut Arc<Mutex<Self>>, cookies: CookieJar) -> Result<()> {
         InnerServer::with_this_mut(this, "add_cookies", |this| {
             for cookie in cookies.iter() {
-                this.cookies.add(cookie.to_owned());
+                this.store_cookie(cookie)?;
             }
-        })
+
+            Ok(())
+        })?
     }
 
     pub(crate) fn add_cookie(this: &mut Arc<Mutex<Self>>, cookie: Cookie) -> Result<()> {
-        InnerServer::with_this_mut(this, "add_cookie", |this| {
-            this.cookies.add(cookie.into_owned());
+        InnerServer::with_this_mut(this, "add_cookie", |this| this.store_cookie(&cookie))?
+    }
+
+    /// Stores the cookie, as though it was set by a response from the server address.
+    fn store_cookie(&self, cookie: &Cookie) -> Result<()> {
+        let set_cookie_header = HeaderValue::from_str(&cookie.to_string())
+            .with_context(|| format!("Invalid cookie '{}'", cookie.name()))?;
+
+        self.cookie_store
+            .store_set_cookies(&mut std::iter::once(&set_cookie_header), &self.server_uri);
+
+        Ok(())
+    }
+
+    pub(crate) fn add_header(
+        this: &mut Arc<Mutex<Self>>,
+        name: HeaderName,
+        value: HeaderValue,
+    ) -> Result<()> {
+        InnerServer::with_this_mut(this, "add_header", |this| {
+            this.default_headers.append(name, value);
+        })
+    }
+
+    pub(crate) fn add_middleware(
+        this: &mut Arc<Mutex<Self>>,
+        middleware: Arc<dyn Middleware>,
+    ) -> Result<()> {
+        InnerServer::with_this_mut(this, "add_middleware", |this| {
+            this.middleware.push(middleware);
+        })
+    }
+
+    pub(crate) fn set_authorization(
+        this: &mut Arc<Mutex<Self>>,
+        authorization: Option<Authorization>,
+    ) -> Result<()> {
+        InnerServer::with_this_mut(this, "set_authorization", |this| {
+            this.authorization = authorization;
+            this.auth_provider = None;
+        })
+    }
+
+    pub(crate) fn set_auth_provider(
+        this: &mut Arc<Mutex<Self>>,
+        auth_provider: Option<Arc<dyn AuthProvider>>,
+    ) -> Result<()> {
+        InnerServer::with_this_mut(this, "set_auth_provider", |this| {
+            this.authorization = None;
+            this.auth_provider = auth_provider;
+        })
+    }
+
+    pub(crate) fn set_digest_auth(
+        this: &mut Arc<Mutex<Self>>,
+        username: String,
+        password: String,
+    ) -> Result<()> {
+        InnerServer::with_this_mut(this, "set_digest_auth", |this| {
+            let digest_auth = DigestAuth::new(username, password, this.digest_nonces.clone());
+            this.authorization = None;
+            this.auth_provider = Some(Arc::new(digest_auth));
+        })
+    }
+
+    pub(crate) fn clear_headers(this: &mut Arc<Mutex<Self>>) -> Result<()> {
+        InnerServer::with_this_mut(this, "clear_headers", |this| {
+            this.default_headers.clear();
         })
     }
 
@@ -100,7 +277,24 @@ impl InnerServer {
                 method,
                 request_path,
                 save_cookies: this.save_cookies,
+                cookie_store: this.cookie_store.clone(),
+                middleware: this.middleware.clone(),
                 content_type: this.default_content_type.clone(),
+                headers: this.default_headers.clone(),
+                query_params: this.default_query_params.clone(),
+                authorization: this.authorization.clone(),
+                auth_provider: this.auth_provider.clone(),
+                digest_nonces: this.digest_nonces.clone(),
+                user_agent: this.user_agent.clone(),
+                expected_status: this.expected_status,
+                redirect_policy: this.redirect_policy,
+                retry_policy: this.retry_policy.clone(),
+                connect_timeout: this.connect_timeout,
+                headers_timeout: this.headers_timeout,
+                total_timeout: this.total_timeout,
+                max_decompressed_size: this.max_decompressed_size,
+                compression: this.compression,
+                compression_threshold: this.compression_threshold,
             };
 
             Ok(config)
@@ -144,12 +338,28 @@ impl InnerServer {
     }
 }
 
+fn build_client(
+    connect_timeout: Option<Duration>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: usize,
+) -> HttpClient {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(connect_timeout);
+    let https = HttpsConnector::new_with_connector(http);
+
+    Client::builder()
+        .pool_idle_timeout(pool_idle_timeout)
+        .pool_max_idle_per_host(pool_max_idle_per_host)
+        .build(https)
+}
+
 fn build_request_path(root: &str, sub_path: &str) -> Result<Uri> {
     if sub_path.is_empty() {
         return Ok(root.try_into()?);
     }
 
-    if sub_path.starts_with("/") {
+    if sub_path.starts_with('/') {
         let full_path = format!("{}{}", root, sub_path).try_into()?;
         return Ok(full_path);
     }
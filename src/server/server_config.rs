@@ -0,0 +1,208 @@
+use ::hyper::http::HeaderMap;
+use ::std::sync::Arc;
+use ::std::time::Duration;
+
+use crate::AuthProvider;
+use crate::Authorization;
+use crate::CookieStore;
+use crate::Encoding;
+use crate::ExpectedStatus;
+use crate::Middleware;
+use crate::RedirectPolicy;
+use crate::RetryPolicy;
+use crate::ServerConfigBuilder;
+
+/// The default for how long an idle connection is kept open in the pool.
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// The default for the smallest body which is compressed, in bytes.
+const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// The default for the largest size a response body is decompressed to.
+const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// This is for customising the `Server` on construction.
+///
+/// The settings here are used as the defaults for every `Request`
+/// made by the `Server`, and each `Request` can override them.
+///
+/// It implements `Default` to ease building configurations.
+///
+/// ```rust
+/// use ::std::time::Duration;
+/// use ::kantan::ServerConfig;
+///
+/// let config = ServerConfig {
+///     pool_idle_timeout: Some(Duration::from_secs(30)),
+///     ..ServerConfig::default()
+/// };
+/// ```
+///
+/// Alternatively use `ServerConfig::builder()`.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Set for the server to save cookies that are returned,
+    /// for use in future requests.
+    ///
+    /// This is useful for automatically saving session cookies (and similar)
+    /// like a browser would do.
+    ///
+    /// **Defaults** to false (being turned off).
+    pub save_cookies: bool,
+
+    /// Where cookies are stored, and loaded from when sending requests.
+    ///
+    /// This allows a store to be shared across servers,
+    /// or for cookies to be kept somewhere other than in memory.
+    ///
+    /// **Defaults** to `None` (each server uses its own `MemoryCookieStore`).
+    pub cookie_store: Option<Arc<dyn CookieStore>>,
+
+    /// Middleware run around sending every request, in order.
+    ///
+    /// **Defaults** to no middleware.
+    pub middleware: Vec<Arc<dyn Middleware>>,
+
+    /// Sets the default content type for all requests created by the server.
+    ///
+    /// This overrides the content type a `Request` would pick
+    /// when setting a body, such as `application/json` from `Request::json`.
+    ///
+    /// **Defaults** to `None` (no default).
+    pub default_content_type: Option<String>,
+
+    /// Headers to be sent on every request created by the server.
+    ///
+    /// **Defaults** to no headers.
+    pub default_headers: HeaderMap,
+
+    /// Query parameters to be sent on every request created by the server.
+    ///
+    /// They are added after any query already on the request path.
+    ///
+    /// **Defaults** to no query parameters.
+    pub default_query_params: Vec<(String, String)>,
+
+    /// Credentials sent in the `Authorization` header of every request.
+    ///
+    /// **Defaults** to `None` (no credentials are sent).
+    pub authorization: Option<Authorization>,
+
+    /// Provides the credentials for every request, such as an `OAuth2Provider`.
+    ///
+    /// This replaces the `Authorization` header from `ServerConfig::authorization`.
+    ///
+    /// **Defaults** to `None` (no provider is used).
+    pub auth_provider: Option<Arc<dyn AuthProvider>>,
+
+    /// The `User-Agent` header sent with every request.
+    ///
+    /// **Defaults** to `None` (no user agent is sent).
+    pub user_agent: Option<String>,
+
+    /// The status code every request expects to get back.
+    /// When it doesn't match, awaiting the request will panic.
+    ///
+    /// **Defaults** to `None` (any status code is accepted).
+    pub expected_status: Option<ExpectedStatus>,
+
+    /// Which redirects are followed when sending requests.
+    ///
+    /// **Defaults** to `RedirectPolicy::None` (redirects are never followed).
+    pub redirect_policy: RedirectPolicy,
+
+    /// When failed requests are sent again, and how long to wait between attempts.
+    ///
+    /// **Defaults** to `RetryPolicy::none()` (requests are never retried).
+    pub retry_policy: RetryPolicy,
+
+    /// How long to wait for a connection to the server to be opened.
+    ///
+    /// **Defaults** to `None` (no timeout).
+    pub connect_timeout: Option<Duration>,
+
+    /// How long to wait for the response headers to arrive,
+    /// after sending the request. This includes connecting.
+    ///
+    /// **Defaults** to `None` (no timeout).
+    pub headers_timeout: Option<Duration>,
+
+    /// How long to wait for the whole request to finish,
+    /// including reading all of the response body, and any retries.
+    ///
+    /// **Defaults** to `None` (no timeout).
+    pub total_timeout: Option<Duration>,
+
+    /// How long an idle connection is kept open in the connection pool,
+    /// for reuse by later requests.
+    ///
+    /// `None` will keep idle connections open indefinitely.
+    ///
+    /// **Defaults** to 90 seconds.
+    pub pool_idle_timeout: Option<Duration>,
+
+    /// The maximum number of idle connections kept open per host.
+    ///
+    /// Setting this to `0` turns off connection reuse,
+    /// and every request will open a fresh connection.
+    ///
+    /// **Defaults** to no limit.
+    pub pool_max_idle_per_host: usize,
+
+    /// The largest size a compressed response body is allowed to decompress to.
+    ///
+    /// Responses which decompress to more than this fail with an `Error::Decompress`,
+    /// guarding against small responses which expand to use huge amounts of memory.
+    ///
+    /// **Defaults** to 64 megabytes.
+    pub max_decompressed_size: usize,
+
+    /// Compresses the body of every request using this encoding,
+    /// and sets the `Content-Encoding` header to match.
+    ///
+    /// This applies to bodies set using `Request::json`, `Request::text`,
+    /// `Request::bytes`, and `Request::form`. Files, streams, and multipart forms are sent as they are.
+    ///
+    /// **Defaults** to `None` (bodies are not compressed).
+    pub compression: Option<Encoding>,
+
+    /// Bodies smaller than this many bytes are sent uncompressed,
+    /// as compressing them would save little.
+    ///
+    /// **Defaults** to 1024 bytes.
+    pub compression_threshold: usize,
+}
+
+impl ServerConfig {
+    /// Creates a builder, for setting up a `ServerConfig` one option at a time.
+    pub fn builder() -> ServerConfigBuilder {
+        ServerConfigBuilder::default()
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            save_cookies: false,
+            cookie_store: None,
+            middleware: vec![],
+            default_content_type: None,
+            default_headers: HeaderMap::new(),
+            default_query_params: vec![],
+            authorization: None,
+            auth_provider: None,
+            user_agent: None,
+            expected_status: None,
+            redirect_policy: RedirectPolicy::None,
+            retry_policy: RetryPolicy::none(),
+            connect_timeout: None,
+            headers_timeout: None,
+            total_timeout: None,
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            pool_max_idle_per_host: usize::MAX,
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+            compression: None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use ::hyper::http::header::HeaderName;
+use ::hyper::http::HeaderValue;
+use ::hyper::http::StatusCode;
+use ::std::fmt::Display;
+use ::std::sync::Arc;
+use ::std::time::Duration;
+
+use crate::AuthProvider;
+use crate::Authorization;
+use crate::CookieStore;
+use crate::Encoding;
+use crate::ExpectedStatus;
+use crate::Middleware;
+use crate::RedirectPolicy;
+use crate::RetryPolicy;
+use crate::ServerConfig;
+
+/// A builder for creating a `ServerConfig`.
+///
+/// This is created by calling `ServerConfig::builder()`.
+///
+/// ```rust
+/// use ::kantan::ServerConfig;
+///
+/// let config = ServerConfig::builder()
+///     .save_cookies()
+///     .default_content_type("application/json")
+///     .user_agent("my-app/1.0")
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct ServerConfigBuilder {
+    config: ServerConfig,
+}
+
+impl ServerConfigBuilder {
+    /// Turns on saving cookies returned from responses,
+    /// for use by future requests.
+    pub fn save_cookies(mut self) -> Self {
+        self.config.save_cookies = true;
+        self
+    }
+
+    /// Turns off saving cookies returned from responses.
+    ///
+    /// This is the default behaviour.
+    pub fn do_not_save_cookies(mut self) -> Self {
+        self.config.save_cookies = false;
+        self
+    }
+
+    /// Sets the store used for cookies, in place of the default `MemoryCookieStore`.
+    pub fn cookie_store<S>(mut self, cookie_store: Arc<S>) -> Self
+    where
+        S: CookieStore + 'static,
+    {
+        self.config.cookie_store = Some(cookie_store);
+        self
+    }
+
+    /// Sets the content type used by every request by default.
+    pub fn default_content_type(mut self, content_type: &str) -> Self {
+        self.config.default_content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// Adds a `Middleware` to run around sending every request.
+    ///
+    /// Middleware run in the order they are added.
+    pub fn add_middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware + 'static,
+    {
+        self.config.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Sends the token given as `Authorization: Bearer <token>` on every request.
+    pub fn bearer_auth<T>(mut self, token: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.config.authorization = Some(Authorization::bearer(token));
+        self
+    }
+
+    /// Sends the username and password using HTTP Basic authentication on every request.
+    pub fn basic_auth<U, P>(mut self, username: U, password: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        self.config.authorization = Some(Authorization::basic(username, password));
+        self
+    }
+
+    /// Uses the `AuthProvider` given to provide the credentials for every request,
+    /// such as an `OAuth2Provider`.
+    pub fn auth_provider<P>(mut self, auth_provider: P) -> Self
+    where
+        P: AuthProvider + 'static,
+    {
+        self.config.auth_provider = Some(Arc::new(auth_provider));
+        self
+    }
+
+    /// Adds a header to be sent on every request.
+    ///
+    /// Multiple headers with the same name will all be sent.
+    pub fn add_default_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.config.default_headers.append(name, value);
+        self
+    }
+
+    /// Adds a query parameter to be sent on every request.
+    pub fn add_default_query_param<V>(mut self, key: &str, value: V) -> Self
+    where
+        V: Display,
+    {
+        self.config
+            .default_query_params
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the `User-Agent` header sent on every request.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.config.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Every request will expect a successful status code (2xx) by default,
+    /// and panic if they don't get one.
+    pub fn expect_success(mut self) -> Self {
+        self.config.expected_status = Some(ExpectedStatus::Success);
+        self
+    }
+
+    /// Every request will expect a status code outside of 2xx by default,
+    /// and panic if they don't get one.
+    pub fn expect_failure(mut self) -> Self {
+        self.config.expected_status = Some(ExpectedStatus::Failure);
+        self
+    }
+
+    /// Every request will expect the status code given by default,
+    /// and panic if they don't get it.
+    pub fn expect_status(mut self, status_code: StatusCode) -> Self {
+        self.config.expected_status = Some(ExpectedStatus::Code(status_code));
+        self
+    }
+
+    /// Sets which redirects are followed when sending requests.
+    pub fn redirect_policy(mut self, redirect_policy: RedirectPolicy) -> Self {
+        self.config.redirect_policy = redirect_policy;
+        self
+    }
+
+    /// Sets when failed requests are sent again.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.config.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets how long to wait for a connection to the server to be opened.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait for the response headers to arrive.
+    pub fn headers_timeout(mut self, timeout: Duration) -> Self {
+        self.config.headers_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait for the whole request to finish,
+    /// including reading the response body.
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.config.total_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long idle connections are kept open for reuse.
+    ///
+    /// `None` keeps them open indefinitely.
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of idle connections kept open per host.
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.config.pool_max_idle_per_host = max_idle;
+        self
+    }
+
+    /// Sets the largest size a compressed response body is allowed to decompress to.
+    pub fn max_decompressed_size(mut self, max_size: usize) -> Self {
+        self.config.max_decompressed_size = max_size;
+        self
+    }
+
+    /// Compresses the body of every request using the encoding given.
+    pub fn compression(mut self, encoding: Encoding) -> Self {
+        self.config.compression = Some(encoding);
+        self
+    }
+
+    /// Sets the smallest body which is compressed, in bytes.
+    pub fn compression_threshold(mut self, threshold: usize) -> Self {
+        self.config.compression_threshold = threshold;
+        self
+    }
+
+    /// Finishes building, returning the `ServerConfig`.
+    pub fn build(self) -> ServerConfig {
+        self.config
+    }
+}
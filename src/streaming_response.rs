@@ -0,0 +1,258 @@
+use ::futures_util::Stream;
+use ::hyper::body::to_bytes;
+use ::hyper::body::Body;
+use ::hyper::body::Bytes;
+use ::hyper::body::HttpBody;
+use ::hyper::http::header::AsHeaderName;
+use ::hyper::http::header::CONTENT_ENCODING;
+use ::hyper::http::response::Parts;
+use ::hyper::http::HeaderMap;
+use ::hyper::http::HeaderValue;
+use ::hyper::http::Method;
+use ::hyper::http::StatusCode;
+use ::hyper::Uri;
+use ::std::pin::Pin;
+use ::std::task::Context;
+use ::std::task::Poll;
+
+use crate::decode_body;
+use crate::Error;
+use crate::Redirect;
+use crate::Response;
+
+/// The most bytes read from a body which is being thrown away.
+/// Past this the connection is given up, instead of reading the rest.
+const MAX_DISCARD_SIZE: u64 = 64 * 1024;
+
+/// A response where the body has not yet been read.
+/// It is returned from `Request::send_streaming`.
+///
+/// The status code and headers are available straight away,
+/// and the body is read in chunks using it as a `Stream`.
+/// This allows large downloads, or endless streams, to be handled as they arrive.
+///
+/// Call `StreamingResponse::collect` to read the rest of the body into a `Response`.
+///
+/// The chunks streamed are the body exactly as it was sent,
+/// and are not decompressed. Only `StreamingResponse::collect` will decompress the body.
+#[derive(Debug)]
+pub struct StreamingResponse {
+    request_method: Method,
+    request_uri: Uri,
+    final_uri: Uri,
+    redirect_history: Vec<Redirect>,
+    parts: Parts,
+    body: Body,
+    max_decompressed_size: usize,
+}
+
+impl StreamingResponse {
+    pub(crate) fn new(
+        request_method: Method,
+        request_uri: Uri,
+        final_uri: Uri,
+        redirect_history: Vec<Redirect>,
+        parts: Parts,
+        body: Body,
+        max_decompressed_size: usize,
+    ) -> Self {
+        Self {
+            request_method,
+            request_uri,
+            final_uri,
+            redirect_history,
+            parts,
+            body,
+            max_decompressed_size,
+        }
+    }
+
+    /// The HTTP method that was used to produce this response.
+    #[must_use]
+    pub fn request_method(&self) -> &Method {
+        &self.request_method
+    }
+
+    /// The URL that was requested.
+    ///
+    /// When redirects are followed, this is the URL before any redirects.
+    #[must_use]
+    pub fn request_uri(&self) -> &Uri {
+        &self.request_uri
+    }
+
+    /// The URL that produced this response,
+    /// after following any redirects.
+    #[must_use]
+    pub fn final_uri(&self) -> &Uri {
+        &self.final_uri
+    }
+
+    /// The redirects that were followed to produce this response, in order.
+    #[must_use]
+    pub fn redirect_history(&self) -> &[Redirect] {
+        &self.redirect_history
+    }
+
+    /// The status_code of the response.
+    #[must_use]
+    pub fn status_code(&self) -> StatusCode {
+        self.parts.status
+    }
+
+    /// Returns the headers returned from the response.
+    #[must_use]
+    pub fn headers(&self) -> &HeaderMap<HeaderValue> {
+        &self.parts.headers
+    }
+
+    /// Finds a header with the given name.
+    /// If there are multiple headers with the same name,
+    /// then only the first will be returned.
+    ///
+    /// `None` is returned when no header was found.
+    #[must_use]
+    pub fn maybe_header<N>(&self, header_name: N) -> Option<HeaderValue>
+    where
+        N: AsHeaderName,
+    {
+        self.parts.headers.get(header_name).map(|h| h.to_owned())
+    }
+
+    /// Reads the rest of the body, and returns it as a `Response`.
+    ///
+    /// Any chunks already taken from the stream are not included.
+    ///
+    /// When the response has a `Content-Encoding` which is turned on,
+    /// then the body is decompressed. The original is kept, for `Response::raw_bytes`.
+    pub async fn collect(self) -> Result<Response, Error> {
+        let response_bytes = to_bytes(self.body).await.map_err(|err| Error::BodyRead {
+            method: self.request_method.clone(),
+            request_path: self.final_uri.clone(),
+            source: err,
+        })?;
+
+        let maybe_decompressed_bytes = match self.parts.headers.get(CONTENT_ENCODING) {
+            None => None,
+            Some(content_encoding) => decode_body(
+                &response_bytes,
+                content_encoding,
+                self.max_decompressed_size,
+            )
+            .await
+            .map_err(|err| Error::Decompress {
+                request_path: self.final_uri.clone(),
+                source: err.into(),
+            })?,
+        };
+
+        let mut response = Response::new(
+            self.request_method,
+            self.request_uri,
+            self.parts,
+            response_bytes,
+        );
+        response.set_redirect_history(self.final_uri, self.redirect_history);
+        if let Some(decompressed_bytes) = maybe_decompressed_bytes {
+            response.set_decompressed_body(decompressed_bytes);
+        }
+
+        Ok(response)
+    }
+
+    /// Reads the rest of the body and throws it away,
+    /// so the connection can be reused.
+    ///
+    /// See `discard_body` for how much of the body is read.
+    pub(crate) async fn discard(self) -> Result<(), Error> {
+        discard_body(self.body)
+            .await
+            .map_err(|err| Error::BodyRead {
+                method: self.request_method,
+                request_path: self.final_uri,
+                source: err,
+            })
+    }
+}
+
+/// Reads a body and throws it away, so the connection can be reused.
+///
+/// Only small bodies are read to the end. When the body is larger than
+/// `MAX_DISCARD_SIZE`, it is dropped part way, which closes the connection.
+pub(crate) async fn discard_body(mut body: Body) -> Result<(), ::hyper::Error> {
+    if HttpBody::size_hint(&body).lower() > MAX_DISCARD_SIZE {
+        return Ok(());
+    }
+
+    let mut discarded_size = 0;
+    while let Some(chunk) = body.data().await {
+        discarded_size += chunk?.len() as u64;
+        if discarded_size > MAX_DISCARD_SIZE {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+impl Stream for StreamingResponse {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        Pin::new(&mut this.body).poll_next(cx).map(|maybe_chunk| {
+            maybe_chunk.map(|chunk| {
+                chunk.map_err(|err| Error::BodyRead {
+                    method: this.request_method.clone(),
+                    request_path: this.final_uri.clone(),
+                    source: err,
+                })
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        Stream::size_hint(&self.body)
+    }
+}
+
+#[cfg(test)]
+mod test_discard_body {
+    use super::*;
+
+    use ::futures_util::stream::repeat_with;
+    use ::futures_util::StreamExt;
+    use ::std::convert::Infallible;
+    use ::std::sync::atomic::AtomicU64;
+    use ::std::sync::atomic::Ordering;
+    use ::std::sync::Arc;
+
+    #[tokio::test]
+    async fn it_should_read_small_bodies_to_the_end() {
+        let read_size = Arc::new(AtomicU64::new(0));
+        let stream_read_size = read_size.clone();
+        let chunks = repeat_with(move || {
+            stream_read_size.fetch_add(1024, Ordering::SeqCst);
+            Ok::<_, Infallible>(Bytes::from(vec![0; 1024]))
+        })
+        .take(4);
+
+        discard_body(Body::wrap_stream(chunks)).await.unwrap();
+
+        assert_eq!(read_size.load(Ordering::SeqCst), 4 * 1024);
+    }
+
+    #[tokio::test]
+    async fn it_should_stop_reading_endless_bodies() {
+        let read_size = Arc::new(AtomicU64::new(0));
+        let stream_read_size = read_size.clone();
+        let chunks = repeat_with(move || {
+            stream_read_size.fetch_add(1024, Ordering::SeqCst);
+            Ok::<_, Infallible>(Bytes::from(vec![0; 1024]))
+        });
+
+        discard_body(Body::wrap_stream(chunks)).await.unwrap();
+
+        assert!(read_size.load(Ordering::SeqCst) <= MAX_DISCARD_SIZE + 1024);
+    }
+}
@@ -0,0 +1,7 @@
+use ::axum::Router;
+use ::axum_test::TestServer;
+
+/// Runs the router given on a local port, for tests to send requests to.
+pub(crate) fn new_test_server(router: Router) -> TestServer {
+    TestServer::new(router.into_make_service()).expect("Should create test server")
+}
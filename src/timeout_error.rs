@@ -0,0 +1,116 @@
+use ::hyper::http::Method;
+use ::hyper::Uri;
+use ::std::error::Error as StdError;
+use ::std::fmt::Display;
+use ::std::fmt::Formatter;
+use ::std::fmt::Result as FmtResult;
+use ::std::io::Error as IoError;
+use ::std::io::ErrorKind as IoErrorKind;
+use ::std::time::Duration;
+
+/// Which part of sending a `Request` ran out of time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// Opening the connection to the server took too long.
+    Connect,
+
+    /// The server took too long to send back the response headers.
+    Headers,
+
+    /// The whole request, including reading the response body, took too long.
+    Total,
+}
+
+impl Display for TimeoutKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Connect => write!(f, "connecting"),
+            Self::Headers => write!(f, "waiting for response headers"),
+            Self::Total => write!(f, "waiting for the response"),
+        }
+    }
+}
+
+/// The error returned when a `Request` runs out of time.
+///
+/// The timeouts used are set on the `ServerConfig`,
+/// and can be overridden on each `Request`.
+#[derive(Debug, Clone)]
+pub struct TimeoutError {
+    kind: TimeoutKind,
+    duration: Duration,
+    method: Method,
+    request_path: Uri,
+}
+
+impl TimeoutError {
+    pub(crate) fn new(
+        kind: TimeoutKind,
+        duration: Duration,
+        method: Method,
+        request_path: Uri,
+    ) -> Self {
+        Self {
+            kind,
+            duration,
+            method,
+            request_path,
+        }
+    }
+
+    /// Which part of the request ran out of time.
+    #[must_use]
+    pub fn kind(&self) -> TimeoutKind {
+        self.kind
+    }
+
+    /// The timeout which was exceeded.
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The method of the request which timed out.
+    #[must_use]
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The path of the request which timed out.
+    #[must_use]
+    pub fn request_path(&self) -> &Uri {
+        &self.request_path
+    }
+}
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Request timed out {} after {:?}, for {} {}",
+            self.kind, self.duration, self.method, self.request_path
+        )
+    }
+}
+
+impl StdError for TimeoutError {}
+
+/// Returns true if the error was caused by a connection timing out.
+pub(crate) fn is_connect_timeout(error: &hyper::Error) -> bool {
+    if !error.is_connect() {
+        return false;
+    }
+
+    let mut maybe_source = error.source();
+    while let Some(source) = maybe_source {
+        if let Some(io_error) = source.downcast_ref::<IoError>() {
+            if io_error.kind() == IoErrorKind::TimedOut {
+                return true;
+            }
+        }
+
+        maybe_source = source.source();
+    }
+
+    false
+}